@@ -0,0 +1,135 @@
+//! Suggestions of place names as they're typed, from a sorted table of folded names so that each lookup only
+//! looks at the names which start with what has been typed
+
+use crate::{fold_name, population_value, CityId, CountryId, GeoDatabase, Place, RegionId, SubregionId};
+
+/// A place suggested for a prefix
+#[derive(Clone, Debug, PartialEq)]
+pub struct Suggestion {
+    pub place: Place,
+    pub name: String,
+    /// The city's population, or the total population of the cities in a sub-region, region or country
+    pub population: f64
+}
+
+/// An index of the names of the cities, sub-regions, regions and countries of a database, built by
+/// `GeoDatabase::autocomplete_index`
+///
+/// Each name is indexed both whole and from the start of each later word, so "paul" suggests "Saint Paul".
+/// Names are compared ignoring case, diacritics and the punctuation between words, so "saint e" suggests
+/// "Saint-Étienne".
+pub struct AutocompleteIndex {
+    places: Vec<Suggestion>,
+    /// Folded names or the folded words which start them, each with the index of the place and whether it's
+    /// the whole name, sorted by key
+    keys: Vec<(String, usize, bool)>
+}
+
+impl AutocompleteIndex {
+    pub fn new(database: &GeoDatabase) -> AutocompleteIndex {
+        let mut places = vec![];
+        for (city_ix, city) in database.cities.iter().enumerate() {
+            places.push(Suggestion { place: Place::City(CityId(city_ix)), name: city.name.clone(), population: population_value(city.population) });
+        }
+        let tables = [
+            (&database.subregions, database.total_populations(database.subregions.len(), |city| city.subregion_ix.0)),
+            (&database.regions, database.total_populations(database.regions.len(), |city| city.region_ix.0))
+        ];
+        for (table_ix, (names, populations)) in tables.into_iter().enumerate() {
+            for (ix, name) in names.iter().enumerate() {
+                let place = if table_ix == 0 { Place::Subregion(SubregionId(ix)) } else { Place::Region(RegionId(ix)) };
+                places.push(Suggestion { place, name: name.clone(), population: populations[ix] });
+            }
+        }
+        let country_populations = database.total_populations(database.countries.len(), |city| city.country_ix.0);
+        for (country_ix, country) in database.countries.iter().enumerate() {
+            places.push(Suggestion { place: Place::Country(CountryId(country_ix)), name: country[2..].to_owned(), population: country_populations[country_ix] });
+        }
+
+        let mut keys = vec![];
+        for (place_ix, place) in places.iter().enumerate() {
+            let folded = index_key(&place.name);
+            let word_starts = folded.match_indices(' ').map(|(i, _)| i + 1).filter(|start| *start < folded.len());
+            for start in word_starts {
+                keys.push((folded[start..].to_owned(), place_ix, false));
+            }
+            keys.push((folded, place_ix, true));
+        }
+        keys.sort_unstable();
+        return AutocompleteIndex { places, keys };
+    }
+
+    /// Up to `limit` places with a name, or a word of their name, starting with `prefix`
+    ///
+    /// Places whose whole name starts with the prefix come first, otherwise the most populous come first.
+    pub fn autocomplete(self: &Self, prefix: &str, limit: usize) -> Vec<Suggestion> {
+        let prefix = index_key(prefix.trim_start());
+        let start = self.keys.partition_point(|(key, _, _)| key.as_str() < prefix.as_str());
+        let mut found: Vec<(usize, bool)> = self.keys[start..].iter()
+            .take_while(|(key, _, _)| key.starts_with(&prefix))
+            .map(|(_, place_ix, whole_name)| (*place_ix, *whole_name))
+            .collect();
+
+        // a place may start with the prefix in several places, keep the best of them
+        found.sort_unstable_by_key(|(place_ix, whole_name)| (*place_ix, !whole_name));
+        found.dedup_by_key(|(place_ix, _)| *place_ix);
+        found.sort_by(|(a, a_whole), (b, b_whole)| {
+            b_whole.cmp(a_whole).then(self.places[*b].population.total_cmp(&self.places[*a].population))
+        });
+        return found.into_iter().take(limit).map(|(place_ix, _)| self.places[place_ix].clone()).collect();
+    }
+}
+
+/// A name folded with `fold_name` and with each run of characters between words replaced by a single space
+fn index_key(name: &str) -> String {
+    let mut key = String::with_capacity(name.len());
+    for c in fold_name(name).chars() {
+        if c.is_alphanumeric() {
+            key.push(c);
+        } else if !key.is_empty() && !key.ends_with(' ') {
+            key.push(' ');
+        }
+    }
+    return key;
+}
+
+impl GeoDatabase {
+    /// Build an index for suggesting place names as they're typed, see `AutocompleteIndex`
+    pub fn autocomplete_index(self: &Self) -> AutocompleteIndex {
+        return AutocompleteIndex::new(self);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{CityId, CountryId, GeoDatabase, NewCity, Place};
+
+    #[test]
+    fn suggestions() {
+        let mut builder = GeoDatabase::builder();
+        let city = NewCity {
+            name: "Saint Paul", latitude: 44.94441, longitude: -93.09327, population: 0x305,
+            country_code: "US", country: "United States", region: "Minnesota", subregion: "Ramsey County",
+            timezone: "America/Chicago", feature: "PPLA"
+        };
+        builder.add_city(city);
+        builder.add_city(NewCity { name: "Saint-Étienne", latitude: 45.43389, longitude: 4.39, population: 0x175,
+            country_code: "FR", country: "France", region: "Auvergne-Rhône-Alpes", subregion: "Loire", timezone: "Europe/Paris", ..city });
+        builder.add_city(NewCity { name: "Paulding", population: 0x133, ..city });
+        let index = builder.build().unwrap().autocomplete_index();
+
+        let names = |prefix, limit| index.autocomplete(prefix, limit).into_iter().map(|suggestion| suggestion.name).collect::<Vec<_>>();
+        assert_eq!(names("sain", 5), ["Saint Paul", "Saint-Étienne"]);
+        assert_eq!(names("saint e", 5), ["Saint-Étienne"]);
+        // whole names first, then words within names
+        assert_eq!(names("Paul", 5), ["Paulding", "Saint Paul"]);
+        assert_eq!(names("sain", 1), ["Saint Paul"]);
+        assert_eq!(names("xyz", 5), Vec::<String>::new());
+
+        let united = index.autocomplete("uni", 5);
+        assert_eq!(united.len(), 1);
+        assert_eq!(united[0].place, Place::Country(CountryId(0)));
+        assert_eq!(united[0].population, 3.0e5 + 1.3e3);
+        assert_eq!(index.autocomplete("étienne", 1)[0].place, Place::City(CityId(1)));
+    }
+}
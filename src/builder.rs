@@ -0,0 +1,386 @@
+//! Construction of a `GeoDatabase` from scratch, e.g. for test fixtures or custom gazetteers
+
+use std::{collections::HashMap, fmt};
+use crate::{write_city_entry, CityEntry, CityId, CountryId, FeatureId, GeoDatabase, RegionId, SubregionId, TimezoneId};
+
+/// Largest number of entries each table can hold, limited by the number of bits used for the index in a
+/// city record
+const MAX_COUNTRIES: usize = 1 << 8;
+const MAX_REGIONS: usize = 1 << 12;
+const MAX_SUBREGIONS: usize = 1 << 16;
+const MAX_TIMEZONES: usize = 1 << 9;
+const MAX_FEATURES: usize = 1 << 6;
+
+/// The number of entries in the country, region, sub-region, timezone and feature tables
+pub(crate) type TableSizes = [usize; 5];
+const TABLE_NAMES: [&str; 5] = ["countries", "regions", "sub-regions", "timezones", "features"];
+
+/// A city to add to a `GeoDatabaseBuilder`, referring to its country, region, sub-region, timezone and
+/// feature by name
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NewCity<'a> {
+    pub name: &'a str,
+    pub latitude: f64,
+    pub longitude: f64,
+    /// Population in the packed form returned by `parse_population_string`
+    pub population: u16,
+    pub country_code: &'a str,
+    pub country: &'a str,
+    pub region: &'a str,
+    pub subregion: &'a str,
+    pub timezone: &'a str,
+    pub feature: &'a str
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum DatabaseBuildError {
+    TooManyEntries { table: &'static str, max: usize, found: usize },
+    InvalidName { table: &'static str, name: String, msg: &'static str },
+    InvalidCountryCode { code: String },
+    ConflictingCountryName { code: String, first: String, second: String },
+    PositionOutOfRange { city: String, latitude: f64, longitude: f64 },
+    InvalidPopulation { city: String, population: u16 },
+    InvalidReference { city: String, table: &'static str },
+    UnreferencedEntry { table: &'static str, name: String },
+    UnencodableCity { city: String }
+}
+
+impl fmt::Display for DatabaseBuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DatabaseBuildError::TooManyEntries { table, max, found } => write!(f, "too many {table}, at most {max} can be stored but found {found}"),
+            DatabaseBuildError::InvalidName { table, name, msg } => write!(f, "invalid name {name:?} in {table}: {msg}"),
+            DatabaseBuildError::InvalidCountryCode { code } => write!(f, "invalid country code {code:?}, expected two ASCII letters"),
+            DatabaseBuildError::ConflictingCountryName { code, first, second } => write!(f, "country code {code} used for both \"{first}\" and \"{second}\""),
+            DatabaseBuildError::PositionOutOfRange { city, latitude, longitude } => write!(f, "position of {city} ({latitude}, {longitude}) is out of range, latitude must be at least -90 and less than 90 and longitude at least -180 and less than 180"),
+            DatabaseBuildError::InvalidPopulation { city, population } => write!(f, "invalid population 0x{population:X} for {city}"),
+            DatabaseBuildError::InvalidReference { city, table } => write!(f, "{city} refers to an entry which doesn't exist in {table}"),
+            DatabaseBuildError::UnreferencedEntry { table, name } => write!(f, "no city refers to \"{name}\" in {table}"),
+            DatabaseBuildError::UnencodableCity { city } => write!(f, "the record for {city} would be read back as the end of the cities section")
+        }
+    }
+}
+
+impl std::error::Error for DatabaseBuildError {}
+
+/// A table of unique names in insertion order
+#[derive(Default)]
+struct InternTable {
+    names: Vec<String>,
+    lookup: HashMap<String, usize>
+}
+
+impl InternTable {
+    fn intern(self: &mut Self, name: &str) -> usize {
+        if let Some(ix) = self.lookup.get(name) {
+            return *ix;
+        }
+        self.names.push(name.to_owned());
+        self.lookup.insert(name.to_owned(), self.names.len() - 1);
+        return self.names.len() - 1;
+    }
+}
+
+/// Builds a `GeoDatabase`, adding each distinct country, region, sub-region, timezone and feature name to
+/// its table the first time it is used
+#[derive(Default)]
+pub struct GeoDatabaseBuilder {
+    comment: String,
+    cities: Vec<CityEntry>,
+    /// (code, name) in insertion order
+    countries: Vec<(String, String)>,
+    country_lookup: HashMap<String, usize>,
+    conflicts: Vec<DatabaseBuildError>,
+    regions: InternTable,
+    subregions: InternTable,
+    timezones: InternTable,
+    features: InternTable
+}
+
+impl GeoDatabaseBuilder {
+    pub fn new() -> GeoDatabaseBuilder {
+        GeoDatabaseBuilder::default()
+    }
+
+    pub fn comment(self: &mut Self, comment: impl Into<String>) -> &mut Self {
+        self.comment = comment.into();
+        return self;
+    }
+
+    /// Countries are identified by their code, using the same code with a different name is an error
+    /// reported by `build`
+    pub fn country(self: &mut Self, code: &str, name: &str) -> CountryId {
+        if let Some(ix) = self.country_lookup.get(code) {
+            let (_, existing) = &self.countries[*ix];
+            if existing != name {
+                self.conflicts.push(DatabaseBuildError::ConflictingCountryName { code: code.to_owned(), first: existing.clone(), second: name.to_owned() });
+            }
+            return CountryId(*ix);
+        }
+        self.countries.push((code.to_owned(), name.to_owned()));
+        self.country_lookup.insert(code.to_owned(), self.countries.len() - 1);
+        return CountryId(self.countries.len() - 1);
+    }
+
+    pub fn region(self: &mut Self, name: &str) -> RegionId {
+        return RegionId(self.regions.intern(name));
+    }
+
+    pub fn subregion(self: &mut Self, name: &str) -> SubregionId {
+        return SubregionId(self.subregions.intern(name));
+    }
+
+    pub fn timezone(self: &mut Self, name: &str) -> TimezoneId {
+        return TimezoneId(self.timezones.intern(name));
+    }
+
+    pub fn feature(self: &mut Self, name: &str) -> FeatureId {
+        return FeatureId(self.features.intern(name));
+    }
+
+    pub fn add_city(self: &mut Self, city: NewCity) -> CityId {
+        let entry = CityEntry {
+            name: city.name.to_owned(),
+            latitude: city.latitude,
+            longitude: city.longitude,
+            population: city.population,
+            country_ix: self.country(city.country_code, city.country),
+            region_ix: self.region(city.region),
+            subregion_ix: self.subregion(city.subregion),
+            timezone_ix: self.timezone(city.timezone),
+            feature_ix: self.feature(city.feature),
+            reserved: 0
+        };
+        return self.add_city_entry(entry);
+    }
+
+    /// Add a city whose references were obtained from this builder
+    pub fn add_city_entry(self: &mut Self, city: CityEntry) -> CityId {
+        self.cities.push(city);
+        return CityId(self.cities.len() - 1);
+    }
+
+    /// Check everything can be represented in the database file format and create the database. Every table
+    /// entry must be referred to by a city.
+    pub fn build(self: Self) -> Result<GeoDatabase, DatabaseBuildError> {
+        if let Some(conflict) = self.conflicts.first() {
+            return Err(conflict.clone());
+        }
+
+        check_line("comment", &self.comment, true)?;
+        let sizes = [self.countries.len(), self.regions.names.len(), self.subregions.names.len(), self.timezones.names.len(), self.features.names.len()];
+        check_table_sizes(sizes)?;
+
+        for (code, name) in &self.countries {
+            if code.len() != 2 || !code.chars().all(|c| c.is_ascii_alphabetic()) {
+                return Err(DatabaseBuildError::InvalidCountryCode { code: code.clone() });
+            }
+            check_line("countries", name, false)?;
+        }
+        for (table, names) in [("regions", &self.regions.names), ("sub-regions", &self.subregions.names), ("timezones", &self.timezones.names), ("features", &self.features.names)] {
+            for name in names {
+                check_line(table, name, false)?;
+            }
+        }
+
+        for city in &self.cities {
+            check_city(city, sizes)?;
+        }
+        check_referenced(&self)?;
+
+        Ok(GeoDatabase {
+            comment: self.comment,
+            cities: self.cities,
+            countries: self.countries.into_iter().map(|(code, name)| code + &name).collect(),
+            regions: self.regions.names,
+            subregions: self.subregions.names,
+            timezones: self.timezones.names,
+            features: self.features.names,
+            extra_sections: vec![],
+            region_codes: vec![]
+        })
+    }
+}
+
+/// Check each table's indices fit in their field of a city record
+pub(crate) fn check_table_sizes(sizes: TableSizes) -> Result<(), DatabaseBuildError> {
+    let maximums = [MAX_COUNTRIES, MAX_REGIONS, MAX_SUBREGIONS, MAX_TIMEZONES, MAX_FEATURES];
+    for ((table, found), max) in TABLE_NAMES.into_iter().zip(sizes).zip(maximums) {
+        if found > max {
+            return Err(DatabaseBuildError::TooManyEntries { table, max, found });
+        }
+    }
+    return Ok(());
+}
+
+/// Names are stored one per line, ended by a newline
+fn check_line(table: &'static str, name: &str, allow_empty: bool) -> Result<(), DatabaseBuildError> {
+    let invalid = |msg| Err(DatabaseBuildError::InvalidName { table, name: name.to_owned(), msg });
+    if name.is_empty() && !allow_empty {
+        return invalid("cannot be empty");
+    }
+    if name.contains('\n') {
+        return invalid("cannot contain line breaks");
+    }
+    if name.contains('\0') {
+        return invalid("cannot contain NUL characters");
+    }
+    return Ok(());
+}
+
+/// Check a city can be written as a record and read back the same, given the sizes of the tables it refers to
+pub(crate) fn check_city(city: &CityEntry, sizes: TableSizes) -> Result<(), DatabaseBuildError> {
+    check_line("cities", &city.name, false)?;
+    if !(-90.0..90.0).contains(&city.latitude) || !(-180.0..180.0).contains(&city.longitude) {
+        return Err(DatabaseBuildError::PositionOutOfRange { city: city.name.clone(), latitude: city.latitude, longitude: city.longitude });
+    }
+    if city.population > 0xfff || city.population >> 8 > 9 || (city.population >> 4 & 0x0f) > 9 {
+        return Err(DatabaseBuildError::InvalidPopulation { city: city.name.clone(), population: city.population });
+    }
+
+    // references added with add_city_entry may not have come from this builder
+    let indices = [city.country_ix.0, city.region_ix.0, city.subregion_ix.0, city.timezone_ix.0, city.feature_ix.0];
+    for ((table, ix), len) in TABLE_NAMES.into_iter().zip(indices).zip(sizes) {
+        if ix >= len {
+            return Err(DatabaseBuildError::InvalidReference { city: city.name.clone(), table });
+        }
+    }
+
+    let mut record = vec![];
+    write_city_entry(&mut record, city).expect("writing to a vec cannot fail");
+    if record[..6] == [0, 0, 0, 0, 1, 0xA] {
+        return Err(DatabaseBuildError::UnencodableCity { city: city.name.clone() });
+    }
+    return Ok(());
+}
+
+/// The parents of regions and sub-regions are found from their cities, so every entry must have one
+fn check_referenced(builder: &GeoDatabaseBuilder) -> Result<(), DatabaseBuildError> {
+    let country_names: Vec<String> = builder.countries.iter().map(|(_, name)| name.clone()).collect();
+    let tables = [
+        ("countries", &country_names),
+        ("regions", &builder.regions.names),
+        ("sub-regions", &builder.subregions.names),
+        ("timezones", &builder.timezones.names),
+        ("features", &builder.features.names)
+    ];
+    let mut referenced = tables.map(|(_, names)| vec![false; names.len()]);
+    for city in &builder.cities {
+        let indices = [city.country_ix.0, city.region_ix.0, city.subregion_ix.0, city.timezone_ix.0, city.feature_ix.0];
+        referenced.iter_mut().zip(indices).for_each(|(referenced, ix)| referenced[ix] = true);
+    }
+    for ((table, names), referenced) in tables.into_iter().zip(referenced) {
+        if let Some(ix) = referenced.iter().position(|referenced| !referenced) {
+            return Err(DatabaseBuildError::UnreferencedEntry { table, name: names[ix].clone() });
+        }
+    }
+    return Ok(());
+}
+
+impl GeoDatabase {
+    pub fn builder() -> GeoDatabaseBuilder {
+        GeoDatabaseBuilder::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DatabaseBuildError, MAX_FEATURES};
+    use crate::{CityEntry, CountryId, FeatureId, GeoDatabase, GeoDatabaseBuilder, NewCity, RegionId, SubregionId, TimezoneId};
+
+    const CITY: NewCity<'static> = NewCity {
+        name: "Canterbury", latitude: 51.27904, longitude: 1.07992, population: 0x454,
+        country_code: "GB", country: "United Kingdom", region: "England", subregion: "Kent",
+        timezone: "Europe/London", feature: "PPL"
+    };
+
+    fn build_with(add: impl FnOnce(&mut GeoDatabaseBuilder)) -> Result<GeoDatabase, DatabaseBuildError> {
+        let mut builder = GeoDatabase::builder();
+        builder.add_city(CITY);
+        add(&mut builder);
+        return builder.build();
+    }
+
+    #[test]
+    fn valid_database() {
+        let database = build_with(|builder| {
+            builder.comment("Test");
+            builder.add_city(NewCity { name: "Dover", latitude: 51.12598, longitude: 1.31257, ..CITY });
+        }).unwrap();
+        assert_eq!(database.cities().count(), 2);
+        assert_eq!(database.subregion_parents(SubregionId(0)), (RegionId(0), CountryId(0), TimezoneId(0)));
+    }
+
+    #[test]
+    fn too_many_entries() {
+        let names: Vec<String> = (1..=MAX_FEATURES).map(|ix| format!("F{ix}")).collect();
+        let result = build_with(|builder| names.iter().for_each(|feature| { builder.add_city(NewCity { feature, ..CITY }); }));
+        assert_eq!(result.err(), Some(DatabaseBuildError::TooManyEntries { table: "features", max: MAX_FEATURES, found: MAX_FEATURES + 1 }));
+    }
+
+    #[test]
+    fn invalid_name() {
+        let result = build_with(|builder| { builder.add_city(NewCity { region: "South\nEast", ..CITY }); });
+        assert!(matches!(result, Err(DatabaseBuildError::InvalidName { table: "regions", .. })));
+        let result = build_with(|builder| { builder.add_city(NewCity { name: "Can\nterbury", ..CITY }); });
+        assert!(matches!(result, Err(DatabaseBuildError::InvalidName { table: "cities", .. })));
+    }
+
+    #[test]
+    fn invalid_country_code() {
+        let result = build_with(|builder| { builder.add_city(NewCity { country_code: "G1", ..CITY }); });
+        assert_eq!(result.err(), Some(DatabaseBuildError::InvalidCountryCode { code: "G1".to_owned() }));
+    }
+
+    #[test]
+    fn conflicting_country_name() {
+        let result = build_with(|builder| { builder.add_city(NewCity { country: "Britain", ..CITY }); });
+        assert!(matches!(result, Err(DatabaseBuildError::ConflictingCountryName { second, .. }) if second == "Britain"));
+    }
+
+    #[test]
+    fn position_out_of_range() {
+        let result = build_with(|builder| { builder.add_city(NewCity { latitude: 90.0, ..CITY }); });
+        assert!(matches!(result, Err(DatabaseBuildError::PositionOutOfRange { .. })));
+        let result = build_with(|builder| { builder.add_city(NewCity { longitude: f64::NAN, ..CITY }); });
+        assert!(matches!(result, Err(DatabaseBuildError::PositionOutOfRange { .. })));
+    }
+
+    #[test]
+    fn invalid_population() {
+        let result = build_with(|builder| { builder.add_city(NewCity { population: 0x4A4, ..CITY }); });
+        assert!(matches!(result, Err(DatabaseBuildError::InvalidPopulation { population: 0x4A4, .. })));
+    }
+
+    #[test]
+    fn invalid_reference() {
+        let result = build_with(|builder| {
+            builder.add_city_entry(CityEntry {
+                name: "Nowhere".to_owned(), latitude: 0.0, longitude: 0.0, population: 0,
+                country_ix: CountryId(0), region_ix: RegionId(0), subregion_ix: SubregionId(0), timezone_ix: TimezoneId(1), feature_ix: FeatureId(0), reserved: 0
+            });
+        });
+        assert_eq!(result.err(), Some(DatabaseBuildError::InvalidReference { city: "Nowhere".to_owned(), table: "timezones" }));
+    }
+
+    #[test]
+    fn unreferenced_entry() {
+        let result = build_with(|builder| { builder.subregion("Surrey"); });
+        assert_eq!(result.err(), Some(DatabaseBuildError::UnreferencedEntry { table: "sub-regions", name: "Surrey".to_owned() }));
+        let result = build_with(|builder| { builder.country("FR", "France"); });
+        assert_eq!(result.err(), Some(DatabaseBuildError::UnreferencedEntry { table: "countries", name: "France".to_owned() }));
+    }
+
+    #[test]
+    fn unencodable_city() {
+        // the eleventh country at the south pole and the sixteenth longitude step gives the bytes of the
+        // table terminator 0,0,0,0,1,\n
+        let codes: Vec<String> = (b'A'..=b'K').map(|c| format!("X{}", c as char)).collect();
+        let result = build_with(|builder| {
+            for code in &codes {
+                builder.add_city(NewCity { country_code: code, country: code, latitude: -90.0, longitude: -180.0 + 16.0 * 360.0 / f64::from(0x100000), ..CITY });
+            }
+        });
+        assert_eq!(result.err(), Some(DatabaseBuildError::UnencodableCity { city: "Canterbury".to_owned() }));
+    }
+}
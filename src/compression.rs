@@ -0,0 +1,128 @@
+//! Transparent handling of gzip and zstd compressed database files, each enabled by the cargo feature of the
+//! same name
+
+use std::{fmt, io::{self, BufRead, Write}, path::Path};
+use crate::DatabaseReadError;
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd
+}
+
+impl Compression {
+    /// Detect the compression of a file from its first few bytes
+    pub fn detect(data: &[u8]) -> Compression {
+        if data.starts_with(&GZIP_MAGIC) {
+            Compression::Gzip
+        } else if data.starts_with(&ZSTD_MAGIC) {
+            Compression::Zstd
+        } else {
+            Compression::None
+        }
+    }
+
+    /// The compression implied by a file's extension, ".gz" or ".zst", e.g. to choose how to write a new file
+    pub fn from_path(path: impl AsRef<Path>) -> Compression {
+        match path.as_ref().extension().and_then(|ext| ext.to_str()) {
+            Some("gz") => Compression::Gzip,
+            Some("zst") => Compression::Zstd,
+            _ => Compression::None
+        }
+    }
+
+    /// Whether the crate was built with support for reading and writing this compression
+    pub fn is_supported(self: Self) -> bool {
+        match self {
+            Compression::None => true,
+            Compression::Gzip => cfg!(feature = "gzip"),
+            Compression::Zstd => cfg!(feature = "zstd")
+        }
+    }
+}
+
+impl fmt::Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Compression::None => "uncompressed",
+            Compression::Gzip => "gzip",
+            Compression::Zstd => "zstd"
+        })
+    }
+}
+
+/// Wrap a reader in a decoder for `compression`
+pub(crate) fn decoder<'r>(reader: &'r mut dyn BufRead, compression: Compression) -> Result<Box<dyn BufRead + 'r>, DatabaseReadError> {
+    match compression {
+        Compression::None => Ok(Box::new(reader)),
+        #[cfg(feature = "gzip")]
+        Compression::Gzip => Ok(Box::new(io::BufReader::new(flate2::bufread::MultiGzDecoder::new(reader)))),
+        #[cfg(feature = "zstd")]
+        Compression::Zstd => Ok(Box::new(io::BufReader::new(zstd::stream::read::Decoder::with_buffer(reader)?))),
+        #[allow(unreachable_patterns)]
+        compression => Err(DatabaseReadError::UnsupportedCompression { compression })
+    }
+}
+
+/// Run `write` with a writer which compresses its output with `compression` before passing it to `writer`
+pub(crate) fn encode<F>(writer: &mut dyn Write, compression: Compression, write: F) -> Result<(), io::Error>
+where
+    F: FnOnce(&mut dyn Write) -> Result<(), io::Error>
+{
+    match compression {
+        Compression::None => write(writer),
+        #[cfg(feature = "gzip")]
+        Compression::Gzip => {
+            let mut encoder = flate2::write::GzEncoder::new(writer, flate2::Compression::default());
+            write(&mut encoder)?;
+            encoder.finish()?;
+            Ok(())
+        },
+        #[cfg(feature = "zstd")]
+        Compression::Zstd => {
+            let mut encoder = zstd::stream::write::Encoder::new(writer, zstd::DEFAULT_COMPRESSION_LEVEL)?;
+            write(&mut encoder)?;
+            encoder.finish()?;
+            Ok(())
+        },
+        #[allow(unreachable_patterns)]
+        compression => Err(io::Error::new(io::ErrorKind::Unsupported, format!("{compression} support was not enabled when building")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Compression;
+    use crate::{CityId, GeoDatabase, NewCity, CURRENT_VERSION};
+
+    #[test]
+    fn round_trip() {
+        let mut builder = GeoDatabase::builder();
+        builder.add_city(NewCity {
+            name: "Reykjavík", latitude: 64.13548, longitude: -21.89541, population: 0x115,
+            country_code: "IS", country: "Iceland", region: "Capital Region", subregion: "Reykjavíkurborg",
+            timezone: "Atlantic/Reykjavik", feature: "PPLC"
+        });
+        let database = builder.build().unwrap();
+        let mut uncompressed = vec![];
+        database.write_to(&mut uncompressed).unwrap();
+
+        for compression in [Compression::None, Compression::Gzip, Compression::Zstd] {
+            if !compression.is_supported() {
+                continue;
+            }
+            let mut bytes = vec![];
+            database.write_compressed(&mut bytes, compression, CURRENT_VERSION).unwrap();
+            assert_eq!(Compression::detect(&bytes), compression);
+            let read = GeoDatabase::read_from(&mut &bytes[..]).expect("database can be read back");
+            assert_eq!(read.city(CityId(0)).name(), "Reykjavík");
+            let mut rewritten = vec![];
+            read.write_to(&mut rewritten).unwrap();
+            assert_eq!(rewritten, uncompressed, "{compression}");
+        }
+    }
+}
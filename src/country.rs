@@ -0,0 +1,96 @@
+//! Matching country qualifiers exactly, by ISO 3166-1 code or by name
+//!
+//! With the `iso3166` feature alpha-3 and numeric codes are also accepted, looked up in an embedded table.
+
+use crate::fold_name;
+
+/// The codes of a country in ISO 3166-1
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CountryCodes {
+    pub alpha2: &'static str,
+    pub alpha3: &'static str,
+    pub numeric: u16,
+    pub name: &'static str
+}
+
+#[cfg(feature = "iso3166")]
+static ISO_3166: std::sync::LazyLock<Vec<CountryCodes>> = std::sync::LazyLock::new(|| {
+    include_str!("../data/iso3166-1.tsv").lines()
+        .filter(|line| !line.starts_with('#'))
+        .map(|line| {
+            let fields: Vec<&str> = line.split('\t').collect();
+            CountryCodes { alpha2: fields[0], alpha3: fields[1], numeric: fields[2].parse().expect("invalid numeric code in ISO 3166 table"), name: fields[3] }
+        })
+        .collect()
+});
+
+/// Look up a country in the embedded ISO 3166-1 table by its alpha-2, alpha-3 or numeric code, ignoring case
+#[cfg(feature = "iso3166")]
+pub fn iso3166_country(code: &str) -> Option<&'static CountryCodes> {
+    let code = code.trim();
+    let numeric: Option<u16> = code.parse().ok();
+    return ISO_3166.iter().find(|country| {
+        country.alpha2.eq_ignore_ascii_case(code) || country.alpha3.eq_ignore_ascii_case(code) || Some(country.numeric) == numeric
+    });
+}
+
+/// Whether `code` is the ISO 3166-1 alpha-2, or with the `iso3166` feature alpha-3 or numeric, code of the
+/// country with alpha-2 code `alpha2`
+pub fn is_country_code(code: &str, alpha2: &str) -> bool {
+    if code.eq_ignore_ascii_case(alpha2) {
+        return true;
+    }
+    #[cfg(feature = "iso3166")]
+    if let Some(country) = iso3166_country(code) {
+        return country.alpha2.eq_ignore_ascii_case(alpha2);
+    }
+    return false;
+}
+
+/// Whether a country qualifier names a country table entry (the code followed by the name), either by code
+/// or by the whole name, compared ignoring case and diacritics if `fold`
+pub(crate) fn country_matches(query: &str, country: &str, fold: bool) -> bool {
+    let (code, name) = country.split_at(2);
+    return is_country_code(query, code) || name == query || (fold && fold_name(name) == fold_name(query));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::country_matches;
+    use crate::{GeoDatabase, NewCity, SearchOptions};
+
+    #[test]
+    fn exact_country_matches() {
+        assert!(country_matches("GB", "GBUnited Kingdom", false));
+        assert!(country_matches("gb", "GBUnited Kingdom", false));
+        assert!(country_matches("United Kingdom", "GBUnited Kingdom", false));
+        assert!(!country_matches("United", "GBUnited Kingdom", false));
+        assert!(!country_matches("Niger", "NGNigeria", false));
+        assert!(!country_matches("GB", "XXLand of GBs", false));
+        assert!(country_matches("côte d'ivoire", "CICôte d'Ivoire", true));
+        #[cfg(feature = "iso3166")]
+        {
+            assert!(country_matches("GBR", "GBUnited Kingdom", false));
+            assert!(country_matches("826", "GBUnited Kingdom", false));
+            assert!(!country_matches("NER", "NGNigeria", false));
+        }
+    }
+
+    #[test]
+    fn qualifiers_match_exactly() {
+        let mut builder = GeoDatabase::builder();
+        let city = NewCity {
+            name: "Maradi", latitude: 13.50000, longitude: 7.10174, population: 0x264,
+            country_code: "NE", country: "Niger", region: "Maradi", subregion: "Maradi",
+            timezone: "Africa/Niamey", feature: "PPLA"
+        };
+        builder.add_city(city);
+        builder.add_city(NewCity { latitude: 7.01667, longitude: 3.43333, country_code: "NG", country: "Nigeria", region: "Ogun", subregion: "Ijebu North", timezone: "Africa/Lagos", ..city });
+        let database = builder.build().unwrap();
+        let options = SearchOptions::default();
+
+        assert_eq!(database.find_matching_cities("Maradi, Niger", &options).unwrap().len(), 1);
+        assert_eq!(database.find_matching_cities("Maradi, NG", &options).unwrap().len(), 1);
+        assert_eq!(database.find_matching_countries("Niger", &options).unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,347 @@
+//! A borrowed view of a database file which only decodes the city records that are used, for answering a
+//! few lookups without reading the whole database
+
+use std::io::{self, Write};
+use crate::{format::RECORD_LEN, reader::{check_header, is_sections, table_terminator, ErrorLocation, CITIES_TERMINATOR}, filter::{sort_distance, sort_results, SortKey}, population_value, search::exact_first, write_city_details, write_entries, CityEntry, CityId, CityInfo, CityQuery, PatternError,
+    CoordinateFormat, CountryId, DatabaseReadError, FeatureId, FormatVersion, GeoDatabase, RegionId, SearchOptions, Section, SubregionId, TimezoneId};
+
+/// A database borrowed from the bytes of a database file, e.g. a memory mapped `MappedDatabase`
+///
+/// Opening the view finds where each city record and table entry starts but leaves the records encoded,
+/// names are borrowed from the file rather than copied.
+pub struct GeoDatabaseRef<'a> {
+    data: &'a [u8],
+    format: &'static dyn FormatVersion,
+    comment: &'a str,
+    /// Offset of each city's record in `data`
+    city_offsets: Vec<usize>,
+    city_names: Vec<&'a str>,
+    countries: Vec<&'a str>,
+    regions: Vec<&'a str>,
+    subregions: Vec<&'a str>,
+    timezones: Vec<&'a str>,
+    features: Vec<&'a str>,
+    extra_sections: &'a [u8]
+}
+
+/// Reads lines and records from a byte slice the same way `GeoDatabase::read_from` reads them from a stream
+struct SliceReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    section: Section,
+    /// Index of the entry being read in the current section
+    entry: Option<usize>
+}
+
+impl<'a> SliceReader<'a> {
+    fn location(self: &Self, offset: usize) -> ErrorLocation {
+        ErrorLocation::new(offset as u64, self.section, self.entry, self.data, 0)
+    }
+
+    fn truncated(self: &Self) -> DatabaseReadError {
+        DatabaseReadError::Truncated { location: self.location(self.data.len()) }
+    }
+
+    fn take(self: &mut Self, len: usize) -> Result<&'a [u8], DatabaseReadError> {
+        let bytes = self.data.get(self.pos..self.pos + len).ok_or_else(|| self.truncated())?;
+        self.pos += len;
+        return Ok(bytes);
+    }
+
+    /// A newline terminated line without its newline, any other trailing whitespace is kept
+    fn line(self: &mut Self) -> Result<&'a str, DatabaseReadError> {
+        let rest = &self.data[self.pos..];
+        let len = rest.iter().position(|b| *b == b'\n').ok_or_else(|| self.truncated())? + 1;
+        let line = std::str::from_utf8(&rest[..len - 1])
+            .map_err(|err| DatabaseReadError::InvalidText { location: self.location(self.pos + err.valid_up_to()) })?;
+        self.pos += len;
+        return Ok(line);
+    }
+
+    fn table(self: &mut Self, section: Section, format: &dyn FormatVersion) -> Result<Vec<&'a str>, DatabaseReadError> {
+        self.section = section;
+        let terminator = table_terminator(section, format);
+        let mut entries = vec![];
+        loop {
+            self.entry = Some(entries.len());
+            let entry = self.line()?;
+            if entry.as_bytes() == terminator {
+                return Ok(entries);
+            }
+            entries.push(entry);
+        }
+    }
+}
+
+impl<'a> GeoDatabaseRef<'a> {
+    pub fn from_bytes(data: &'a [u8]) -> Result<GeoDatabaseRef<'a>, DatabaseReadError> {
+        let mut reader = SliceReader { data, pos: 0, section: Section::Header, entry: None };
+        let header_line = reader.line()?;
+        let comment = reader.line()?;
+        let (format, n_cities) = check_header(header_line.trim_end())?;
+
+        // cities
+        reader.section = Section::Cities;
+        let mut city_offsets = vec![];
+        let mut city_names = vec![];
+        loop {
+            reader.entry = Some(city_offsets.len());
+            let offset = reader.pos;
+            if reader.take(CITIES_TERMINATOR.len())? == CITIES_TERMINATOR {
+                break;
+            }
+            reader.take(RECORD_LEN - CITIES_TERMINATOR.len())?;
+            city_offsets.push(offset);
+            city_names.push(reader.line()?);
+        }
+        if city_offsets.len() != n_cities {
+            return Err(DatabaseReadError::CityCountMismatch { expected: n_cities, found: city_offsets.len() });
+        }
+
+        let countries = reader.table(Section::Countries, format)?;
+        let regions = reader.table(Section::Regions, format)?;
+        let subregions = reader.table(Section::Subregions, format)?;
+        let timezones = reader.table(Section::Timezones, format)?;
+        let features = if format.has_feature_table() {
+            reader.table(Section::Features, format)?
+        } else {
+            vec![""]
+        };
+
+        let extra_sections = &data[reader.pos..];
+        if !is_sections(extra_sections) {
+            reader.entry = None;
+            return Err(DatabaseReadError::TrailingData { location: reader.location(reader.pos) });
+        }
+
+        Ok(GeoDatabaseRef { data, format, comment, city_offsets, city_names, countries, regions, subregions, timezones, features, extra_sections })
+    }
+
+    pub fn comment(self: &Self) -> &'a str {
+        return self.comment;
+    }
+
+    pub fn city_count(self: &Self) -> usize {
+        return self.city_offsets.len();
+    }
+
+    pub fn country_count(self: &Self) -> usize {
+        return self.countries.len();
+    }
+
+    pub fn region_count(self: &Self) -> usize {
+        return self.regions.len();
+    }
+
+    pub fn subregion_count(self: &Self) -> usize {
+        return self.subregions.len();
+    }
+
+    pub fn timezone_count(self: &Self) -> usize {
+        return self.timezones.len();
+    }
+
+    pub fn feature_count(self: &Self) -> usize {
+        return self.features.len();
+    }
+
+    pub fn city_name(self: &Self, city_ix: CityId) -> &'a str {
+        return self.city_names[city_ix.0];
+    }
+
+    /// Decode a city's record
+    pub fn city_entry(self: &Self, city_ix: CityId) -> CityEntry {
+        let mut city = self.decode(city_ix);
+        city.name = self.city_names[city_ix.0].to_owned();
+        return city;
+    }
+
+    /// Decode a city's record without copying its name
+    fn decode(self: &Self, city_ix: CityId) -> CityEntry {
+        let offset = self.city_offsets[city_ix.0];
+        let record = self.data[offset..offset + RECORD_LEN].try_into().expect("record offsets are checked when opened");
+        return self.format.decode_record(record, String::new());
+    }
+
+    pub fn city_info(self: &Self, city_ix: CityId) -> CityInfo {
+        let city = self.decode(city_ix);
+        CityInfo {
+            city_ix,
+            name: self.city_names[city_ix.0].to_owned(),
+            latitude: city.latitude,
+            longitude: city.longitude,
+            population: city.population,
+            subregion: self.subregion_name(city.subregion_ix).to_owned(),
+            region: self.region_name(city.region_ix).to_owned(),
+            region_code: None,
+            country: self.country_name(city.country_ix).to_owned(),
+            country_code: self.country_code(city.country_ix).to_owned(),
+            timezone: self.timezone_name(city.timezone_ix).to_owned(),
+            feature: self.feature_name(city.feature_ix).to_owned()
+        }
+    }
+
+    pub fn subregion_name(self: &Self, subregion_ix: SubregionId) -> &'a str {
+        return self.subregions[subregion_ix.0];
+    }
+
+    pub fn region_name(self: &Self, region_ix: RegionId) -> &'a str {
+        return self.regions[region_ix.0];
+    }
+
+    pub fn country_name(self: &Self, country_ix: CountryId) -> &'a str {
+        return &self.countries[country_ix.0][2..];
+    }
+
+    pub fn country_code(self: &Self, country_ix: CountryId) -> &'a str {
+        return &self.countries[country_ix.0][0..2];
+    }
+
+    pub fn timezone_name(self: &Self, timezone_ix: TimezoneId) -> &'a str {
+        return self.timezones[timezone_ix.0];
+    }
+
+    pub fn feature_name(self: &Self, feature_ix: FeatureId) -> &'a str {
+        return self.features[feature_ix.0];
+    }
+
+    /// Same as `GeoDatabase::find_matching_cities`, only the records of cities with a matching name are decoded
+    ///
+    /// There are no region codes, see `GeoDatabase::import_region_codes`, so a region qualifier only matches names.
+    pub fn find_matching_cities(self: &Self, name: &str, options: &SearchOptions) -> Result<Vec<CityId>, PatternError> {
+        let query = CityQuery::parse(name, options)?;
+        let mut found: Vec<(CityId, CityEntry)> = exact_first((0..self.city_count()).map(CityId)
+            .filter(|city_ix| query.name.equals(self.city_names[city_ix.0]))
+            .map(|city_ix| (city_ix, self.decode(city_ix)))
+            .filter(|(city_ix, city)| {
+                return query.matches(
+                    self.city_names[city_ix.0],
+                    self.subregions[city.subregion_ix.0],
+                    self.regions[city.region_ix.0],
+                    None,
+                    self.countries[city.country_ix.0]
+                ) && options.filter.passes(
+                    city,
+                    self.countries[city.country_ix.0],
+                    self.regions[city.region_ix.0],
+                    None,
+                    self.timezones[city.timezone_ix.0],
+                    self.features[city.feature_ix.0]
+                );
+            })
+            .map(|(city_ix, city)| {
+                let exact = query.name.is_exact(self.city_names[city_ix.0]);
+                ((city_ix, city), exact)
+            })
+            .collect());
+        sort_results(&mut found, options.sort, |(city_ix, city)| SortKey {
+            name: self.city_names[city_ix.0],
+            population: population_value(city.population),
+            distance: sort_distance(options.sort, city.latitude, city.longitude)
+        });
+        return Ok(found.into_iter().map(|(city_ix, _)| city_ix).collect());
+    }
+
+    pub fn write_city_info(self: &Self, writer: &mut dyn Write, city_ix: CityId, coord_format: &CoordinateFormat) -> Result<(), io::Error> {
+        return write_city_details(writer, &self.city_info(city_ix), coord_format);
+    }
+
+    pub fn write_matching_cities(self: &Self, writer: &mut dyn Write, name: &str, options: &SearchOptions, max_displayed: usize, coord_format: &CoordinateFormat) -> Result<(), io::Error> {
+        write_entries(
+            writer,
+            self.find_matching_cities(name, options)?,
+            |writer, ix| self.write_city_info(writer, ix, coord_format),
+            max_displayed
+        )
+    }
+
+    pub fn print_matching_cities(self: &Self, name: &str, options: &SearchOptions, max_displayed: usize, coord_format: &CoordinateFormat) {
+        self.write_matching_cities(&mut io::stdout(), name, options, max_displayed, coord_format).expect("failed to write to stdout");
+    }
+
+    /// Decode every record into an owned database, e.g. to modify it
+    pub fn to_database(self: &Self) -> GeoDatabase {
+        let owned = |table: &[&str]| table.iter().map(|entry| entry.to_string()).collect();
+        GeoDatabase {
+            comment: self.comment.to_owned(),
+            cities: (0..self.city_count()).map(|city_ix| self.city_entry(CityId(city_ix))).collect(),
+            countries: owned(&self.countries),
+            regions: owned(&self.regions),
+            subregions: owned(&self.subregions),
+            timezones: owned(&self.timezones),
+            features: owned(&self.features),
+            extra_sections: self.extra_sections.to_vec(),
+            region_codes: vec![]
+        }
+    }
+}
+
+/// A database file mapped into memory, which can be viewed as a `GeoDatabaseRef` without reading it
+#[cfg(feature = "mmap")]
+pub struct MappedDatabase {
+    map: memmap2::Mmap
+}
+
+#[cfg(feature = "mmap")]
+impl MappedDatabase {
+    /// # Safety
+    ///
+    /// The file must not be modified or truncated while it is mapped, see `memmap2::Mmap::map`
+    pub unsafe fn map(file: &std::fs::File) -> Result<MappedDatabase, io::Error> {
+        return Ok(MappedDatabase { map: memmap2::Mmap::map(file)? });
+    }
+
+    pub fn database<'a>(self: &'a Self) -> Result<GeoDatabaseRef<'a>, DatabaseReadError> {
+        return GeoDatabaseRef::from_bytes(&self.map);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::GeoDatabaseRef;
+    use crate::{CityId, DatabaseReadError, GeoDatabase, NewCity, SearchOptions};
+
+    fn sample_bytes() -> Vec<u8> {
+        let mut builder = GeoDatabase::builder();
+        builder.comment("sample");
+        let city = NewCity {
+            name: "Springfield", latitude: 39.80172, longitude: -89.64371, population: 0x115,
+            country_code: "US", country: "United States", region: "Illinois", subregion: "Sangamon County",
+            timezone: "America/Chicago", feature: "PPLA"
+        };
+        builder.add_city(city);
+        builder.add_city(NewCity { latitude: 42.10148, longitude: -72.58981, region: "Massachusetts", subregion: "Hampden County", timezone: "America/New_York", ..city });
+        builder.add_city(NewCity { name: "Boston", latitude: 42.35843, longitude: -71.05977, region: "Massachusetts", subregion: "Suffolk County", timezone: "America/New_York", ..city });
+        let mut bytes = vec![];
+        builder.build().unwrap().write_to(&mut bytes).unwrap();
+        return bytes;
+    }
+
+    #[test]
+    fn matches_owned_database() {
+        let bytes = sample_bytes();
+        let borrowed = GeoDatabaseRef::from_bytes(&bytes).expect("sample database is valid");
+        let owned = GeoDatabase::read_from(&mut &bytes[..]).expect("sample database is valid");
+
+        assert_eq!(borrowed.city_count(), 3);
+        assert_eq!(borrowed.city_info(CityId(1)), owned.city_info(CityId(1)));
+        for (query, options) in [("Springfield", SearchOptions::default()), ("Springfield, US", SearchOptions::default()),
+            ("Springfield, Massachusetts, US", SearchOptions::default()), ("Boston, Illinois, US", SearchOptions::default()),
+            ("springfield, massachusetts", SearchOptions { fold: true, ..SearchOptions::default() })] {
+            assert_eq!(borrowed.find_matching_cities(query, &options).unwrap(), owned.find_matching_cities(query, &options).unwrap(), "{query}");
+        }
+
+        let mut rewritten = vec![];
+        borrowed.to_database().write_to(&mut rewritten).unwrap();
+        assert_eq!(rewritten, bytes);
+    }
+
+    #[test]
+    fn truncated_file_is_an_error() {
+        let bytes = sample_bytes();
+        for len in [0, 10, 40, bytes.len() - 1] {
+            let err = GeoDatabaseRef::from_bytes(&bytes[..len]).err().expect("truncated database can't be read");
+            assert!(matches!(&err, DatabaseReadError::Truncated { location } if location.offset == len as u64), "{len}: {err}");
+        }
+    }
+}
@@ -0,0 +1,288 @@
+//! Narrowing searches and listings down by the attributes of cities, and the orders results can be sorted in
+
+use crate::{country::is_country_code, fold_name, population_value, CityEntry, CityId, CountryId, FeatureId, GeoDatabase, RegionId, SearchOptions, SubregionId, TimezoneId};
+
+/// Mean radius of the Earth in kilometres
+const EARTH_RADIUS_KM: f64 = 6371.0088;
+
+/// An area between two latitudes and two longitudes, which crosses the antimeridian if `west` is greater than
+/// `east`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BoundingBox {
+    pub south: f64,
+    pub west: f64,
+    pub north: f64,
+    pub east: f64
+}
+
+impl BoundingBox {
+    pub fn contains(self: &Self, latitude: f64, longitude: f64) -> bool {
+        let within_longitudes = if self.west <= self.east {
+            (self.west..=self.east).contains(&longitude)
+        } else {
+            longitude >= self.west || longitude <= self.east
+        };
+        return (self.south..=self.north).contains(&latitude) && within_longitudes;
+    }
+}
+
+/// Conditions a city must meet, every condition which is set must be met
+///
+/// Names are compared ignoring case and diacritics. The country code may be any code accepted by
+/// `is_country_code` and the region may be its ISO 3166-2 code, if one has been imported. The timezone
+/// matches the timezone's name or any timezone it starts, e.g. "America" matches "America/Chicago".
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CityFilter {
+    pub country_code: Option<String>,
+    pub region: Option<String>,
+    pub timezone: Option<String>,
+    pub feature: Option<String>,
+    pub min_population: Option<f64>,
+    pub max_population: Option<f64>,
+    pub bounding_box: Option<BoundingBox>
+}
+
+impl CityFilter {
+    /// Whether the filter has no conditions, so every city passes
+    pub fn is_empty(self: &Self) -> bool {
+        return *self == CityFilter::default();
+    }
+
+    /// Whether a city passes, `country` is its country table entry and the other names its table entries
+    pub(crate) fn passes(self: &Self, city: &CityEntry, country: &str, region: &str, region_code: Option<&str>, timezone: &str, feature: &str) -> bool {
+        let population = population_value(city.population);
+        return self.country_code.as_ref().is_none_or(|code| is_country_code(code, &country[..2]))
+            && self.region.as_ref().is_none_or(|name| fold_name(region) == fold_name(name) || region_code.is_some_and(|code| code.eq_ignore_ascii_case(name)))
+            && self.timezone.as_ref().is_none_or(|name| {
+                let (timezone, name) = (timezone.to_lowercase(), name.to_lowercase());
+                timezone == name || timezone.starts_with(&format!("{}/", name.trim_end_matches('/')))
+            })
+            && self.feature.as_ref().is_none_or(|name| fold_name(feature) == fold_name(name))
+            && self.min_population.is_none_or(|min| population >= min)
+            && self.max_population.is_none_or(|max| population <= max)
+            && self.bounding_box.is_none_or(|bounds| bounds.contains(city.latitude, city.longitude));
+    }
+}
+
+/// The order to list results in
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum SortOrder {
+    /// Exact matches first, then in the order of the database
+    #[default]
+    Database,
+    /// Alphabetically, ignoring case and diacritics
+    Name,
+    /// Largest population first, the total of their cities for sub-regions, regions and countries
+    Population,
+    /// Nearest first, the nearest of their cities for sub-regions, regions and countries
+    Distance { latitude: f64, longitude: f64 }
+}
+
+/// Great circle distance in kilometres between two positions given in degrees
+pub fn distance_km(latitude_a: f64, longitude_a: f64, latitude_b: f64, longitude_b: f64) -> f64 {
+    let (phi_a, phi_b) = (latitude_a.to_radians(), latitude_b.to_radians());
+    let half_delta_phi = (phi_b - phi_a) / 2.0;
+    let half_delta_lambda = (longitude_b - longitude_a).to_radians() / 2.0;
+    let a = half_delta_phi.sin().powi(2) + phi_a.cos() * phi_b.cos() * half_delta_lambda.sin().powi(2);
+    return 2.0 * EARTH_RADIUS_KM * a.sqrt().min(1.0).asin();
+}
+
+/// What a result is sorted by, the name and then whichever of population and distance the order uses
+pub(crate) struct SortKey<'a> {
+    pub(crate) name: &'a str,
+    pub(crate) population: f64,
+    pub(crate) distance: f64
+}
+
+/// Stably sort results by `order`, `key` gives what each result is sorted by
+pub(crate) fn sort_results<'a, T>(results: &mut [T], order: SortOrder, key: impl Fn(&T) -> SortKey<'a>) {
+    match order {
+        SortOrder::Database => {},
+        SortOrder::Name => results.sort_by_cached_key(|result| fold_name(key(result).name)),
+        SortOrder::Population => results.sort_by(|a, b| key(b).population.total_cmp(&key(a).population)),
+        SortOrder::Distance { .. } => results.sort_by(|a, b| key(a).distance.total_cmp(&key(b).distance))
+    }
+}
+
+/// The distance of a position from the point a sort order measures from, zero for other orders
+pub(crate) fn sort_distance(order: SortOrder, latitude: f64, longitude: f64) -> f64 {
+    match order {
+        SortOrder::Distance { latitude: from_latitude, longitude: from_longitude } => distance_km(from_latitude, from_longitude, latitude, longitude),
+        _ => 0.0
+    }
+}
+
+impl GeoDatabase {
+    /// Whether a city passes a filter
+    pub fn city_passes(self: &Self, city_ix: CityId, filter: &CityFilter) -> bool {
+        let city = &self.cities[city_ix.0];
+        return filter.passes(
+            city,
+            &self.countries[city.country_ix.0],
+            &self.regions[city.region_ix.0],
+            self.region_code(city.region_ix, city.country_ix),
+            &self.timezones[city.timezone_ix.0],
+            &self.features[city.feature_ix.0]
+        );
+    }
+
+    /// Every city which passes the filter and sort order of `options`, in that order
+    pub fn filter_cities(self: &Self, options: &SearchOptions) -> Vec<CityId> {
+        return self.apply_city_options((0..self.cities.len()).map(CityId).collect(), options);
+    }
+
+    /// Every sub-region with a city which passes the filter of `options`, in its sort order
+    pub fn filter_subregions(self: &Self, options: &SearchOptions) -> Vec<SubregionId> {
+        let all = (0..self.subregions.len()).map(SubregionId).collect();
+        return self.apply_table_options(all, options, self.subregions.len(), |ix| self.subregions[ix].as_str(), |ix: &SubregionId| ix.0, |city| city.subregion_ix.0);
+    }
+
+    /// Every region with a city which passes the filter of `options`, in its sort order
+    pub fn filter_regions(self: &Self, options: &SearchOptions) -> Vec<RegionId> {
+        let all = (0..self.regions.len()).map(RegionId).collect();
+        return self.apply_table_options(all, options, self.regions.len(), |ix| self.regions[ix].as_str(), |ix: &RegionId| ix.0, |city| city.region_ix.0);
+    }
+
+    /// Every country with a city which passes the filter of `options`, in its sort order
+    pub fn filter_countries(self: &Self, options: &SearchOptions) -> Vec<CountryId> {
+        let all = (0..self.countries.len()).map(CountryId).collect();
+        return self.apply_table_options(all, options, self.countries.len(), |ix| &self.countries[ix][2..], |ix: &CountryId| ix.0, |city| city.country_ix.0);
+    }
+
+    /// Every timezone with a city which passes the filter of `options`, in its sort order
+    pub fn filter_timezones(self: &Self, options: &SearchOptions) -> Vec<TimezoneId> {
+        let all = (0..self.timezones.len()).map(TimezoneId).collect();
+        return self.apply_table_options(all, options, self.timezones.len(), |ix| self.timezones[ix].as_str(), |ix: &TimezoneId| ix.0, |city| city.timezone_ix.0);
+    }
+
+    /// Every feature with a city which passes the filter of `options`, in its sort order
+    pub fn filter_features(self: &Self, options: &SearchOptions) -> Vec<FeatureId> {
+        let all = (0..self.features.len()).map(FeatureId).collect();
+        return self.apply_table_options(all, options, self.features.len(), |ix| self.features[ix].as_str(), |ix: &FeatureId| ix.0, |city| city.feature_ix.0);
+    }
+
+    /// For each entry of a table with `len` entries, whether it has a city which passes `filter`, `key` gives
+    /// the entry a city is in
+    pub(crate) fn entries_with_passing_city(self: &Self, len: usize, filter: &CityFilter, key: impl Fn(&CityEntry) -> usize) -> Vec<bool> {
+        let mut passing = vec![filter.is_empty(); len];
+        if !filter.is_empty() {
+            for (city_ix, city) in self.cities.iter().enumerate() {
+                if self.city_passes(CityId(city_ix), filter) {
+                    passing[key(city)] = true;
+                }
+            }
+        }
+        return passing;
+    }
+
+    /// Remove the cities which don't pass the filter of `options` and sort the rest
+    pub(crate) fn apply_city_options(self: &Self, mut cities: Vec<CityId>, options: &SearchOptions) -> Vec<CityId> {
+        if !options.filter.is_empty() {
+            cities.retain(|city_ix| self.city_passes(*city_ix, &options.filter));
+        }
+        sort_results(&mut cities, options.sort, |city_ix| {
+            let city = &self.cities[city_ix.0];
+            SortKey {
+                name: &city.name,
+                population: population_value(city.population),
+                distance: sort_distance(options.sort, city.latitude, city.longitude)
+            }
+        });
+        return cities;
+    }
+
+    /// Remove the entries of a table with no cities which pass the filter of `options` and sort the rest
+    ///
+    /// The table has `len` entries, `name` gives the name of an entry, `index` the index of a result and `key`
+    /// the entry a city is in.
+    pub(crate) fn apply_table_options<'a, T>(
+        self: &'a Self,
+        mut entries: Vec<T>,
+        options: &SearchOptions,
+        len: usize,
+        name: impl Fn(usize) -> &'a str,
+        index: impl Fn(&T) -> usize,
+        key: impl Fn(&CityEntry) -> usize
+    ) -> Vec<T> {
+        if options.filter.is_empty() && options.sort == SortOrder::Database {
+            return entries;
+        }
+        let mut passing = vec![false; len];
+        let mut populations = vec![0.0; len];
+        let mut distances = vec![f64::INFINITY; len];
+        for city_ix in self.apply_city_options((0..self.cities.len()).map(CityId).collect(), &SearchOptions { sort: SortOrder::Database, ..options.clone() }) {
+            let city = &self.cities[city_ix.0];
+            let entry = key(city);
+            passing[entry] = true;
+            populations[entry] += population_value(city.population);
+            distances[entry] = distances[entry].min(sort_distance(options.sort, city.latitude, city.longitude));
+        }
+        entries.retain(|entry| passing[index(entry)]);
+        sort_results(&mut entries, options.sort, |entry| {
+            let entry = index(entry);
+            SortKey { name: name(entry), population: populations[entry], distance: distances[entry] }
+        });
+        return entries;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{distance_km, BoundingBox, CityFilter, SortOrder};
+    use crate::{CityId, GeoDatabase, NewCity, SearchOptions};
+
+    /// Cities out of alphabetical order, with an accented name which sorts differently when folded
+    fn sample() -> GeoDatabase {
+        let mut builder = GeoDatabase::builder();
+        let city = NewCity {
+            name: "Anchorage", latitude: 61.21806, longitude: -149.90028, population: 0x295,
+            country_code: "US", country: "United States", region: "Alaska", subregion: "Anchorage",
+            timezone: "America/Anchorage", feature: "PPL"
+        };
+        builder.add_city(NewCity { name: "Suva", latitude: -18.14161, longitude: 178.44149, population: 0x884, country_code: "FJ", country: "Fiji",
+            region: "Central", subregion: "Rewa", timezone: "Pacific/Fiji", feature: "PPLC" });
+        builder.add_city(NewCity { name: "Honolulu", latitude: 21.30694, longitude: -157.85833, population: 0x106, region: "Hawaii",
+            subregion: "Honolulu County", timezone: "Pacific/Honolulu", feature: "PPLA", ..city });
+        builder.add_city(NewCity { name: "Évry", latitude: 48.62900, longitude: 2.44040, population: 0x544, country_code: "FR", country: "France",
+            region: "Île-de-France", subregion: "Essonne", timezone: "Europe/Paris", feature: "PPLA2" });
+        builder.add_city(city);
+        builder.add_city(NewCity { name: "Juneau", latitude: 58.30194, longitude: -134.41972, population: 0x324, subregion: "Juneau", feature: "PPLA", ..city });
+        return builder.build().unwrap();
+    }
+
+    #[test]
+    fn distances() {
+        // London to Paris
+        assert!((distance_km(51.50853, -0.12574, 48.85341, 2.3488) - 343.5).abs() < 1.0);
+        assert_eq!(distance_km(10.0, 20.0, 10.0, 20.0), 0.0);
+    }
+
+    #[test]
+    fn filters_combine() {
+        let database = sample();
+        let only = |filter: CityFilter| database.filter_cities(&SearchOptions { filter, ..SearchOptions::default() });
+
+        assert_eq!(only(CityFilter { country_code: Some("us".to_owned()), ..CityFilter::default() }), vec![CityId(1), CityId(3), CityId(4)]);
+        assert_eq!(only(CityFilter { region: Some("alaska".to_owned()), feature: Some("ppla".to_owned()), ..CityFilter::default() }), vec![CityId(4)]);
+        assert_eq!(only(CityFilter { timezone: Some("Pacific".to_owned()), min_population: Some(1e6), ..CityFilter::default() }), vec![CityId(1)]);
+        assert_eq!(only(CityFilter { max_population: Some(2.0e5), ..CityFilter::default() }), vec![CityId(0), CityId(2), CityId(4)]);
+        // crossing the antimeridian
+        let bounding_box = BoundingBox { south: -30.0, west: 170.0, north: 30.0, east: -150.0 };
+        assert_eq!(only(CityFilter { bounding_box: Some(bounding_box), ..CityFilter::default() }), vec![CityId(0), CityId(1)]);
+    }
+
+    #[test]
+    fn sorting() {
+        let database = sample();
+        let sorted = |sort| database.filter_cities(&SearchOptions { sort, ..SearchOptions::default() });
+
+        assert_eq!(sorted(SortOrder::Name), vec![CityId(3), CityId(2), CityId(1), CityId(4), CityId(0)]);
+        assert_eq!(sorted(SortOrder::Population), vec![CityId(1), CityId(3), CityId(0), CityId(2), CityId(4)]);
+        assert_eq!(sorted(SortOrder::Distance { latitude: 58.0, longitude: -135.0 }), vec![CityId(4), CityId(3), CityId(1), CityId(2), CityId(0)]);
+
+        let options = SearchOptions { sort: SortOrder::Population, filter: CityFilter { country_code: Some("US".to_owned()), ..CityFilter::default() }, ..SearchOptions::default() };
+        assert_eq!(database.find_matching_regions("Alaska", &options).unwrap().len(), 1);
+        assert_eq!(database.find_matching_countries("United States", &options).unwrap().len(), 1);
+        assert!(database.find_matching_countries("Fiji", &options).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,123 @@
+//! Readers and writers for each version of the Geolocation.dat format
+//!
+//! Every version has the same overall layout:
+//! * a header line, `Geolocation<version> <number of cities>`
+//! * a comment line
+//! * the city records, each a 13 byte record followed by the newline terminated name, ending with
+//!   `0,0,0,0,1,\n`
+//! * newline terminated country entries (a two letter code followed by the name), ending with `0,0,0,0,2,\n`
+//! * newline terminated region names, ending with `0,0,0,0,3,\n`
+//! * newline terminated sub-region names, ending with `0,0,0,0,4,\n`
+//! * newline terminated timezone names
+//! * in versions with feature codes, a `0,0,0,0,5,\n` terminator then newline terminated feature names
+//! * a final `0,0,0,0,0,\n` terminator
+//!
+//! Versions differ in the layout of the city record, see `V1_03`.
+//!
+//! Geolocation.dat is ExifTool's database and has no published specification, so only 1.03, the version
+//! ExifTool currently ships, is supported. Another version can be added by implementing `FormatVersion` once its
+//! layout has been checked against a real file of that version.
+
+use std::io::{self, Write};
+use crate::{decode_city_record, write_city_entry, CityEntry};
+
+/// Length of the fixed size part of a city record
+pub(crate) const RECORD_LEN: usize = 13;
+
+/// The layout of a version of the database format
+pub trait FormatVersion: Sync {
+    /// The version number written in the header, e.g. "1.03"
+    fn version(self: &Self) -> &'static str;
+
+    /// Decode the fixed size part of a city record
+    fn decode_record(self: &Self, data: &[u8; RECORD_LEN], name: String) -> CityEntry;
+
+    /// Write a city record followed by its name, failing if the city can't be represented in this version
+    fn write_record(self: &Self, writer: &mut dyn Write, city: &CityEntry) -> Result<(), io::Error>;
+
+    /// Whether the timezones are followed by a table of feature names
+    fn has_feature_table(self: &Self) -> bool;
+}
+
+/// Version 1.03, the current version
+///
+/// City record:
+/// * 20 bit latitude
+/// * 20 bit longitude
+/// * 8 bit country index
+/// * 12 bit population (4 bit integer, 4 bit decimal, 4 bit significand)
+/// * 12 bit region index
+/// * 16 bit subregion index
+/// * 8 bit timezone index low bits
+/// * 1 bit timezone index high bit
+/// * 1 bit unused, kept in `CityEntry::reserved`
+/// * 6 bit feature code index
+pub struct V1_03;
+
+/// The version written by `GeoDatabase::write_to`
+pub const CURRENT_VERSION: &dyn FormatVersion = &V1_03;
+
+/// Every supported version, oldest first
+pub const FORMAT_VERSIONS: &[&dyn FormatVersion] = &[&V1_03];
+
+/// Find the layout of a version by its version number
+pub fn format_version(version: &str) -> Option<&'static dyn FormatVersion> {
+    return FORMAT_VERSIONS.iter().copied().find(|format| format.version() == version);
+}
+
+impl FormatVersion for V1_03 {
+    fn version(self: &Self) -> &'static str {
+        "1.03"
+    }
+
+    fn decode_record(self: &Self, data: &[u8; RECORD_LEN], name: String) -> CityEntry {
+        return decode_city_record(data, name);
+    }
+
+    fn write_record(self: &Self, writer: &mut dyn Write, city: &CityEntry) -> Result<(), io::Error> {
+        let fields = [("country", city.country_ix.0, 0xff), ("region", city.region_ix.0, 0xfff), ("sub-region", city.subregion_ix.0, 0xffff),
+            ("timezone", city.timezone_ix.0, 0x1ff), ("feature", city.feature_ix.0, 0x3f)];
+        if let Some((field, ix, _)) = fields.iter().find(|(_, ix, max)| ix > max) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("{} has a {field} index, {ix}, too large for version 1.03", city.name)));
+        }
+        return write_city_entry(writer, city);
+    }
+
+    fn has_feature_table(self: &Self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use super::{FormatVersion, V1_03};
+    use crate::{CityEntry, CountryId, FeatureId, RegionId, SubregionId, TimezoneId};
+
+    fn sample(subregion_ix: usize, timezone_ix: usize) -> CityEntry {
+        CityEntry {
+            name: String::from("Nuuk"), latitude: 64.18347, longitude: -51.72157, population: 0x114,
+            country_ix: CountryId(3), region_ix: RegionId(0xabc), subregion_ix: SubregionId(subregion_ix),
+            timezone_ix: TimezoneId(timezone_ix), feature_ix: FeatureId(5), reserved: 0x40
+        }
+    }
+
+    fn round_trip(format: &dyn FormatVersion, city: &CityEntry) -> CityEntry {
+        let mut bytes = vec![];
+        format.write_record(&mut bytes, city).unwrap();
+        assert_eq!(&bytes[13..], b"Nuuk\n");
+        return format.decode_record(bytes[..13].try_into().unwrap(), city.name.clone());
+    }
+
+    #[test]
+    fn v1_03_record_layout() {
+        let city = sample(0xffff, 0x1a2);
+        let read = round_trip(&V1_03, &city);
+        assert_eq!(read, CityEntry { latitude: read.latitude, longitude: read.longitude, ..city });
+
+        for city in [sample(0x10000, 0), sample(0, 0x200), CityEntry { feature_ix: FeatureId(0x40), ..sample(0, 0) }] {
+            let err = V1_03.write_record(&mut vec![], &city).expect_err("index can't be written");
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput, "{err}");
+        }
+    }
+}
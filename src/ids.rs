@@ -0,0 +1,54 @@
+//! Typed indices into the tables of a `GeoDatabase`, so that e.g. a region index can't be passed where a
+//! sub-region index is expected.
+
+use std::fmt;
+
+macro_rules! table_id {
+    ($(#[$meta:meta])* $name:ident) => {
+        $(#[$meta])*
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+        #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(transparent))]
+        pub struct $name(pub usize);
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "{}", self.0)
+            }
+        }
+    };
+}
+
+table_id!(
+    /// Index of a city
+    CityId
+);
+table_id!(
+    /// Index into the country table
+    CountryId
+);
+table_id!(
+    /// Index into the region table
+    RegionId
+);
+table_id!(
+    /// Index into the sub-region table
+    SubregionId
+);
+table_id!(
+    /// Index into the timezone table
+    TimezoneId
+);
+table_id!(
+    /// Index into the feature table
+    FeatureId
+);
+
+/// Any entry of a database with a name that can be looked up
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Place {
+    City(CityId),
+    Subregion(SubregionId),
+    Region(RegionId),
+    Country(CountryId)
+}
@@ -0,0 +1,356 @@
+//! A minimal JSON writer for the command line tool's structured output
+
+use std::fmt::Write;
+use exif_geolocation_util::{format_date_time, format_population, CityInfo, CoordinateFormat, CountrySummary, DatabaseSummary, Place, RegionSummary, RoundTripDifference, SubregionSummary, Suggestion, TimezoneId, TimezoneIssue, TimezoneMismatch, TimezoneProblem, UtcOffset};
+
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(&'static str, Json)>)
+}
+
+impl From<&str> for Json {
+    fn from(value: &str) -> Self {
+        Json::String(value.to_owned())
+    }
+}
+
+impl From<String> for Json {
+    fn from(value: String) -> Self {
+        Json::String(value)
+    }
+}
+
+impl From<usize> for Json {
+    fn from(value: usize) -> Self {
+        Json::Number(value as f64)
+    }
+}
+
+impl From<f64> for Json {
+    fn from(value: f64) -> Self {
+        Json::Number(value)
+    }
+}
+
+impl From<bool> for Json {
+    fn from(value: bool) -> Self {
+        Json::Bool(value)
+    }
+}
+
+impl<T: Into<Json>> From<Option<T>> for Json {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Json::Null, Into::into)
+    }
+}
+
+impl Json {
+    /// Serialise on a single line, as used for newline delimited JSON
+    pub fn to_compact_string(self: &Self) -> String {
+        let mut out = String::new();
+        self.write(&mut out, None);
+        return out;
+    }
+
+    /// Serialise across multiple lines with two space indentation
+    pub fn to_pretty_string(self: &Self) -> String {
+        let mut out = String::new();
+        self.write(&mut out, Some(0));
+        return out;
+    }
+
+    fn write(self: &Self, out: &mut String, indent: Option<usize>) {
+        let newline = |out: &mut String, depth: usize| {
+            if indent.is_some() {
+                out.push('\n');
+                out.push_str(&"  ".repeat(depth));
+            }
+        };
+        let depth = indent.unwrap_or(0);
+        let inner = indent.map(|depth| depth + 1);
+        match self {
+            Json::Null => out.push_str("null"),
+            Json::Bool(value) => out.push_str(if *value { "true" } else { "false" }),
+            Json::Number(value) => {
+                if value.is_finite() {
+                    write!(out, "{}", value).expect("writing to a string cannot fail");
+                } else {
+                    out.push_str("null");
+                }
+            },
+            Json::String(value) => write_string(out, value),
+            Json::Array(items) => {
+                out.push('[');
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    newline(out, depth + 1);
+                    item.write(out, inner);
+                }
+                if !items.is_empty() {
+                    newline(out, depth);
+                }
+                out.push(']');
+            },
+            Json::Object(fields) => {
+                out.push('{');
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    newline(out, depth + 1);
+                    write_string(out, key);
+                    out.push(':');
+                    if indent.is_some() {
+                        out.push(' ');
+                    }
+                    value.write(out, inner);
+                }
+                if !fields.is_empty() {
+                    newline(out, depth);
+                }
+                out.push('}');
+            }
+        }
+    }
+}
+
+fn write_string(out: &mut String, value: &str) {
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32).expect("writing to a string cannot fail"),
+            c => out.push(c)
+        }
+    }
+    out.push('"');
+}
+
+pub fn database_summary(summary: &DatabaseSummary) -> Json {
+    Json::Object(vec![
+        ("comment", summary.comment.as_str().into()),
+        ("cities", summary.n_cities.into()),
+        ("countries", summary.n_countries.into()),
+        ("regions", summary.n_regions.into()),
+        ("subregions", summary.n_subregions.into()),
+        ("timezones", summary.n_timezones.into()),
+        ("features", summary.n_features.into()),
+    ])
+}
+
+pub fn city_info(city: &CityInfo, coord_format: &CoordinateFormat) -> Json {
+    Json::Object(vec![
+        ("index", city.city_ix.0.into()),
+        ("name", city.name.as_str().into()),
+        ("latitude", city.latitude.into()),
+        ("longitude", city.longitude.into()),
+        ("position", coord_format.format_position(city.latitude, city.longitude).into()),
+        ("subregion", city.subregion.as_str().into()),
+        ("region", city.region.as_str().into()),
+        ("region_code", city.region_code.as_deref().into()),
+        ("country", city.country.as_str().into()),
+        ("country_code", city.country_code.as_str().into()),
+        ("timezone", city.timezone.as_str().into()),
+        ("population", format_population(city.population).into()),
+        ("feature", city.feature.as_str().into()),
+    ])
+}
+
+pub fn subregion_summary(summary: &SubregionSummary) -> Json {
+    Json::Object(vec![
+        ("index", summary.subregion_ix.0.into()),
+        ("name", summary.name.as_str().into()),
+        ("region", summary.region.as_str().into()),
+        ("country", summary.country.as_str().into()),
+        ("country_code", summary.country_code.as_str().into()),
+        ("cities", summary.n_cities.into()),
+        ("timezones", summary.n_timezones.into()),
+    ])
+}
+
+pub fn region_summary(summary: &RegionSummary) -> Json {
+    Json::Object(vec![
+        ("index", summary.region_ix.0.into()),
+        ("name", summary.name.as_str().into()),
+        ("code", summary.code.as_deref().into()),
+        ("country", summary.country.as_str().into()),
+        ("country_code", summary.country_code.as_str().into()),
+        ("cities", summary.n_cities.into()),
+        ("subregions", summary.n_subregions.into()),
+        ("timezones", summary.n_timezones.into()),
+    ])
+}
+
+pub fn country_summary(summary: &CountrySummary) -> Json {
+    Json::Object(vec![
+        ("index", summary.country_ix.0.into()),
+        ("name", summary.name.as_str().into()),
+        ("code", summary.code.as_str().into()),
+        ("cities", summary.n_cities.into()),
+        ("subregions", summary.n_subregions.into()),
+        ("regions", summary.n_regions.into()),
+        ("timezones", summary.n_timezones.into()),
+    ])
+}
+
+pub fn suggestion(suggestion: &Suggestion, kind: &str) -> Json {
+    let index = match suggestion.place {
+        Place::City(ix) => ix.0,
+        Place::Subregion(ix) => ix.0,
+        Place::Region(ix) => ix.0,
+        Place::Country(ix) => ix.0
+    };
+    Json::Object(vec![
+        ("type", kind.into()),
+        ("index", index.into()),
+        ("name", suggestion.name.as_str().into()),
+        ("population", suggestion.population.into()),
+    ])
+}
+
+/// A result of a fuzzy search, with its similarity added as "score"
+pub fn scored(mut value: Json, score: f64) -> Json {
+    if let Json::Object(fields) = &mut value {
+        fields.push(("score", score.into()));
+    }
+    return value;
+}
+
+pub fn round_trip_difference(difference: Option<&RoundTripDifference>) -> Json {
+    let Some(difference) = difference else {
+        return Json::Object(vec![("identical", true.into())]);
+    };
+    Json::Object(vec![
+        ("identical", false.into()),
+        ("offset", (difference.offset as usize).into()),
+        ("original_length", (difference.original_len as usize).into()),
+        ("rewritten_length", (difference.rewritten_len as usize).into()),
+    ])
+}
+
+pub fn timezone_issue(issue: &TimezoneIssue) -> Json {
+    let (problem, replacement) = match &issue.problem {
+        TimezoneProblem::Unknown { suggestion } => ("unknown", suggestion.as_deref()),
+        TimezoneProblem::Deprecated { replacement } => ("deprecated", Some(replacement.as_str()))
+    };
+    Json::Object(vec![
+        ("index", issue.timezone_ix.0.into()),
+        ("name", issue.name.as_str().into()),
+        ("problem", problem.into()),
+        ("replacement", replacement.into()),
+        ("cities", issue.n_cities.into()),
+    ])
+}
+
+/// The offset of a city's timezone at a time, `local` is seconds since 1970 local time
+pub fn utc_offset(city: &CityInfo, zone: &str, local: i64, offset: &UtcOffset) -> Json {
+    Json::Object(vec![
+        ("index", city.city_ix.0.into()),
+        ("name", city.name.as_str().into()),
+        ("timezone", zone.into()),
+        ("local_time", format_date_time(local).replace(' ', "T").into()),
+        ("utc_time", format!("{}Z", format_date_time(local - i64::from(offset.seconds)).replace(' ', "T")).into()),
+        ("utc_offset", offset.to_offset_string().into()),
+        ("offset_seconds", (offset.seconds as f64).into()),
+        ("abbreviation", offset.abbreviation.as_str().into()),
+        ("dst", offset.is_dst.into()),
+    ])
+}
+
+/// A city found by the timezone audit, `timezone_name` resolves the majority timezone
+pub fn timezone_finding<'a>(city: &CityInfo, mismatch: &TimezoneMismatch, timezone_name: impl Fn(TimezoneId) -> &'a str) -> Json {
+    let mut fields = vec![
+        ("index", city.city_ix.0.into()),
+        ("name", city.name.as_str().into()),
+        ("timezone", city.timezone.as_str().into()),
+        ("country_code", city.country_code.as_str().into()),
+    ];
+    match mismatch {
+        TimezoneMismatch::Neighbours { majority, agreeing, compared } | TimezoneMismatch::Subregion { majority, agreeing, compared } => {
+            let check = if matches!(mismatch, TimezoneMismatch::Neighbours { .. }) { "neighbours" } else { "subregion" };
+            fields.push(("check", check.into()));
+            fields.push(("majority", timezone_name(*majority).into()));
+            fields.push(("agreeing", (*agreeing).into()));
+            fields.push(("compared", (*compared).into()));
+        },
+        TimezoneMismatch::Country { zone_countries } => {
+            fields.push(("check", "country".into()));
+            fields.push(("zone_countries", Json::Array(zone_countries.iter().map(|code| code.as_str().into()).collect())));
+        }
+    }
+    Json::Object(fields)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{write_string, Json};
+
+    fn escaped(value: &str) -> String {
+        let mut out = String::new();
+        write_string(&mut out, value);
+        return out;
+    }
+
+    #[test]
+    fn strings_are_escaped() {
+        assert_eq!(escaped("plain São Paulo"), "\"plain São Paulo\"");
+        assert_eq!(escaped("say \"hi\" \\ bye"), r#""say \"hi\" \\ bye""#);
+        assert_eq!(escaped("a\nb\rc\td"), r#""a\nb\rc\td""#);
+        assert_eq!(escaped("\0\u{1}\u{1f} \u{7f}"), "\"\\u0000\\u0001\\u001f \u{7f}\"");
+    }
+
+    #[test]
+    fn values() {
+        assert_eq!(Json::Number(1.5).to_compact_string(), "1.5");
+        assert_eq!(Json::Number(-3.0).to_compact_string(), "-3");
+        assert_eq!(Json::Number(f64::NAN).to_compact_string(), "null");
+        assert_eq!(Json::Number(f64::INFINITY).to_compact_string(), "null");
+        assert_eq!(Json::Number(f64::NEG_INFINITY).to_pretty_string(), "null");
+        assert_eq!(Json::from(None::<&str>).to_compact_string(), "null");
+        assert_eq!(Json::from(true).to_compact_string(), "true");
+        assert_eq!(Json::Array(vec![]).to_compact_string(), "[]");
+        assert_eq!(Json::Array(vec![]).to_pretty_string(), "[]");
+        assert_eq!(Json::Object(vec![]).to_compact_string(), "{}");
+        assert_eq!(Json::Object(vec![]).to_pretty_string(), "{}");
+    }
+
+    #[test]
+    fn compact_and_pretty_output() {
+        let value = Json::Object(vec![
+            ("name", "Nuuk".into()),
+            ("population", 17036_usize.into()),
+            ("capital", true.into()),
+            ("codes", Json::Array(vec!["GL".into(), "GRL".into()])),
+            ("empty", Json::Array(vec![])),
+            ("region", Json::Object(vec![("name", "Sermersooq".into()), ("code", Json::Null)]))
+        ]);
+        assert_eq!(value.to_compact_string(),
+            r#"{"name":"Nuuk","population":17036,"capital":true,"codes":["GL","GRL"],"empty":[],"region":{"name":"Sermersooq","code":null}}"#);
+        assert_eq!(value.to_pretty_string(), concat!(
+            "{\n",
+            "  \"name\": \"Nuuk\",\n",
+            "  \"population\": 17036,\n",
+            "  \"capital\": true,\n",
+            "  \"codes\": [\n",
+            "    \"GL\",\n",
+            "    \"GRL\"\n",
+            "  ],\n",
+            "  \"empty\": [],\n",
+            "  \"region\": {\n",
+            "    \"name\": \"Sermersooq\",\n",
+            "    \"code\": null\n",
+            "  }\n",
+            "}"
+        ));
+    }
+}
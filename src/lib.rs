@@ -1,23 +1,78 @@
-use std::{collections::HashSet, io::{self, BufRead, Write}};
+#![allow(clippy::needless_return, clippy::needless_arbitrary_self_type, clippy::needless_lifetimes)]
+
+use std::{collections::HashSet, fmt, io::{self, BufRead, Write}};
 use byteorder::{NetworkEndian, ReadBytesExt, WriteBytesExt};
 use regex::Regex;
 
+mod autocomplete;
+mod builder;
+mod compression;
+mod country;
+mod database_ref;
+mod filter;
+mod format;
+mod ids;
+mod position;
+mod reader;
+mod region_codes;
+mod search;
+#[cfg(feature = "serde")]
+mod serde_support;
+mod timezone;
+mod timezone_audit;
+mod tzdata;
+mod verify;
+mod view;
+
+pub use autocomplete::{AutocompleteIndex, Suggestion};
+pub use builder::{DatabaseBuildError, GeoDatabaseBuilder, NewCity};
+pub use compression::Compression;
+pub use country::{is_country_code, CountryCodes};
+#[cfg(feature = "iso3166")]
+pub use country::iso3166_country;
+pub use database_ref::GeoDatabaseRef;
+#[cfg(feature = "mmap")]
+pub use database_ref::MappedDatabase;
+pub use filter::{distance_km, BoundingBox, CityFilter, SortOrder};
+pub use format::{format_version, FormatVersion, CURRENT_VERSION, FORMAT_VERSIONS, V1_03};
+pub use ids::{CityId, CountryId, FeatureId, Place, RegionId, SubregionId, TimezoneId};
+pub use position::{parse_pos_string, CoordinateFormat, CoordinateNotation, PositionParseError};
+pub use reader::{ErrorLocation, Section};
+pub use region_codes::{RegionCode, RegionCodeError, RegionCodeImport, UnmatchedRegionCode};
+pub use search::{fold_name, similarity, PatternError, PatternSyntax, ScoredMatch, SearchOptions, DEFAULT_MIN_SIMILARITY};
+pub use timezone::{default_zoneinfo_dir, format_date_time, parse_timestamp, LocalOffset, TimeZone, TimeZoneError, Timestamp, UtcOffset};
+pub use timezone_audit::{TimezoneAuditFinding, TimezoneMismatch};
+pub use tzdata::{TimezoneIssue, TimezoneProblem, TzData, TzDataError, ZoneName};
+pub use verify::{verify_round_trip, RoundTripDifference};
+pub use view::{CityView, CountryView};
+
+use search::{best_first, exact_first, split_query, SearchTerm};
+
 fn read_line(reader :&mut dyn BufRead) -> Result<String, io::Error> {
     let mut s = String::new();
     reader.read_line(&mut s)?;
-    return Ok(s.trim_end().to_owned());
+    if s.ends_with('\n') {
+        s.pop();
+    }
+    return Ok(s);
 }
 
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct CityEntry {
     pub name: String,
     pub latitude: f64,
     pub longitude: f64,
     pub population: u16,
-    pub country_ix: usize,
-    pub region_ix: usize,
-    pub subregion_ix: usize,
-    pub timezone_ix: usize,
-    pub feature_ix: usize
+    pub country_ix: CountryId,
+    pub region_ix: RegionId,
+    pub subregion_ix: SubregionId,
+    pub timezone_ix: TimezoneId,
+    pub feature_ix: FeatureId,
+    /// Bits of the record which are unused by the format version it was read from, in their positions in the
+    /// record's last byte, kept so that the record is written back unchanged
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub reserved: u8
 }
 
 pub fn write_city_entry(writer: &mut dyn Write, city: &CityEntry) -> Result<(), io::Error> {
@@ -27,10 +82,10 @@ pub fn write_city_entry(writer: &mut dyn Write, city: &CityEntry) -> Result<(),
     let lt = (lat >> 4) as u16;
     let f = ( ((lat & 0x0f) << 4) | (long & 0x0f) )as u8;
     let ln = (long >> 4) as u16;
-    let code = ((city.country_ix as u32) << 24) | ((city.population as u32) << 12) | (city.region_ix as u32);
-    let sn = city.subregion_ix as u16;
-    let tn = (city.timezone_ix & 0xff) as u8;
-    let ftn = (((city.timezone_ix & 0x100) as u8) << 7) | (city.feature_ix as u8);
+    let code = ((city.country_ix.0 as u32) << 24) | ((city.population as u32) << 12) | (city.region_ix.0 as u32);
+    let sn = city.subregion_ix.0 as u16;
+    let tn = (city.timezone_ix.0 & 0xff) as u8;
+    let ftn = ((((city.timezone_ix.0 >> 8) & 1) as u8) << 7) | (city.reserved & 0x40) | (city.feature_ix.0 as u8 & 0x3f);
 
     writer.write_u16::<NetworkEndian>(lt)?;
     writer.write_u8(f)?;
@@ -44,7 +99,7 @@ pub fn write_city_entry(writer: &mut dyn Write, city: &CityEntry) -> Result<(),
     Ok(())
 }
 
-/// Format is, I believe, as follows:
+/// Version 1.03 format is, I believe, as follows:
 /// * 20 bit latitute
 /// * 20 bit longitude
 /// * 8 bit country index
@@ -56,6 +111,12 @@ pub fn write_city_entry(writer: &mut dyn Write, city: &CityEntry) -> Result<(),
 /// * 6 bit feature code index 
 /// * newline terminated string name
 pub fn parse_city_entry(data: &[u8;13], reader: &mut dyn BufRead) -> Result<CityEntry, io::Error> {
+    let name = read_line(reader)?;
+    Ok(decode_city_record(data, name))
+}
+
+/// Decode the fixed size part of a city record, see `parse_city_entry`
+fn decode_city_record(data: &[u8;13], name: String) -> CityEntry {
     let mut data_reader = io::Cursor::new(data);
 
     let lt = u32::from(data_reader.read_u16::<NetworkEndian>().unwrap());
@@ -66,32 +127,35 @@ pub fn parse_city_entry(data: &[u8;13], reader: &mut dyn BufRead) -> Result<City
     let tn = u16::from(data_reader.read_u8().unwrap());
     let ftn = u32::from(data_reader.read_u8().unwrap());
 
-    let name = read_line(reader)?;
-
     let lat = (lt << 4) | (f >> 4);
     let long = (ln << 4) | (f & 0x0f);
 
     let lat_deg = (180.0 * (f64::from(lat) / f64::from(0x100000))) - 90.0;
     let long_deg = (360.0 * (f64::from(long) / f64::from(0x100000))) - 180.0;
 
-    let country_ix = (code >> 24) as usize;
+    let country_ix = CountryId((code >> 24) as usize);
 
     let pop = (code >> 12 & 0xfff) as u16;
 
-    let region_ix = (code & 0xfff) as usize;
+    let region_ix = RegionId((code & 0xfff) as usize);
 
-    let subregion_ix = sn as usize;
-    let timezone_ix = if (ftn & 0x80) != 0 {
+    let subregion_ix = SubregionId(sn as usize);
+    let timezone_ix = TimezoneId(if (ftn & 0x80) != 0 {
         tn + 256
     } else {
         tn
-    } as usize;
+    } as usize);
 
-    let feature_ix = (ftn & 0x3f) as usize;
+    let feature_ix = FeatureId((ftn & 0x3f) as usize);
+    let reserved = (ftn & 0x40) as u8;
 
-    Ok(CityEntry{ name, latitude: lat_deg, longitude: long_deg, population: pop, country_ix, region_ix, subregion_ix, timezone_ix, feature_ix })
+    CityEntry{ name, latitude: lat_deg, longitude: long_deg, population: pop, country_ix, region_ix, subregion_ix, timezone_ix, feature_ix, reserved }
 }
 
+/// With the `serde` feature the tables are serialised as stored, with each country entry holding its two
+/// letter code followed by its name. Deserialising checks every city refers to entries which exist.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "serde_support::GeoDatabaseTables"))]
 pub struct GeoDatabase {
     comment:String,
     cities:Vec<CityEntry>,
@@ -99,103 +163,13 @@ pub struct GeoDatabase {
     regions:Vec<String>,
     subregions:Vec<String>,
     timezones:Vec<String>,
-    features:Vec<String>
-}
-
-fn dd_to_dms(dd:f64, if_pos:char, if_neg:char) -> String {
-    let d = dd.trunc().abs() as i64;
-    let m = dd.fract().abs() * 60.0;
-    let s = m.fract() * 60.0;
-    return format!("{}°{}'{:.2}\"{}", d, m.trunc() as i8, s, if dd >= 0.0 { if_pos } else { if_neg });
-}
-
-fn dd_string_to_dd(sign: &str, deg: &str, dir: &str, max_abs: f64) -> Result<f64, &'static str> {
-    let dd = deg.parse::<f64>().or(Err("degrees not a valid decimal number"))?;
-    return parse_dd(sign, dd, dir, max_abs);
-}
-
-fn dm_string_to_dd(sign: &str, deg: &str, min: &str, dir: &str, max_abs: f64) -> Result<f64, &'static str> {
-    let deg = deg.parse::<i32>().or(Err("degrees not a valid integer"))?;
-    let min = min.parse::<f64>().or(Err("minutes not a valid decimal number"))?;
-    if min < 0.0 || min >= 60.0 {
-        return Err("minutes must be between 0 inclusive and 60 exclusive");
-    }
-    return parse_dd(sign, f64::from(deg) + (min / 60.0), dir, max_abs);
-}
-
-fn dms_string_to_dd(sign: &str, deg: &str, min: &str, sec: &str, dir: &str, max_abs: f64) -> Result<f64, &'static str> {
-    let deg = deg.parse::<i32>().or(Err("degrees not a valid integer"))?;
-    let min = min.parse::<i32>().or(Err("minutes not a valid integer"))?;
-    if min < 0 || min >= 60 {
-        return Err("minutes must be between 0 inclusive and 60 exclusive");
-    }
-    let sec = sec.parse::<f64>().or(Err("Seconds not a valid decimal number"))?;
-    if sec < 0.0 || sec >= 60.0 {
-        return Err("seconds must be between 0 inclusive and 60 exclusive");
-    }
-    return parse_dd(sign, f64::from(deg) + ((f64::from(min) + (sec / 60.0)) / 60.0), dir, max_abs);
-}
-
-fn parse_dd(sign: &str, dd: f64, dir: &str, max_abs: f64) -> Result<f64, &'static str> {
-    let mut dd = dd;
-    if sign == "-" {
-        if dir == "S" || dir == "W" {
-            return Err("a negative angle south or west is likely a mistake so it is disallowed")
-        }
-        dd = -dd;
-    } else if dir == "S" || dir == "W" {
-        dd = -dd;
-    }
-    if dd > max_abs {
-        return Err("angle cannot be greater than {max_abs:.1}");
-    }
-    if dd < -max_abs {
-        return Err("angle cannot be less than than -{max_abs:.1}");
-    }
-    return Ok(dd);
-}
-
-pub fn parse_pos_string(dms: &str) -> Result<(f64, f64), &'static str> {
-    // decimal degrees
-    let regex_dd_part = r#"(-?)([\d]+(?:.[\d]+)?)[\s]*(?:°|d|deg|)?"#;
-    let regex_dd = Regex::new(&format!(r#"^{}[\s]*(N|S|)[\s,]*{}[\s]*(E|W|)$"#, regex_dd_part, regex_dd_part)).expect("invalid regex pattern");
-    if let Some(captures) = regex_dd.captures(dms) {
-        let (_, [
-            lat_sign, lat_str, lat_dir,
-            long_sign, long_str, long_dir,
-        ]) = captures.extract();
-        let lat = dd_string_to_dd(lat_sign, lat_str, lat_dir, 90.0)?;
-        let long = dd_string_to_dd(long_sign, long_str, long_dir, 180.0)?;
-        return Ok((lat, long));
-    }
-
-    // degree, minutes
-    let regex_dm_part = r#"(-?)([\d]+)[\s]*(?:°|d|deg)[\s]*([\d]+(?:.[\d]+)?)(?:'|\u2018|\u2019|m|min)"#;
-    let regex_dm = Regex::new(&format!(r#"^{}[\s]*(N|S)[\s,]*{}[\s]*(E|W)$"#, regex_dm_part, regex_dm_part)).expect("invalid regex pattern");
-    if let Some(captures) = regex_dm.captures(dms) {
-        let (_, [
-            lat_sign, lat_deg, lat_min, lat_dir,
-            long_sign, long_deg, long_min, long_dir,
-        ]) = captures.extract();
-        let lat = dm_string_to_dd(lat_sign, lat_deg, lat_min, lat_dir, 90.0)?;
-        let long = dm_string_to_dd(long_sign, long_deg, long_min, long_dir, 180.0)?;
-        return Ok((lat, long))
-    }
-
-    // degree, minutes, seconds
-    let regex_dms_part = r#"(-?)([\d]+)[\s]*(?:°|d|deg)[\s]*([\d]+)[\s]*(?:'|\u2018|\u2019|m|min)[\s]*([\d]+(?:.[\d]+)?)(?:"|\u201C|\u201D|s|sec)"#;
-    let regex_dms = Regex::new(&format!(r#"^{}[\s]*(N|S)[\s,]*{}[\s]*(E|W)$"#, regex_dms_part, regex_dms_part)).expect("invalid regex pattern");
-    if let Some(captures) = regex_dms.captures(dms) {
-        let (_, [
-            lat_sign, lat_deg, lat_min, lat_sec, lat_dir,
-            long_sign, long_deg, long_min, long_sec, long_dir,
-        ]) = captures.extract();
-        let lat = dms_string_to_dd(lat_sign, lat_deg, lat_min, lat_sec, lat_dir, 90.0)?;
-        let long = dms_string_to_dd(long_sign, long_deg, long_min, long_sec, long_dir, 180.0)?;
-        return Ok((lat, long))
-    }
-
-    return Err("parse error, expected in format \"<deg>°<min>'<sec>\"<N|S>, <deg>°<min>'<sec>\"<E|W>\"");
+    features:Vec<String>,
+    /// Sections after the features which aren't understood, kept so they are written back unchanged
+    #[cfg_attr(feature = "serde", serde(default))]
+    extra_sections:Vec<u8>,
+    /// ISO 3166-2 codes of regions, these aren't written to the database file
+    #[cfg_attr(feature = "serde", serde(default))]
+    region_codes:Vec<RegionCode>
 }
 
 pub fn parse_population_string(s: &str) -> Result<u16, &'static str> {
@@ -220,7 +194,7 @@ pub fn parse_population_string(s: &str) -> Result<u16, &'static str> {
                     return Err("significand must be between 0-15 inclusive");
                 }
 
-                return Ok( ( ((integer & 0x0f) << 8) | ((decimal & 0x0f) << 4) | (significand & 0x0f) ) as u16 );
+                return Ok( ((integer & 0x0f) << 8) | ((decimal & 0x0f) << 4) | (significand & 0x0f) );
             }
             None => Err("parse error, expected in format \"<whole>.<decimal>e+<significand>\"")
         }
@@ -234,265 +208,636 @@ pub fn format_population(pop: u16) -> String {
     }
 }
 
-fn format_position(lat_dd: f64, long_dd: f64) -> String {
-    format!("{}, {}", dd_to_dms(lat_dd, 'N', 'S'), dd_to_dms(long_dd, 'E', 'W'))
+/// The number a packed population stands for, e.g. 0x215 (2.1e+5) is 210000
+pub fn population_value(pop: u16) -> f64 {
+    return ((pop >> 8) as f64 + (pop >> 4 & 0x0f) as f64 / 10.0) * 10f64.powi(i32::from(pop & 0x0f));
+}
+
+/// Counts of each type of entry in a database
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DatabaseSummary {
+    pub comment: String,
+    pub n_cities: usize,
+    pub n_countries: usize,
+    pub n_regions: usize,
+    pub n_subregions: usize,
+    pub n_timezones: usize,
+    pub n_features: usize
+}
+
+/// A city with its sub-region, region, country, timezone and feature resolved to names
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CityInfo {
+    pub city_ix: CityId,
+    pub name: String,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub population: u16,
+    pub subregion: String,
+    pub region: String,
+    /// The region's ISO 3166-2 code, if one has been imported
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub region_code: Option<String>,
+    pub country: String,
+    pub country_code: String,
+    pub timezone: String,
+    pub feature: String
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SubregionSummary {
+    pub subregion_ix: SubregionId,
+    pub name: String,
+    pub region: String,
+    pub country: String,
+    pub country_code: String,
+    pub n_cities: usize,
+    pub n_timezones: usize
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RegionSummary {
+    pub region_ix: RegionId,
+    pub name: String,
+    /// The ISO 3166-2 code, if one has been imported
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub code: Option<String>,
+    pub country: String,
+    pub country_code: String,
+    pub n_cities: usize,
+    pub n_subregions: usize,
+    pub n_timezones: usize
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CountrySummary {
+    pub country_ix: CountryId,
+    pub name: String,
+    pub code: String,
+    pub n_cities: usize,
+    pub n_subregions: usize,
+    pub n_regions: usize,
+    pub n_timezones: usize
+}
+
+/// A city search string, the name optionally followed by the sub-region, region and country separated by
+/// commas, where the qualifiers are parsed last to first
+struct CityQuery<'q> {
+    name: SearchTerm<'q>,
+    subregion: Option<SearchTerm<'q>>,
+    region: Option<SearchTerm<'q>>,
+    country: Option<SearchTerm<'q>>
+}
+
+impl<'q> CityQuery<'q> {
+    fn parse(query: &'q str, options: &SearchOptions) -> Result<CityQuery<'q>, PatternError> {
+        let name_parts = split_query(query, options.syntax, 4)?;
+        let (name, subregion, region, country) = match name_parts.len() {
+            1 => (name_parts[0], None, None, None),
+            2 => (name_parts[0].trim(), None, None, Some(name_parts[1].trim())),
+            3 => (name_parts[0].trim(), None, Some(name_parts[1].trim()), Some(name_parts[2].trim())),
+            _ => (name_parts[0].trim(), Some(name_parts[1].trim()), Some(name_parts[2].trim()), Some(name_parts[3].trim()))
+        };
+        let term = |text| SearchTerm::new(text, options);
+        return Ok(CityQuery { name: term(name)?, subregion: subregion.map(term).transpose()?, region: region.map(term).transpose()?, country: country.map(term).transpose()? });
+    }
+
+    /// `country` is the country table entry, so the qualifier can be either the code or the name
+    /// and the region qualifier can be either the region's ISO 3166-2 code, if it has one, or its name
+    fn matches(self: &Self, name: &str, subregion: &str, region: &str, region_code: Option<&str>, country: &str) -> bool {
+        return self.name.equals(name) && self.qualifiers_match(subregion, region, region_code, country);
+    }
+
+    fn qualifiers_match(self: &Self, subregion: &str, region: &str, region_code: Option<&str>, country: &str) -> bool {
+        return self.country.as_ref().is_none_or(|q| q.matches_country(country))
+            && self.region.as_ref().is_none_or(|q| q.contained_in(region) || q.is_code(region_code))
+            && self.subregion.as_ref().is_none_or(|q| q.contained_in(subregion));
+    }
+}
+
+/// A sub-region search string, the name optionally followed by the region and country
+fn parse_subregion_query<'q>(query: &'q str, options: &SearchOptions) -> Result<(SearchTerm<'q>, Option<SearchTerm<'q>>, Option<SearchTerm<'q>>), PatternError> {
+    let name_parts = split_query(query, options.syntax, 3)?;
+    let (name, region, country) = match name_parts.len() {
+        1 => (name_parts[0], None, None),
+        2 => (name_parts[0].trim(), None, Some(name_parts[1].trim())),
+        _ => (name_parts[0].trim(), Some(name_parts[1].trim()), Some(name_parts[2].trim()))
+    };
+    let term = |text| SearchTerm::new(text, options);
+    return Ok((term(name)?, region.map(term).transpose()?, country.map(term).transpose()?));
+}
+
+/// A region search string, the name optionally followed by the country
+fn parse_region_query<'q>(query: &'q str, options: &SearchOptions) -> Result<(SearchTerm<'q>, Option<SearchTerm<'q>>), PatternError> {
+    let name_parts = split_query(query, options.syntax, 2)?;
+    let (name, country) = match name_parts.len() {
+        1 => (name_parts[0], None),
+        _ => (name_parts[0].trim(), Some(name_parts[1].trim()))
+    };
+    return Ok((SearchTerm::new(name, options)?, country.map(|country| SearchTerm::new(country, options)).transpose()?));
+}
+
+fn plural<'a>(n: usize, singular: &'a str, plural: &'a str) -> &'a str {
+    return if n == 1 { singular } else { plural };
 }
 
 impl GeoDatabase {
-    pub fn print_info(self: &GeoDatabase) {
-        println!("Comment: {}", self.comment);
-        println!("{} cities", self.cities.len());
-        println!("{} countries", self.countries.len());
-        println!("{} regions", self.regions.len());
-        println!("{} subregions", self.subregions.len());
-        println!("{} timezones", self.timezones.len());
-        println!("{} features", self.features.len());
+    pub fn summary(self: &Self) -> DatabaseSummary {
+        DatabaseSummary {
+            comment: self.comment.clone(),
+            n_cities: self.cities.len(),
+            n_countries: self.countries.len(),
+            n_regions: self.regions.len(),
+            n_subregions: self.subregions.len(),
+            n_timezones: self.timezones.len(),
+            n_features: self.features.len()
+        }
+    }
+
+    pub fn write_info(self: &Self, writer: &mut dyn Write) -> Result<(), io::Error> {
+        let summary = self.summary();
+        writeln!(writer, "Comment: {}", summary.comment)?;
+        writeln!(writer, "{} cities", summary.n_cities)?;
+        writeln!(writer, "{} countries", summary.n_countries)?;
+        writeln!(writer, "{} regions", summary.n_regions)?;
+        writeln!(writer, "{} subregions", summary.n_subregions)?;
+        writeln!(writer, "{} timezones", summary.n_timezones)?;
+        writeln!(writer, "{} features", summary.n_features)?;
+        Ok(())
     }
 
-    pub fn print_city_info(self: &Self, city_ix: usize) {
-        let city = &self.cities[city_ix];
-        println!("{}, {}, {}, {}", city.name, self.subregions[city.subregion_ix], self.regions[city.region_ix], self.country_name(city.country_ix));
-        println!("{}", format_position(city.latitude, city.longitude));
-        println!("Timezone: {}, Population: {}", self.timezones[city.timezone_ix], format_population(city.population));
-        println!("{}", self.features[city.feature_ix]);
+    pub fn print_info(self: &Self) {
+        self.write_info(&mut io::stdout()).expect("failed to write to stdout");
     }
 
-    pub fn print_subregion_info(self: &Self, subregion_ix: usize) {
+    pub fn city_info(self: &Self, city_ix: CityId) -> CityInfo {
+        return self.city(city_ix).to_info();
+    }
+
+    pub fn write_city_info(self: &Self, writer: &mut dyn Write, city_ix: CityId, coord_format: &CoordinateFormat) -> Result<(), io::Error> {
+        return write_city_details(writer, &self.city_info(city_ix), coord_format);
+    }
+
+    pub fn print_city_info(self: &Self, city_ix: CityId, coord_format: &CoordinateFormat) {
+        self.write_city_info(&mut io::stdout(), city_ix, coord_format).expect("failed to write to stdout");
+    }
+
+    pub fn subregion_summary(self: &Self, subregion_ix: SubregionId) -> SubregionSummary {
         let ( region_ix, country_ix, _ ) = self.subregion_parents(subregion_ix);
-        let mut n_cities: u32 = 0;
+        let mut n_cities = 0;
         let mut timezones = HashSet::new();
-        for city in &self.cities {
-            if city.subregion_ix == subregion_ix {
-                n_cities += 1;
-                timezones.insert(city.timezone_ix);
-            }
+        for city in self.cities_in_subregion(subregion_ix) {
+            n_cities += 1;
+            timezones.insert(city.entry.timezone_ix);
+        }
+
+        SubregionSummary {
+            subregion_ix,
+            name: self.subregion_name(subregion_ix).to_owned(),
+            region: self.region_name(region_ix).to_owned(),
+            country: self.country_name(country_ix).to_owned(),
+            country_code: self.country_code(country_ix).to_owned(),
+            n_cities,
+            n_timezones: timezones.len()
         }
+    }
 
-        println!("{}, {}, {}", self.subregions[subregion_ix], self.regions[region_ix], self.country_name(country_ix));
-        println!("Containing {} {}", n_cities, if n_cities == 1 { "city" } else { "cities" });
-        println!("Covers {} {}", timezones.len(), if timezones.len() == 1 { "timezone" } else { "timezones" });
+    pub fn write_subregion_info(self: &Self, writer: &mut dyn Write, subregion_ix: SubregionId) -> Result<(), io::Error> {
+        let summary = self.subregion_summary(subregion_ix);
+        writeln!(writer, "{}, {}, {}", summary.name, summary.region, summary.country)?;
+        writeln!(writer, "Containing {} {}", summary.n_cities, plural(summary.n_cities, "city", "cities"))?;
+        writeln!(writer, "Covers {} {}", summary.n_timezones, plural(summary.n_timezones, "timezone", "timezones"))?;
+        Ok(())
+    }
+
+    pub fn print_subregion_info(self: &Self, subregion_ix: SubregionId) {
+        self.write_subregion_info(&mut io::stdout(), subregion_ix).expect("failed to write to stdout");
     }
 
-    pub fn print_region_info(self: &Self, region_ix: usize) {
+    pub fn region_summary(self: &Self, region_ix: RegionId) -> RegionSummary {
         let country_ix = self.region_parent(region_ix);
-        let mut n_cities: u32 = 0;
+        let mut n_cities = 0;
         let mut subregions = HashSet::new();
         let mut timezones = HashSet::new();
-        for city in &self.cities {
-            if city.region_ix == region_ix {
-                n_cities += 1;
-                subregions.insert(city.subregion_ix);
-                timezones.insert(city.timezone_ix);
-            }
+        for city in self.cities_in_region(region_ix) {
+            n_cities += 1;
+            subregions.insert(city.entry.subregion_ix);
+            timezones.insert(city.entry.timezone_ix);
+        }
+
+        RegionSummary {
+            region_ix,
+            name: self.region_name(region_ix).to_owned(),
+            code: self.region_code(region_ix, country_ix).map(str::to_owned),
+            country: self.country_name(country_ix).to_owned(),
+            country_code: self.country_code(country_ix).to_owned(),
+            n_cities,
+            n_subregions: subregions.len(),
+            n_timezones: timezones.len()
+        }
+    }
+
+    pub fn write_region_info(self: &Self, writer: &mut dyn Write, region_ix: RegionId) -> Result<(), io::Error> {
+        let summary = self.region_summary(region_ix);
+        match &summary.code {
+            Some(code) => writeln!(writer, "{} ({}), {}", summary.name, code, summary.country)?,
+            None => writeln!(writer, "{}, {}", summary.name, summary.country)?
         }
+        writeln!(writer, "Containing {} {}", summary.n_cities, plural(summary.n_cities, "city", "cities"))?;
+        writeln!(writer, "Containing {} {}", summary.n_subregions, plural(summary.n_subregions, "sub-region", "sub-regions"))?;
+        writeln!(writer, "Covers {} {}", summary.n_timezones, plural(summary.n_timezones, "timezone", "timezones"))?;
+        Ok(())
+    }
 
-        println!("{}, {}", self.regions[region_ix], self.country_name(country_ix));
-        println!("Containing {} {}", n_cities, if n_cities == 1 { "city" } else { "cities" });
-        println!("Containing {} sub-region{}", subregions.len(), if subregions.len() == 1 { "" } else { "s" });
-        println!("Covers {} {}", timezones.len(), if timezones.len() == 1 { "timezone" } else { "timezones" });
+    pub fn print_region_info(self: &Self, region_ix: RegionId) {
+        self.write_region_info(&mut io::stdout(), region_ix).expect("failed to write to stdout");
     }
 
-    pub fn print_country_info(self: &Self, country_ix: usize) {
-        let mut n_cities: u32 = 0;
+    pub fn country_summary(self: &Self, country_ix: CountryId) -> CountrySummary {
+        let mut n_cities = 0;
         let mut subregions = HashSet::new();
         let mut regions = HashSet::new();
         let mut timezones = HashSet::new();
-        for city in &self.cities {
-            if city.country_ix == country_ix {
-                n_cities += 1;
-                subregions.insert(city.subregion_ix);
-                regions.insert(city.region_ix);
-                timezones.insert(city.timezone_ix);
-            }
+        for city in self.cities_in_country(country_ix) {
+            n_cities += 1;
+            subregions.insert(city.entry.subregion_ix);
+            regions.insert(city.entry.region_ix);
+            timezones.insert(city.entry.timezone_ix);
         }
 
-        println!("{} ({})", self.country_name(country_ix), self.country_code(country_ix));
-        println!("Containing {} {}", n_cities, if n_cities == 1 { "city" } else { "cities" });
-        println!("Containing {} sub-region{}", subregions.len(), if subregions.len() == 1 { "" } else { "s" });
-        println!("Containing {} region{}", regions.len(), if regions.len() == 1 { "" } else { "s" });
-        println!("Covers {} {}", timezones.len(), if timezones.len() == 1 { "timezone" } else { "timezones" });
+        CountrySummary {
+            country_ix,
+            name: self.country_name(country_ix).to_owned(),
+            code: self.country_code(country_ix).to_owned(),
+            n_cities,
+            n_subregions: subregions.len(),
+            n_regions: regions.len(),
+            n_timezones: timezones.len()
+        }
     }
 
-    pub fn find_matching_cities(self: &GeoDatabase, name: &str) -> Vec<usize> {
-        let name_parts: Vec<&str> = name.split(',').collect();
-        let (name, subregion, region, country) = match name_parts.len() {
-            1 => (name_parts[0], None, None, None),
-            2 => (name_parts[0].trim(), None, None, Some(name_parts[1].trim())),
-            3 => (name_parts[0].trim(), None, Some(name_parts[1].trim()), Some(name_parts[2].trim())),
-            4 => (name_parts[0].trim(), Some(name_parts[1].trim()), Some(name_parts[2].trim()), Some(name_parts[3].trim())),
-            _ => panic!("Cannot have more than 4 parts to a city search string")
-        };
+    pub fn write_country_info(self: &Self, writer: &mut dyn Write, country_ix: CountryId) -> Result<(), io::Error> {
+        let summary = self.country_summary(country_ix);
+        writeln!(writer, "{} ({})", summary.name, summary.code)?;
+        writeln!(writer, "Containing {} {}", summary.n_cities, plural(summary.n_cities, "city", "cities"))?;
+        writeln!(writer, "Containing {} {}", summary.n_subregions, plural(summary.n_subregions, "sub-region", "sub-regions"))?;
+        writeln!(writer, "Containing {} {}", summary.n_regions, plural(summary.n_regions, "region", "regions"))?;
+        writeln!(writer, "Covers {} {}", summary.n_timezones, plural(summary.n_timezones, "timezone", "timezones"))?;
+        Ok(())
+    }
 
-        return self.cities.iter().enumerate()
-            .filter(|(_, city)| {
-                return city.name == name && match country {
-                    None => true,
-                    Some(country) => self.countries[city.country_ix].contains(country)
-                } && match region {
-                    None => true,
-                    Some(region) => self.regions[city.region_ix].contains(region)
-                } && match subregion {
-                    None => true,
-                    Some(subregion) => self.subregions[city.subregion_ix].contains(subregion)
-                };
-            })
-            .map(|(city_ix, _)| city_ix)
-            .collect();
+    pub fn print_country_info(self: &Self, country_ix: CountryId) {
+        self.write_country_info(&mut io::stdout(), country_ix).expect("failed to write to stdout");
+    }
+
+    pub fn find_matching_cities(self: &GeoDatabase, name: &str, options: &SearchOptions) -> Result<Vec<CityId>, PatternError> {
+        let query = CityQuery::parse(name, options)?;
+        let found = exact_first(self.cities.iter().enumerate()
+            .filter(|(_, city)| query.matches(
+                &city.name,
+                &self.subregions[city.subregion_ix.0],
+                &self.regions[city.region_ix.0],
+                self.region_code(city.region_ix, city.country_ix),
+                &self.countries[city.country_ix.0]
+            ))
+            .map(|(city_ix, city)| (CityId(city_ix), query.name.is_exact(&city.name)))
+            .collect());
+        return Ok(self.apply_city_options(found, options));
     }
 
     pub fn add_city(self: &mut Self, city: CityEntry) {
         self.cities.push(city);
     }
 
-    pub fn remove_city(self: &mut Self, city_ix: usize) {
-        self.cities.remove(city_ix);
+    pub fn remove_city(self: &mut Self, city_ix: CityId) {
+        self.cities.remove(city_ix.0);
     }
 
-    pub fn print_matching_cities(self: &GeoDatabase, name: &str, max_displayed: usize) {
-        print_entries(
-            self.find_matching_cities(name), 
-            |city| self.print_city_info(city),
+    pub fn write_matching_cities(self: &Self, writer: &mut dyn Write, name: &str, options: &SearchOptions, max_displayed: usize, coord_format: &CoordinateFormat) -> Result<(), io::Error> {
+        write_entries(
+            writer,
+            self.find_matching_cities(name, options)?,
+            |writer, ix| self.write_city_info(writer, ix, coord_format),
             max_displayed
-        );
+        )
+    }
+
+    pub fn print_matching_cities(self: &GeoDatabase, name: &str, options: &SearchOptions, max_displayed: usize, coord_format: &CoordinateFormat) {
+        self.write_matching_cities(&mut io::stdout(), name, options, max_displayed, coord_format).expect("failed to write to stdout");
+    }
+
+    pub fn write_cities(self: &Self, writer: &mut dyn Write) -> Result<(), io::Error> {
+        for name in self.cities().map(|city| city.name()) {
+            writeln!(writer, "{}", name)?;
+        }
+        Ok(())
     }
 
     pub fn print_cities(self: &GeoDatabase) {
-        self.cities.iter().for_each(|city| println!("{}", city.name));
+        self.write_cities(&mut io::stdout()).expect("failed to write to stdout");
     }
 
-    pub fn find_matching_subregions(self: &GeoDatabase, name: &str) -> Vec<usize> {
-        let name_parts: Vec<&str> = name.split(',').collect();
-        let (name, region, country) = match name_parts.len() {
-            1 => (name_parts[0], None, None),
-            2 => (name_parts[0].trim(), None, Some(name_parts[1].trim())),
-            3 => (name_parts[0].trim(), Some(name_parts[1].trim()), Some(name_parts[2].trim())),
-            _ => panic!("Cannot have more than 3 parts to a subregion search string")
-        };
+    pub fn find_matching_subregions(self: &GeoDatabase, name: &str, options: &SearchOptions) -> Result<Vec<SubregionId>, PatternError> {
+        let (name, region, country) = parse_subregion_query(name, options)?;
 
-        self.subregions.iter().enumerate()
+        let found = exact_first(self.subregions.iter().enumerate()
             .filter(|(subregion_ix, subregion)| {
-                if *subregion == name {
-                    let ( region_ix, country_ix, _ ) = self.subregion_parents(*subregion_ix);
-                    return match country {
-                        None => true,
-                        Some(country) => self.countries[country_ix].contains(country)
-                    } && match region {
-                        None => true,
-                        Some(region) => self.regions[region_ix].contains(region)
-                    };
-                }
-                return false;
+                return name.equals(subregion) && self.subregion_qualifiers_match(SubregionId(*subregion_ix), &region, &country);
             })
-            .map(|(subregion_ix, _)| {
-                subregion_ix
+            .map(|(subregion_ix, subregion)| {
+                (SubregionId(subregion_ix), name.is_exact(subregion))
             })
-            .collect()
+            .collect());
+        return Ok(self.apply_table_options(found, options, self.subregions.len(), |ix| self.subregions[ix].as_str(), |subregion_ix: &SubregionId| subregion_ix.0, |city| city.subregion_ix.0));
     }
 
-    pub fn print_matching_subregion(self: &GeoDatabase, name: &str, max_displayed: usize) {
-        print_entries(
-            self.find_matching_subregions(name), 
-            |ix| self.print_subregion_info(ix),
+    pub fn write_matching_subregions(self: &Self, writer: &mut dyn Write, name: &str, options: &SearchOptions, max_displayed: usize) -> Result<(), io::Error> {
+        write_entries(
+            writer,
+            self.find_matching_subregions(name, options)?,
+            |writer, ix| self.write_subregion_info(writer, ix),
             max_displayed
-        );
+        )
+    }
+
+    pub fn print_matching_subregion(self: &GeoDatabase, name: &str, options: &SearchOptions, max_displayed: usize) {
+        self.write_matching_subregions(&mut io::stdout(), name, options, max_displayed).expect("failed to write to stdout");
     }
     
+    pub fn write_subregions(self: &Self, writer: &mut dyn Write) -> Result<(), io::Error> {
+        for (_, name) in self.subregions() {
+            writeln!(writer, "{}", name)?;
+        }
+        Ok(())
+    }
+
     pub fn print_subregions(self: &GeoDatabase) {
-        self.subregions.iter().for_each(|subregion| println!("{}", subregion));
+        self.write_subregions(&mut io::stdout()).expect("failed to write to stdout");
     }
 
-    pub fn find_matching_regions(self: &GeoDatabase, name: &str) -> Vec<usize> {
-        let name_parts: Vec<&str> = name.split(',').collect();
-        let (name, country) = match name_parts.len() {
-            1 => (name_parts[0], None),
-            2 => (name_parts[0].trim(), Some(name_parts[1].trim())),
-            _ => panic!("Cannot have more than 2 parts to a region search string")
-        };
+    pub fn find_matching_regions(self: &GeoDatabase, name: &str, options: &SearchOptions) -> Result<Vec<RegionId>, PatternError> {
+        let (name, country) = parse_region_query(name, options)?;
 
-        self.regions.iter().enumerate()
+        let found = exact_first(self.regions.iter().enumerate()
             .filter(|(region_ix, region)| {
-                if *region == name {
-                    let country_ix = self.region_parent(*region_ix);
-                    return match country {
-                        None => true,
-                        Some(country) => self.countries[country_ix].contains(country)
-                    };
-                }
-                return false;
+                let region_ix = RegionId(*region_ix);
+                return (name.equals(region) || self.codes_of_region(region_ix).any(|code| name.is_code(Some(code))))
+                    && self.region_qualifiers_match(region_ix, &country);
             })
-            .map(|(region_ix, _)| {
-                region_ix
+            .map(|(region_ix, region)| {
+                (RegionId(region_ix), name.is_exact(region))
             })
-            .collect()
+            .collect());
+        return Ok(self.apply_table_options(found, options, self.regions.len(), |ix| self.regions[ix].as_str(), |region_ix: &RegionId| region_ix.0, |city| city.region_ix.0));
     }
 
-    pub fn print_matching_regions(self: &GeoDatabase, name: &str, max_displayed: usize) {
-        print_entries(
-            self.find_matching_regions(name), 
-            |ix| self.print_region_info(ix),
+    pub fn write_matching_regions(self: &Self, writer: &mut dyn Write, name: &str, options: &SearchOptions, max_displayed: usize) -> Result<(), io::Error> {
+        write_entries(
+            writer,
+            self.find_matching_regions(name, options)?,
+            |writer, ix| self.write_region_info(writer, ix),
             max_displayed
-        );
+        )
+    }
+
+    pub fn print_matching_regions(self: &GeoDatabase, name: &str, options: &SearchOptions, max_displayed: usize) {
+        self.write_matching_regions(&mut io::stdout(), name, options, max_displayed).expect("failed to write to stdout");
     }
     
+    pub fn write_regions(self: &Self, writer: &mut dyn Write) -> Result<(), io::Error> {
+        for (_, name) in self.regions() {
+            writeln!(writer, "{}", name)?;
+        }
+        Ok(())
+    }
+
     pub fn print_regions(self: &GeoDatabase) {
-        self.regions.iter().for_each(|region| println!("{}", region));
+        self.write_regions(&mut io::stdout()).expect("failed to write to stdout");
     }
 
-    pub fn find_matching_countries(self: &GeoDatabase, name: &str) -> Vec<usize> {
-        self.countries.iter().enumerate()
+    pub fn find_matching_countries(self: &GeoDatabase, name: &str, options: &SearchOptions) -> Result<Vec<CountryId>, PatternError> {
+        let name = SearchTerm::new(name, options)?;
+        let found = exact_first(self.countries.iter().enumerate()
             .filter(|(_, country)| {
-                return country.contains(name);
+                return name.matches_country(country);
             })
-            .map(|(country_ix, _)| {
-                country_ix
+            .map(|(country_ix, country)| {
+                (CountryId(country_ix), name.is_exact(&country[2..]) || name.is_exact(&country[..2]))
             })
-            .collect()
+            .collect());
+        return Ok(self.apply_table_options(found, options, self.countries.len(), |ix| &self.countries[ix][2..], |country_ix: &CountryId| country_ix.0, |city| city.country_ix.0));
+    }
+
+    pub fn write_matching_countries(self: &Self, writer: &mut dyn Write, name: &str, options: &SearchOptions, max_displayed: usize) -> Result<(), io::Error> {
+        write_entries(
+            writer,
+            self.find_matching_countries(name, options)?,
+            |writer, ix| self.write_country_info(writer, ix),
+            max_displayed
+        )
+    }
+
+    pub fn print_matching_country(self: &GeoDatabase, name: &str, options: &SearchOptions, max_displayed: usize) {
+        self.write_matching_countries(&mut io::stdout(), name, options, max_displayed).expect("failed to write to stdout");
+    }
+
+    /// Cities which pass `filter` with a name similar to `name`, for when it may be misspelt, ordered by score
+    /// then population
+    ///
+    /// Qualifiers are given as in `find_matching_cities` and compared ignoring case and diacritics. The
+    /// sub-region, region and country searches only include entries with a city which passes `filter`. Fails
+    /// if `name` has more parts than `find_matching_cities` accepts.
+    pub fn find_similar_cities(self: &Self, name: &str, min_score: f64, filter: &CityFilter) -> Result<Vec<ScoredMatch<CityId>>, PatternError> {
+        let query = CityQuery::parse(name, &SearchOptions { fold: true, ..SearchOptions::default() })?;
+        return Ok(best_first(self.cities.iter().enumerate()
+            .filter(|(_, city)| query.qualifiers_match(
+                &self.subregions[city.subregion_ix.0],
+                &self.regions[city.region_ix.0],
+                self.region_code(city.region_ix, city.country_ix),
+                &self.countries[city.country_ix.0]
+            ))
+            .map(|(city_ix, city)| (ScoredMatch { entry: CityId(city_ix), score: query.name.similarity(&city.name) }, population_value(city.population)))
+            .filter(|(candidate, _)| candidate.score >= min_score && self.city_passes(candidate.entry, filter))
+            .collect()));
+    }
+
+    /// Sub-regions with a name similar to `name`, ordered by score then the population of their cities
+    pub fn find_similar_subregions(self: &Self, name: &str, min_score: f64, filter: &CityFilter) -> Result<Vec<ScoredMatch<SubregionId>>, PatternError> {
+        let (name, region, country) = parse_subregion_query(name, &SearchOptions { fold: true, ..SearchOptions::default() })?;
+        let populations = self.total_populations(self.subregions.len(), |city| city.subregion_ix.0);
+        let passing = self.entries_with_passing_city(self.subregions.len(), filter, |city| city.subregion_ix.0);
+        return Ok(best_first(self.subregions.iter().enumerate()
+            .map(|(subregion_ix, subregion)| (ScoredMatch { entry: SubregionId(subregion_ix), score: name.similarity(subregion) }, populations[subregion_ix]))
+            .filter(|(candidate, _)| candidate.score >= min_score && passing[candidate.entry.0] && self.subregion_qualifiers_match(candidate.entry, &region, &country))
+            .collect()));
+    }
+
+    /// Regions with a name similar to `name`, ordered by score then the population of their cities
+    pub fn find_similar_regions(self: &Self, name: &str, min_score: f64, filter: &CityFilter) -> Result<Vec<ScoredMatch<RegionId>>, PatternError> {
+        let (name, country) = parse_region_query(name, &SearchOptions { fold: true, ..SearchOptions::default() })?;
+        let populations = self.total_populations(self.regions.len(), |city| city.region_ix.0);
+        let passing = self.entries_with_passing_city(self.regions.len(), filter, |city| city.region_ix.0);
+        return Ok(best_first(self.regions.iter().enumerate()
+            .map(|(region_ix, region)| (ScoredMatch { entry: RegionId(region_ix), score: name.similarity(region) }, populations[region_ix]))
+            .filter(|(candidate, _)| candidate.score >= min_score && passing[candidate.entry.0] && self.region_qualifiers_match(candidate.entry, &country))
+            .collect()));
+    }
+
+    /// Countries with a name similar to `name`, or with `name` as their code, ordered by score then the
+    /// population of their cities
+    pub fn find_similar_countries(self: &Self, name: &str, min_score: f64, filter: &CityFilter) -> Vec<ScoredMatch<CountryId>> {
+        let name = SearchTerm::new(name, &SearchOptions { fold: true, ..SearchOptions::default() }).expect("literal searches are always valid");
+        let populations = self.total_populations(self.countries.len(), |city| city.country_ix.0);
+        let passing = self.entries_with_passing_city(self.countries.len(), filter, |city| city.country_ix.0);
+        return best_first(self.countries.iter().enumerate()
+            .map(|(country_ix, country)| {
+                let score = if name.matches_country(country) { 1.0 } else { name.similarity(&country[2..]) };
+                (ScoredMatch { entry: CountryId(country_ix), score }, populations[country_ix])
+            })
+            .filter(|(candidate, _)| candidate.score >= min_score && passing[candidate.entry.0])
+            .collect());
+    }
+
+    pub fn write_similar_cities(self: &Self, writer: &mut dyn Write, name: &str, min_score: f64, filter: &CityFilter, max_displayed: usize, coord_format: &CoordinateFormat) -> Result<(), io::Error> {
+        write_entries(
+            writer,
+            self.find_similar_cities(name, min_score, filter)?,
+            |writer, candidate| write_scored(writer, candidate, |writer, ix| self.write_city_info(writer, ix, coord_format)),
+            max_displayed
+        )
+    }
+
+    pub fn print_similar_cities(self: &Self, name: &str, min_score: f64, filter: &CityFilter, max_displayed: usize, coord_format: &CoordinateFormat) {
+        self.write_similar_cities(&mut io::stdout(), name, min_score, filter, max_displayed, coord_format).expect("failed to write to stdout");
+    }
+
+    pub fn write_similar_subregions(self: &Self, writer: &mut dyn Write, name: &str, min_score: f64, filter: &CityFilter, max_displayed: usize) -> Result<(), io::Error> {
+        write_entries(
+            writer,
+            self.find_similar_subregions(name, min_score, filter)?,
+            |writer, candidate| write_scored(writer, candidate, |writer, ix| self.write_subregion_info(writer, ix)),
+            max_displayed
+        )
+    }
+
+    pub fn print_similar_subregions(self: &Self, name: &str, min_score: f64, filter: &CityFilter, max_displayed: usize) {
+        self.write_similar_subregions(&mut io::stdout(), name, min_score, filter, max_displayed).expect("failed to write to stdout");
+    }
+
+    pub fn write_similar_regions(self: &Self, writer: &mut dyn Write, name: &str, min_score: f64, filter: &CityFilter, max_displayed: usize) -> Result<(), io::Error> {
+        write_entries(
+            writer,
+            self.find_similar_regions(name, min_score, filter)?,
+            |writer, candidate| write_scored(writer, candidate, |writer, ix| self.write_region_info(writer, ix)),
+            max_displayed
+        )
+    }
+
+    pub fn print_similar_regions(self: &Self, name: &str, min_score: f64, filter: &CityFilter, max_displayed: usize) {
+        self.write_similar_regions(&mut io::stdout(), name, min_score, filter, max_displayed).expect("failed to write to stdout");
     }
 
-    pub fn print_matching_country(self: &GeoDatabase, name: &str, max_displayed: usize) {
-        print_entries(
-            self.find_matching_countries(name), 
-            |ix| self.print_country_info(ix),
+    pub fn write_similar_countries(self: &Self, writer: &mut dyn Write, name: &str, min_score: f64, filter: &CityFilter, max_displayed: usize) -> Result<(), io::Error> {
+        write_entries(
+            writer,
+            self.find_similar_countries(name, min_score, filter),
+            |writer, candidate| write_scored(writer, candidate, |writer, ix| self.write_country_info(writer, ix)),
             max_displayed
-        );
+        )
+    }
+
+    pub fn print_similar_countries(self: &Self, name: &str, min_score: f64, filter: &CityFilter, max_displayed: usize) {
+        self.write_similar_countries(&mut io::stdout(), name, min_score, filter, max_displayed).expect("failed to write to stdout");
+    }
+
+    /// Sum of the populations of the cities in each entry of a table, `key` gives the entry a city is in
+    fn total_populations(self: &Self, len: usize, key: impl Fn(&CityEntry) -> usize) -> Vec<f64> {
+        let mut totals = vec![0.0; len];
+        for city in &self.cities {
+            totals[key(city)] += population_value(city.population);
+        }
+        return totals;
+    }
+
+    fn subregion_qualifiers_match(self: &Self, subregion_ix: SubregionId, region: &Option<SearchTerm>, country: &Option<SearchTerm>) -> bool {
+        let ( region_ix, country_ix, _ ) = self.subregion_parents(subregion_ix);
+        return country.as_ref().is_none_or(|country| country.matches_country(&self.countries[country_ix.0]))
+            && region.as_ref().is_none_or(|region| {
+                region.contained_in(&self.regions[region_ix.0]) || region.is_code(self.region_code(region_ix, country_ix))
+            });
+    }
+
+    fn region_qualifiers_match(self: &Self, region_ix: RegionId, country: &Option<SearchTerm>) -> bool {
+        let country_ix = self.region_parent(region_ix);
+        return country.as_ref().is_none_or(|country| country.matches_country(&self.countries[country_ix.0]));
     }
     
+    pub fn write_countries(self: &Self, writer: &mut dyn Write) -> Result<(), io::Error> {
+        for name in self.countries().map(|country| country.name) {
+            writeln!(writer, "{}", name)?;
+        }
+        Ok(())
+    }
+
     pub fn print_countires(self: &GeoDatabase) {
-        self.regions.iter().enumerate().for_each(|(country_ix, _)| println!("{}", self.country_name(country_ix)));
+        self.write_countries(&mut io::stdout()).expect("failed to write to stdout");
     }
     
-    pub fn find_matching_timezones(self: &GeoDatabase, name: &str) -> Vec<usize> {
-        self.timezones.iter().enumerate()
+    pub fn find_matching_timezones(self: &GeoDatabase, name: &str, options: &SearchOptions) -> Result<Vec<TimezoneId>, PatternError> {
+        let name = SearchTerm::new(name, options)?;
+        let found = exact_first(self.timezones.iter().enumerate()
             .filter(|(_, timezone)| {
-                return timezone.starts_with(name);
+                return name.prefix_of(timezone);
             })
-            .map(|(timezone_ix, _)| {
-                timezone_ix
+            .map(|(timezone_ix, timezone)| {
+                (TimezoneId(timezone_ix), name.is_exact(timezone))
             })
-            .collect()
+            .collect());
+        return Ok(self.apply_table_options(found, options, self.timezones.len(), |ix| self.timezones[ix].as_str(), |timezone_ix: &TimezoneId| timezone_ix.0, |city| city.timezone_ix.0));
+    }
+
+    pub fn write_timezones(self: &Self, writer: &mut dyn Write) -> Result<(), io::Error> {
+        for (_, name) in self.timezones() {
+            writeln!(writer, "{}", name)?;
+        }
+        Ok(())
     }
 
     pub fn print_timezones(self: &GeoDatabase) {
-        self.timezones.iter().for_each(|timezone, | println!("{}", timezone));
+        self.write_timezones(&mut io::stdout()).expect("failed to write to stdout");
     }
     
-    pub fn find_matching_features(self: &GeoDatabase, name: &str) -> Vec<usize> {
-        self.features.iter().enumerate()
+    pub fn find_matching_features(self: &GeoDatabase, name: &str, options: &SearchOptions) -> Result<Vec<FeatureId>, PatternError> {
+        let name = SearchTerm::new(name, options)?;
+        let found = exact_first(self.features.iter().enumerate()
             .filter(|(_, feature)| {
-                return feature.contains(name);
+                return name.contained_in(feature);
             })
-            .map(|(feature_ix, _)| {
-                feature_ix
+            .map(|(feature_ix, feature)| {
+                (FeatureId(feature_ix), name.is_exact(feature))
             })
-            .collect()
+            .collect());
+        return Ok(self.apply_table_options(found, options, self.features.len(), |ix| self.features[ix].as_str(), |feature_ix: &FeatureId| feature_ix.0, |city| city.feature_ix.0));
     }
     
+    pub fn write_features(self: &Self, writer: &mut dyn Write) -> Result<(), io::Error> {
+        for (_, name) in self.features() {
+            writeln!(writer, "{}", name)?;
+        }
+        Ok(())
+    }
+
     pub fn print_features(self: &GeoDatabase) {
-        self.features.iter().for_each(|feature, | println!("{}", feature));
+        self.write_features(&mut io::stdout()).expect("failed to write to stdout");
     }
 
     /// Find the region, country and timezone which contain this sub-region
-    pub fn subregion_parents(self: &Self, subregion_ix: usize) -> ( usize, usize, usize ) {
+    pub fn subregion_parents(self: &Self, subregion_ix: SubregionId) -> ( RegionId, CountryId, TimezoneId ) {
         for city in &self.cities {
             if city.subregion_ix == subregion_ix {
                 return ( city.region_ix, city.country_ix, city.timezone_ix );
@@ -501,7 +846,7 @@ impl GeoDatabase {
         panic!("Didn't find any cities in this subregion");
     }
 
-    pub fn region_parent(self: &Self, region_ix: usize) -> usize {
+    pub fn region_parent(self: &Self, region_ix: RegionId) -> CountryId {
         for city in &self.cities {
             if city.region_ix == region_ix {
                 return city.country_ix;
@@ -510,122 +855,61 @@ impl GeoDatabase {
         panic!("Didn't find any cities in this region");
     }
 
-    pub fn subregion_name<'a>(self: &'a Self, subregion_ix: usize) -> &'a str {
-        return &self.subregions[subregion_ix];
+    pub fn subregion_name<'a>(self: &'a Self, subregion_ix: SubregionId) -> &'a str {
+        return &self.subregions[subregion_ix.0];
     }
 
-    pub fn region_name<'a>(self: &'a Self, region_ix: usize) -> &'a str {
-        return &self.regions[region_ix];
+    pub fn region_name<'a>(self: &'a Self, region_ix: RegionId) -> &'a str {
+        return &self.regions[region_ix.0];
     }
 
-    pub fn country_name<'a>(self: &'a GeoDatabase, country_ix: usize) -> &'a str {
-        return &self.countries[country_ix][2..];
+    pub fn country_name<'a>(self: &'a GeoDatabase, country_ix: CountryId) -> &'a str {
+        return &self.countries[country_ix.0][2..];
     }
 
-    pub fn country_code<'a>(self: &'a GeoDatabase, country_ix: usize) -> &'a str {
-        return &self.countries[country_ix][0..2];
+    pub fn country_code<'a>(self: &'a GeoDatabase, country_ix: CountryId) -> &'a str {
+        return &self.countries[country_ix.0][0..2];
     }
 
-    pub fn timezone_name<'a>(self: &'a Self, timezone_ix: usize) -> &'a str {
-        return &self.timezones[timezone_ix];
+    pub fn timezone_name<'a>(self: &'a Self, timezone_ix: TimezoneId) -> &'a str {
+        return &self.timezones[timezone_ix.0];
     }
 
-    pub fn feature_name<'a>(self: &'a Self, feature_ix: usize) -> &'a str {
-        return &self.features[feature_ix];
+    pub fn feature_name<'a>(self: &'a Self, feature_ix: FeatureId) -> &'a str {
+        return &self.features[feature_ix.0];
     }
 
+    /// Read a database, which may be gzip or zstd compressed
     pub fn read_from(reader: &mut dyn BufRead) -> Result<GeoDatabase, DatabaseReadError> {
-        let header_line = read_line(reader)?;
-        let comment = read_line(reader)?;
-    
-        let version_string = parse_header(&header_line)?;
-        if version_string != "1.03" {
-            return Err(DatabaseReadError::UnsupportedVersion { expected: String::from("1.03"), found: String::from(version_string) });
-        }
-    
-        let mut buf = [0; 13];
-    
-        // cities
-        let mut cities = vec![];
-        loop {
-            let (buf_start, buf_end) = buf.split_at_mut(6);
-            reader.read_exact(buf_start)?;
-            if buf_start == [0,0,0,0,1,0xA] {
-                break;
-            }
-            reader.read_exact(buf_end)?;
-    
-            let city = parse_city_entry(&buf, reader)?;
-            cities.push(city);
-        }
-    
-        // countries
-        let mut countries = vec![];
-        loop {
-            let country = read_line(reader)?;
-            if country.as_bytes() == [0,0,0,0,2] {
-                break;
-            }
-    
-            countries.push(country);
-        }
-    
-        // regions
-        let mut regions = vec![];
-        loop {
-            let region = read_line(reader)?;
-            if region.as_bytes() == [0,0,0,0,3] {
-                break;
-            }
-    
-            regions.push(region);
+        let compression = Compression::detect(reader.fill_buf()?);
+        if compression == Compression::None {
+            return GeoDatabase::read_uncompressed(reader);
         }
-    
-        // subregions
-        let mut subregions = vec![];
-        loop {
-            let subregion = read_line(reader)?;
-            if subregion.as_bytes() == [0,0,0,0,4] {
-                break;
-            }
-    
-            subregions.push(subregion);
-        }
-    
-        // timezones
-        let mut timezones = vec![];
-        loop {
-            let timezone = read_line(reader)?;
-            if timezone.as_bytes() == [0,0,0,0,5] {
-                break;
-            }
-    
-            timezones.push(timezone);
-        }
-    
-        // features
-        let mut features = vec![];
-        loop {
-            let feature = read_line(reader)?;
-            if feature.as_bytes() == [0,0,0,0,0] {
-                break;
-            }
-    
-            features.push(feature);
-        }
-    
-        Ok(GeoDatabase{
-            comment, cities, countries, regions, subregions, timezones, features
-        })
+        return GeoDatabase::read_uncompressed(&mut compression::decoder(reader, compression)?);
+    }
+
+    fn read_uncompressed(reader: &mut dyn BufRead) -> Result<GeoDatabase, DatabaseReadError> {
+        return reader::read_database(reader);
     }
 
+    pub fn write_compressed(self: &Self, writer: &mut dyn Write, compression: Compression, format: &dyn FormatVersion) -> Result<(), io::Error> {
+        return compression::encode(writer, compression, |writer| self.write_version(writer, format));
+    }
+
+    /// Write the database in the current format version
     pub fn write_to(self: &Self, writer: &mut dyn Write) -> Result<(), io::Error> {
-        writeln!(writer, "Geolocation1.03 {}", self.cities.len())?;
+        return self.write_version(writer, CURRENT_VERSION);
+    }
+
+    /// Write the database in a particular format version, features are left out of versions which don't have
+    /// them. Fails if a city can't be represented in the version.
+    pub fn write_version(self: &Self, writer: &mut dyn Write, format: &dyn FormatVersion) -> Result<(), io::Error> {
+        writeln!(writer, "Geolocation{} {}", format.version(), self.cities.len())?;
         writeln!(writer, "{}", self.comment)?;
     
         // cities
         for city in &self.cities {
-            write_city_entry(writer, city)?;
+            format.write_record(writer, city)?;
         }
         writer.write_all(&[0, 0, 0, 0, 1, 0xA])?;
     
@@ -651,56 +935,116 @@ impl GeoDatabase {
         for timezone in &self.timezones {
             writeln!(writer, "{}", timezone)?;
         }
-        writer.write_all(&[0, 0, 0, 0, 5, 0xA])?;
+        if format.has_feature_table() {
+            writer.write_all(&[0, 0, 0, 0, 5, 0xA])?;
     
-        // features
-        for feature in &self.features {
-            writeln!(writer, "{}", feature)?;
+            // features
+            for feature in &self.features {
+                writeln!(writer, "{}", feature)?;
+            }
         }
         writer.write_all(&[0, 0, 0, 0, 0, 0xA])?;
+
+        writer.write_all(&self.extra_sections)?;
     
         Ok(())
     }
 }
 
-fn print_entries<T, F>(entries: Vec<T>, display: F, max_displayed: usize)
+fn write_city_details(writer: &mut dyn Write, city: &CityInfo, coord_format: &CoordinateFormat) -> Result<(), io::Error> {
+    match &city.region_code {
+        Some(code) => writeln!(writer, "{}, {}, {} ({}), {}", city.name, city.subregion, city.region, code, city.country)?,
+        None => writeln!(writer, "{}, {}, {}, {}", city.name, city.subregion, city.region, city.country)?
+    }
+    writeln!(writer, "{}", coord_format.format_position(city.latitude, city.longitude))?;
+    writeln!(writer, "Timezone: {}, Population: {}", city.timezone, format_population(city.population))?;
+    writeln!(writer, "{}", city.feature)?;
+    Ok(())
+}
+
+/// Write an entry found by a fuzzy search followed by its score
+fn write_scored<T, F>(writer: &mut dyn Write, candidate: ScoredMatch<T>, display: F) -> Result<(), io::Error>
+where
+    F: Fn(&mut dyn Write, T) -> Result<(), io::Error>
+{
+    display(writer, candidate.entry)?;
+    writeln!(writer, "Similarity: {:.2}", candidate.score)
+}
+
+fn write_entries<T, F>(writer: &mut dyn Write, entries: Vec<T>, display: F, max_displayed: usize) -> Result<(), io::Error>
 where
     T: Copy,
-    F: Fn(T) -> ()
+    F: Fn(&mut dyn Write, T) -> Result<(), io::Error>
 {
-    println!("-----------------------");
+    writeln!(writer, "-----------------------")?;
     for entry in entries.iter().take(max_displayed) {
-        display(*entry);
-        println!("-----------------------");
+        display(writer, *entry)?;
+        writeln!(writer, "-----------------------")?;
     }
-    if entries.len() == 0 {
-        println!("No results");
-        println!("-----------------------");
+    if entries.is_empty() {
+        writeln!(writer, "No results")?;
+        writeln!(writer, "-----------------------")?;
     } else if entries.len() > max_displayed {
-        println!("     and {} more", entries.len() - max_displayed);
-        println!("-----------------------");
+        writeln!(writer, "     and {} more", entries.len() - max_displayed)?;
+        writeln!(writer, "-----------------------")?;
     }
+    Ok(())
 }
 
-fn parse_header<'a>(header: &'a str) -> Result<&'a str, DatabaseReadError> {
-    let header_regex = Regex::new(r"Geolocation([\d]+.[\d]+)[\s]+([\d]+)").expect("invalid regex pattern");
-    return match header_regex.captures(header) {
-        Some(captures) => {
-            let (_, [version, _n_cities]) = captures.extract();
-            Ok(version)
-        }
-        None => Err(DatabaseReadError::InvalidHeader { msg: String::from("Expected \"Geolocation x.xx (n)\" where \"x.xx\" is the database version number and \"n\" is the number of cities in the database") })
-    };
-}
-
+#[derive(Debug)]
 pub enum DatabaseReadError {
     UnsupportedVersion{ expected: String, found: String },
     InvalidHeader{ msg: String },
-    IoError{ source: io::Error }
+    UnsupportedCompression{ compression: Compression },
+    /// The file ended part way through a section, the location's offset is the length of the file
+    Truncated{ location: ErrorLocation },
+    /// A name or table entry isn't valid UTF-8
+    InvalidText{ location: ErrorLocation },
+    /// The number of cities in the header doesn't match the number of city records
+    CityCountMismatch{ expected: usize, found: usize },
+    /// There is more data after the end of the last section
+    TrailingData{ location: ErrorLocation },
+    IoError{ source: io::Error, location: Option<ErrorLocation> }
+}
+
+impl DatabaseReadError {
+    /// Where in the file reading failed, if known
+    pub fn location(self: &Self) -> Option<&ErrorLocation> {
+        match self {
+            DatabaseReadError::Truncated { location } | DatabaseReadError::InvalidText { location } | DatabaseReadError::TrailingData { location } => Some(location),
+            DatabaseReadError::IoError { location, .. } => location.as_ref(),
+            _ => None
+        }
+    }
+}
+
+impl fmt::Display for DatabaseReadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DatabaseReadError::UnsupportedVersion { expected, found } => write!(f, "database version is not supported, expected {expected} found {found}"),
+            DatabaseReadError::InvalidHeader { msg } => write!(f, "invalid database header: {msg}"),
+            DatabaseReadError::UnsupportedCompression { compression } => write!(f, "database is {compression} compressed but {compression} support was not enabled when building"),
+            DatabaseReadError::Truncated { location } => write!(f, "database is truncated, it ends at {location}"),
+            DatabaseReadError::InvalidText { location } => write!(f, "text is not valid UTF-8 at {location}"),
+            DatabaseReadError::CityCountMismatch { expected, found } => write!(f, "header gives {expected} cities but {found} were found"),
+            DatabaseReadError::TrailingData { location } => write!(f, "unexpected data after the end of the database at byte {}", location.offset),
+            DatabaseReadError::IoError { source, location: Some(location) } => write!(f, "there was an IO error whilst reading the database at {location}: {source}"),
+            DatabaseReadError::IoError { source, location: None } => write!(f, "there was an IO error whilst reading the database: {source}")
+        }
+    }
+}
+
+impl std::error::Error for DatabaseReadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DatabaseReadError::IoError { source, .. } => Some(source),
+            _ => None
+        }
+    }
 }
 
 impl From<io::Error> for DatabaseReadError {
     fn from(value: io::Error) -> Self {
-        DatabaseReadError::IoError { source: value }
+        DatabaseReadError::IoError { source: value, location: None }
     }
 }
\ No newline at end of file
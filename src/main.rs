@@ -1,18 +1,56 @@
-use std::{error::Error, fs::File, io::{BufReader, BufWriter, ErrorKind}, process};
-use clap::{Parser, Subcommand, ValueEnum};
+#![allow(clippy::needless_return, clippy::needless_arbitrary_self_type, clippy::needless_lifetimes)]
+
+use std::{error::Error, fs::File, io::{BufRead, BufReader, BufWriter, ErrorKind}, path::{Path, PathBuf}, process};
+use clap::{Args, Parser, Subcommand, ValueEnum};
 use exif_geolocation_util::{*};
+use json::Json;
+
+mod json;
 
 #[derive(Debug,Parser)]
 #[command(name="exif-geolocation-util")]
 struct Cli {
-    /// The path of the database file to read
+    /// The path of the database file to read, which may be gzip or zstd compressed
     in_file: String,
     /// The path of the database file to write to
+    /// 
+    /// Paths ending in ".gz" or ".zst" are written gzip or zstd compressed. When overwriting the source file
+    /// its compression is kept. The database is written in the current format version unless converting.
     #[arg(long="out")]
     out_file: Option<String>,
     /// Allow the source file to be overwritten
     #[arg(long)]
     overwrite: bool,
+    /// The format of the output
+    /// 
+    /// "json" and "ndjson" (newline delimited JSON) produce structured results for use in scripts. Errors are
+    /// always reported as text on stderr with a non-zero exit code.
+    #[arg(long, value_enum, global=true, default_value="text")]
+    format: OutputFormat,
+    /// The notation used when printing positions
+    #[arg(long, value_enum, global=true, default_value="dms")]
+    coord_format: CoordFormat,
+    /// The number of decimal places of the smallest unit when printing positions
+    /// 
+    /// Defaults to 5 for decimal degrees, ISO 6709 and geo URIs, 3 for degrees and minutes and 2 for degrees,
+    /// minutes and seconds
+    #[arg(long, global=true)]
+    coord_precision: Option<usize>,
+    /// Print positions with a leading minus sign for south and west instead of a hemisphere letter
+    #[arg(long, global=true)]
+    coord_signed: bool,
+    /// A table of ISO 3166-2 region codes to import, each line a code and a region name separated by a tab
+    /// 
+    /// Codes are not stored in the database file. Once imported they can be used as region qualifiers and
+    /// filters and are included in the output.
+    #[arg(long, global=true)]
+    region_codes: Option<String>,
+    /// A zoneinfo directory, e.g. "/usr/share/zoneinfo"
+    /// 
+    /// Timezone names are checked against it instead of the embedded list of IANA time zone names, and UTC
+    /// offsets are read from it. Defaults to $TZDIR or /usr/share/zoneinfo for UTC offsets.
+    #[arg(long, global=true)]
+    zoneinfo: Option<String>,
     #[command(subcommand)]
     command: Commands
 }
@@ -26,6 +64,8 @@ enum Commands {
         /// The type of database entry to list
         #[arg(value_enum)]
         entry_type: EntryTypePlural,
+        #[command(flatten)]
+        filters: FilterArgs
     },
     /// Print details about specific entries
     Find {
@@ -42,11 +82,50 @@ enum Commands {
         ///  * find city "<city>, <country>" e.g. "Bristol, GB" or "Bristol, United Kingdom"
         ///  * find city "<city>, <region>, <country>" e.g. "Wick, England, GB"
         ///  * find city "<city>, <sub-region>, <region>, <country>" e.g. "Kingswood, South Gloucestershire, England, GB"
+        /// 
+        /// Countries, whether searched for or given as a qualifier, must be given by their whole name or ISO
+        /// 3166-1 code, alpha-2 (e.g. "GB"), alpha-3 (e.g. "GBR") or numeric (e.g. "826"). Regions may also be
+        /// given by their ISO 3166-2 code (e.g. "GB-ENG") when imported with --region-codes.
         #[arg(verbatim_doc_comment)]
         name: String,
-        /// The maximum number of entries to print
+        /// The maximum number of entries to print, structured output always includes every entry
         #[arg(long,default_value="4")]
-        max_displayed: usize
+        max_displayed: usize,
+        /// Ignore case and diacritics when comparing names, e.g. "sao paulo" finds "São Paulo"
+        /// 
+        /// Entries whose name matches exactly are listed first.
+        #[arg(long)]
+        fold: bool,
+        /// Tolerate misspellings, listing entries with similar names by how similar they are then by population
+        /// 
+        /// Names are compared ignoring case and diacritics, qualifiers must still be contained in the names
+        /// of the entry's parents.
+        #[arg(long, conflicts_with_all=["regex", "glob"])]
+        fuzzy: bool,
+        /// Treat the name and each qualifier as a regular expression which must match somewhere in the name,
+        /// e.g. " County$"
+        ///
+        /// The search string is split at commas outside "(...)", "[...]" and "{...}" before the patterns are
+        /// compiled, so a repetition such as "{1,3}" can be used. Write any other literal comma as "\x2C".
+        #[arg(long, conflicts_with="glob")]
+        regex: bool,
+        /// Treat the name and each qualifier as a wildcard pattern which must match the whole name, where "*"
+        /// matches any text, "?" any single character and "[...]" any one of a set of characters, e.g. "Saint *"
+        #[arg(long)]
+        glob: bool,
+        /// The lowest similarity, from 0 to 1, of the entries listed by a fuzzy search
+        #[arg(long, default_value_t=DEFAULT_MIN_SIMILARITY)]
+        min_score: f64,
+        #[command(flatten)]
+        filters: FilterArgs
+    },
+    /// Suggest cities, sub-regions, regions and countries with names starting with a prefix, most populous first
+    Autocomplete {
+        /// The start of the name, or of a word in the name, compared ignoring case and diacritics
+        prefix: String,
+        /// The maximum number of suggestions
+        #[arg(long, default_value="10")]
+        limit: usize
     },
     /// Add a new entry to the database
     Add {
@@ -78,6 +157,22 @@ enum Commands {
         ///  * 48.88 S, 123.39 W
         ///  * -48.88 N, -123.39 E
         ///  * -48.88, -123.39
+        ///  * -48°52'36.0", -123°23'36.0"
+        ///  * S 48°52'36.0", W 123°23'36.0"
+        /// 
+        /// The following notations are also accepted:
+        ///  * ISO 6709, e.g. "-48.88-123.39/", "-4852.6-12323.6/" or "-485236-1232336/"
+        ///  * geo URIs (RFC 5870), e.g. "geo:-48.88,-123.39"
+        ///  * Google Maps and OpenStreetMap URLs, e.g. "https://www.openstreetmap.org/#map=5/-48.88/-123.39"
+        ///  * geohashes, lowercase with at least 5 characters including a digit or with a "geohash:" prefix, e.g.
+        ///    "u09tunquc" or "geohash:gcpvj"
+        ///  * full Open Location Codes / plus codes, e.g. "443R4JF4+8M"
+        ///  * Maidenhead locators, e.g. "CE81hc"
+        ///  * UTM with a latitude band letter, e.g. "10F 471160 4586180"
+        ///  * MGRS, e.g. "10FDL7116086180"
+        /// 
+        /// Geohashes, plus codes, Maidenhead locators and MGRS references describe an area rather than a point
+        /// so the centre of that area is used.
         /// 
         /// Note that although decimal seconds is permitted, each latitude and longitude is packed into 
         /// 20 bits which makes the precision (smallest increment) of a latitude and logitude value 
@@ -130,7 +225,137 @@ enum Commands {
         /// the help text for the find command for details.
         name: String,
     },
+    /// Write the database in another version of the database format
+    /// 
+    /// Converting fails if an entry can't be represented in the chosen version.
+    Convert {
+        /// The version to write, e.g. "1.03"
+        #[arg(long)]
+        to_version: String
+    },
+    /// Check that reading the database and writing it back in the same version reproduces the file exactly
+    /// 
+    /// Prints where the rewritten file first differs from the original and exits with a non-zero code if it
+    /// doesn't. Compressed files are compared after decompressing.
+    VerifyRoundtrip,
+    /// Check every entry of the timezone table against the IANA time zone database
+    /// 
+    /// Prints the entries which are unknown or deprecated, with the name to use instead where there is one,
+    /// and exits with a non-zero code if there are any.
+    ValidateTimezones,
+    /// Print the UTC offset of a city's timezone at a date and time, and whether daylight saving time is in
+    /// effect, e.g. to fill the EXIF OffsetTimeOriginal tag
+    Offset {
+        /// The city, see the help text for the find command for how to qualify it
+        city: String,
+        /// The date and time
+        /// 
+        /// A time such as "2024:07:01 12:00:00" (as in EXIF) or "2024-07-01T12:00" is the time shown by a
+        /// clock in the city. A time with "Z" or an offset, e.g. "2024-07-01T11:00:00Z", or "@<seconds since
+        /// 1970>" is an instant.
+        timestamp: String
+    },
+    /// Find cities whose timezone looks wrong
+    /// 
+    /// A city is reported when most of its nearest cities, or most of the other cities in its sub-region,
+    /// are in another timezone, or when zone.tab lists its timezone for other countries. Exits with a
+    /// non-zero code if any are found.
+    AuditTimezones {
+        /// The number of nearest cities to compare each city with
+        #[arg(long, default_value="5")]
+        neighbours: usize
+    },
 }
+/// Filters and sorting shared by `find` and `list`
+/// 
+/// Sub-regions, regions, countries, timezones and features are included if any of their cities pass every
+/// filter.
+#[derive(Debug, Args)]
+struct FilterArgs {
+    /// Only include cities in the country with this ISO 3166-1 code, alpha-2 such as "FR" or, with the
+    /// iso3166 feature, alpha-3 such as "FRA" or numeric such as "250"
+    #[arg(long)]
+    country_code: Option<String>,
+    /// Only include cities in the region with this name
+    #[arg(long)]
+    region: Option<String>,
+    /// Only include cities in this timezone, or any timezone it starts, e.g. "America" or "America/Chicago"
+    #[arg(long)]
+    timezone: Option<String>,
+    /// Only include cities with this feature type
+    #[arg(long)]
+    feature: Option<String>,
+    /// Only include cities with at least this population, e.g. "100000" or "1.0e+5"
+    #[arg(long)]
+    min_population: Option<f64>,
+    /// Only include cities with at most this population
+    #[arg(long)]
+    max_population: Option<f64>,
+    /// Only include cities within "<south>,<west>,<north>,<east>" in decimal degrees, crossing the antimeridian
+    /// when west is greater than east
+    #[arg(long, value_parser=parse_bounding_box, allow_hyphen_values=true)]
+    bbox: Option<BoundingBox>,
+    /// The order of the results, fuzzy searches are always ordered by similarity
+    #[arg(long, value_enum, default_value="database")]
+    sort: SortBy,
+    /// The position distances are measured from when sorting by distance, in any notation accepted by "add"
+    #[arg(long, required_if_eq("sort", "distance"), allow_hyphen_values=true)]
+    near: Option<String>
+}
+
+#[derive(ValueEnum, Copy, Clone, Debug, PartialEq, Eq)]
+enum SortBy {
+    /// Exact matches first, then in the order of the database
+    Database,
+    /// Alphabetically
+    Name,
+    /// Largest population first
+    Population,
+    /// Nearest to the position given by --near first
+    Distance
+}
+
+fn parse_bounding_box(s: &str) -> Result<BoundingBox, String> {
+    let parts: Vec<f64> = s.split(',').map(|part| part.trim().parse::<f64>()).collect::<Result<_, _>>()
+        .map_err(|err| format!("{err}"))?;
+    let [south, west, north, east] = parts[..] else {
+        return Err(String::from("expected four numbers, \"<south>,<west>,<north>,<east>\""));
+    };
+    if south > north {
+        return Err(String::from("south must not be north of north"));
+    }
+    return Ok(BoundingBox { south, west, north, east });
+}
+
+impl FilterArgs {
+    /// The filter and sort order for these arguments, exiting if the position to sort by can't be parsed
+    fn search_options(self: &Self) -> SearchOptions {
+        let filter = CityFilter {
+            country_code: self.country_code.clone(),
+            region: self.region.clone(),
+            timezone: self.timezone.clone(),
+            feature: self.feature.clone(),
+            min_population: self.min_population,
+            max_population: self.max_population,
+            bounding_box: self.bbox
+        };
+        let sort = match self.sort {
+            SortBy::Database => SortOrder::Database,
+            SortBy::Name => SortOrder::Name,
+            SortBy::Population => SortOrder::Population,
+            SortBy::Distance => {
+                let near = self.near.as_deref().unwrap_or_default();
+                let (latitude, longitude) = parse_pos_string(near).unwrap_or_else(|err| {
+                    eprintln!("Error: {}", err.annotate(near));
+                    process::exit(1);
+                });
+                SortOrder::Distance { latitude, longitude }
+            }
+        };
+        return SearchOptions { filter, sort, ..SearchOptions::default() };
+    }
+}
+
 #[derive(ValueEnum, Copy, Clone, Debug, PartialEq, Eq)]
 enum EntryType {
     City, SubRegion, Region, Country
@@ -141,8 +366,148 @@ enum EntryTypePlural {
     Cities, SubRegions, Regions, Countries, Timezones, Features
 }
 
+#[derive(ValueEnum, Copy, Clone, Debug, PartialEq, Eq)]
+enum OutputFormat {
+    /// Human readable text
+    Text,
+    /// A single JSON document
+    Json,
+    /// Newline delimited JSON, one result per line
+    Ndjson
+}
+
+/// Print a single structured result
+fn emit(format: OutputFormat, value: Json) {
+    match format {
+        OutputFormat::Text => unreachable!("text output is printed directly"),
+        OutputFormat::Json => println!("{}", value.to_pretty_string()),
+        OutputFormat::Ndjson => println!("{}", value.to_compact_string())
+    }
+}
+
+/// Print a list of structured results, as an array in JSON or one result per line in newline delimited JSON
+fn emit_list(format: OutputFormat, values: Vec<Json>) {
+    match format {
+        OutputFormat::Ndjson => values.iter().for_each(|value| println!("{}", value.to_compact_string())),
+        _ => emit(format, Json::Array(values))
+    }
+}
+
+#[derive(ValueEnum, Copy, Clone, Debug, PartialEq, Eq)]
+enum CoordFormat {
+    /// Decimal degrees, e.g. 48.87667°S, 123.39333°W
+    Dd,
+    /// Degrees and decimal minutes, e.g. 48°52.600'S, 123°23.600'W
+    Dm,
+    /// Degrees, minutes and decimal seconds, e.g. 48°52'36.00"S, 123°23'36.00"W
+    Dms,
+    /// ISO 6709, e.g. -48.87667-123.39333/
+    Iso6709,
+    /// RFC 5870 geo URI, e.g. geo:-48.87667,-123.39333
+    GeoUri
+}
+
+impl From<CoordFormat> for CoordinateNotation {
+    fn from(value: CoordFormat) -> Self {
+        match value {
+            CoordFormat::Dd => CoordinateNotation::DecimalDegrees,
+            CoordFormat::Dm => CoordinateNotation::DegreesMinutes,
+            CoordFormat::Dms => CoordinateNotation::DegreesMinutesSeconds,
+            CoordFormat::Iso6709 => CoordinateNotation::Iso6709,
+            CoordFormat::GeoUri => CoordinateNotation::GeoUri,
+        }
+    }
+}
+
+/// The number of comma separated parts a search for `entry_type` accepts
+fn max_query_parts(entry_type: EntryType) -> usize {
+    match entry_type {
+        EntryType::City => 4,
+        EntryType::SubRegion => 3,
+        EntryType::Region => 2,
+        EntryType::Country => 1
+    }
+}
+
+/// The options for a search from the command line, exiting if the name isn't a valid search for `entry_type`
+fn search_options(entry_type: EntryType, name: &str, fold: bool, regex: bool, glob: bool, filters: &FilterArgs) -> SearchOptions {
+    let syntax = if regex {
+        PatternSyntax::Regex
+    } else if glob {
+        PatternSyntax::Glob
+    } else {
+        PatternSyntax::Literal
+    };
+    let options = SearchOptions { fold, syntax, ..filters.search_options() };
+    if let Err(err) = options.check_patterns(name, max_query_parts(entry_type)) {
+        eprintln!("Error: {err}");
+        process::exit(1);
+    }
+    return options;
+}
+
+fn place_kind(place: Place) -> &'static str {
+    match place {
+        Place::City(_) => "city",
+        Place::Subregion(_) => "sub-region",
+        Place::Region(_) => "region",
+        Place::Country(_) => "country"
+    }
+}
+
+fn exit_with_read_error(err: DatabaseReadError) -> ! {
+    eprintln!("Error: {err}");
+    if let Some(location) = err.location() {
+        eprintln!("{}", location.hex_dump());
+    }
+    process::exit(1);
+}
+
+/// The time zone names from a zoneinfo directory or, without one, the embedded names if they're available
+fn load_tzdata(zoneinfo: Option<&str>) -> Option<TzData> {
+    let Some(dir) = zoneinfo else {
+        #[cfg(feature = "tzdata")]
+        return Some(TzData::embedded().clone());
+        #[cfg(not(feature = "tzdata"))]
+        return None;
+    };
+    match TzData::load(Path::new(dir)) {
+        Ok(tzdata) => Some(tzdata),
+        Err(err) => {
+            eprintln!("Error: {err}");
+            process::exit(1);
+        }
+    }
+}
+
+fn plural<'a>(n: usize, singular: &'a str, plural: &'a str) -> &'a str {
+    return if n == 1 { singular } else { plural };
+}
+
+/// Import a region code table, warning about entries which don't match a region
+fn import_region_codes(database: &mut GeoDatabase, path: &str) {
+    let imported = File::open(path).map_err(RegionCodeError::Io)
+        .and_then(|file| database.import_region_codes(&mut BufReader::new(file)));
+    match imported {
+        Ok(result) => {
+            for unmatched in result.unmatched {
+                eprintln!("Warning: {}:{}: no region named \"{}\" for {}", path, unmatched.line, unmatched.name, unmatched.code);
+            }
+        },
+        Err(err) => {
+            eprintln!("Error: {}: {}", path, err);
+            process::exit(1);
+        }
+    }
+}
+
 fn main() -> Result<(), Box<dyn Error>> {
     let args = Cli::parse();
+    let coord_format = CoordinateFormat {
+        notation: args.coord_format.into(),
+        signed: args.coord_signed,
+        precision: args.coord_precision
+    };
 
     // open file
     let f = File::open(&args.in_file).unwrap_or_else(|err| {
@@ -154,46 +519,136 @@ fn main() -> Result<(), Box<dyn Error>> {
         process::exit(1);
     });
     let mut reader = BufReader::new(f);
+    let in_compression = Compression::detect(reader.fill_buf().unwrap_or_default());
 
-    // read database
-    let mut database = GeoDatabase::read_from(&mut reader).unwrap_or_else(|err| {
-        eprint!("Error: ");
-        match err {
-            DatabaseReadError::UnsupportedVersion { expected, found } => {
-                eprintln!("Database version is not supported, expected {expected} found {found}");
-            },
-            DatabaseReadError::InvalidHeader { msg } => {
-                eprintln!("Invalid database header: {msg}");
-            },
-            DatabaseReadError::IoError { source } => {
-                eprintln!("There was an IO error whilst reading the database: {}", source)
+    // looking up a city only needs the records of cities with a matching name, so avoid reading the whole file
+    #[cfg(feature = "mmap")]
+    if let (Commands::Find { entry_type: EntryType::City, name, max_displayed, fold, fuzzy: false, regex, glob, filters, .. }, Compression::None, None) = (&args.command, in_compression, &args.region_codes) {
+        let search = search_options(EntryType::City, name, *fold, *regex, *glob, filters);
+        // SAFETY: the file is only read, modifying it while this runs is unsupported
+        if let Ok(mapped) = unsafe { MappedDatabase::map(reader.get_ref()) } {
+            let database = mapped.database().unwrap_or_else(|err| exit_with_read_error(err));
+            match args.format {
+                OutputFormat::Text => database.print_matching_cities(name, &search, *max_displayed, &coord_format),
+                format => emit_list(format, database.find_matching_cities(name, &search)?.into_iter()
+                    .map(|ix| json::city_info(&database.city_info(ix), &coord_format)).collect())
             }
+            return Ok(());
         }
-        process::exit(1);
-    });
+    }
+
+    if let Commands::VerifyRoundtrip = args.command {
+        let difference = verify_round_trip(&mut reader).unwrap_or_else(|err| exit_with_read_error(err));
+        match args.format {
+            OutputFormat::Text => match &difference {
+                None => println!("Round trip OK, the rewritten database is identical"),
+                Some(difference) => {
+                    println!("The rewritten database differs at byte {} (original {} bytes, rewritten {} bytes)",
+                        difference.offset, difference.original_len, difference.rewritten_len);
+                    println!("Original:\n{}", difference.original_hex_dump());
+                    println!("Rewritten:\n{}", difference.rewritten_hex_dump());
+                }
+            },
+            format => emit(format, json::round_trip_difference(difference.as_ref()))
+        }
+        process::exit(if difference.is_some() { 1 } else { 0 });
+    }
+
+    // read database
+    let mut database = GeoDatabase::read_from(&mut reader).unwrap_or_else(|err| exit_with_read_error(err));
+    if let Some(path) = &args.region_codes {
+        import_region_codes(&mut database, path);
+    }
 
     // run action
+    let format = args.format;
     let mut write_out = false;
+    let mut out_version = CURRENT_VERSION;
     match args.command {
-        Commands::Info {  } => {
-            database.print_info();
+        Commands::Info => {
+            match format {
+                OutputFormat::Text => database.print_info(),
+                _ => emit(format, json::database_summary(&database.summary()))
+            }
         },
-        Commands::List { entry_type } => {
-            match entry_type {
-                EntryTypePlural::Cities => database.print_cities(),
-                EntryTypePlural::SubRegions => database.print_subregions(),
-                EntryTypePlural::Regions => database.print_regions(),
-                EntryTypePlural::Countries => database.print_countires(),
-                EntryTypePlural::Timezones => database.print_timezones(),
-                EntryTypePlural::Features => database.print_features(),
+        Commands::List { entry_type, filters } => {
+            let options = filters.search_options();
+            let names: Vec<String> = match entry_type {
+                EntryTypePlural::Cities => database.filter_cities(&options).into_iter()
+                    .map(|ix| database.city_info(ix))
+                    .map(|city| if format == OutputFormat::Text {
+                        format!("{}, {}, {}, {}", city.name, city.subregion, city.region, city.country)
+                    } else {
+                        city.name
+                    })
+                    .collect(),
+                EntryTypePlural::SubRegions => database.filter_subregions(&options).into_iter().map(|ix| database.subregion_name(ix).to_owned()).collect(),
+                EntryTypePlural::Regions => database.filter_regions(&options).into_iter().map(|ix| database.region_name(ix).to_owned()).collect(),
+                EntryTypePlural::Countries => database.filter_countries(&options).into_iter().map(|ix| database.country_name(ix).to_owned()).collect(),
+                EntryTypePlural::Timezones => database.filter_timezones(&options).into_iter().map(|ix| database.timezone_name(ix).to_owned()).collect(),
+                EntryTypePlural::Features => database.filter_features(&options).into_iter().map(|ix| database.feature_name(ix).to_owned()).collect(),
+            };
+            match format {
+                OutputFormat::Text => names.iter().for_each(|name| println!("{name}")),
+                _ => emit_list(format, names.into_iter().map(Json::from).collect())
             }
         },
-        Commands::Find { entry_type, name, max_displayed } => {
-            match entry_type {
-                EntryType::City => database.print_matching_cities(&name, max_displayed),
-                EntryType::SubRegion => database.print_matching_subregion(&name, max_displayed),
-                EntryType::Region => database.print_matching_regions(&name, max_displayed),
-                EntryType::Country => database.print_matching_country(&name, max_displayed)
+        Commands::Find { entry_type, name, max_displayed, fuzzy: true, min_score, filters, .. } => {
+            let filter = search_options(entry_type, &name, false, false, false, &filters).filter;
+            if format == OutputFormat::Text {
+                match entry_type {
+                    EntryType::City => database.print_similar_cities(&name, min_score, &filter, max_displayed, &coord_format),
+                    EntryType::SubRegion => database.print_similar_subregions(&name, min_score, &filter, max_displayed),
+                    EntryType::Region => database.print_similar_regions(&name, min_score, &filter, max_displayed),
+                    EntryType::Country => database.print_similar_countries(&name, min_score, &filter, max_displayed)
+                }
+            } else {
+                let results: Vec<Json> = match entry_type {
+                    EntryType::City => database.find_similar_cities(&name, min_score, &filter)?.into_iter()
+                        .map(|found| json::scored(json::city_info(&database.city_info(found.entry), &coord_format), found.score)).collect(),
+                    EntryType::SubRegion => database.find_similar_subregions(&name, min_score, &filter)?.into_iter()
+                        .map(|found| json::scored(json::subregion_summary(&database.subregion_summary(found.entry)), found.score)).collect(),
+                    EntryType::Region => database.find_similar_regions(&name, min_score, &filter)?.into_iter()
+                        .map(|found| json::scored(json::region_summary(&database.region_summary(found.entry)), found.score)).collect(),
+                    EntryType::Country => database.find_similar_countries(&name, min_score, &filter).into_iter()
+                        .map(|found| json::scored(json::country_summary(&database.country_summary(found.entry)), found.score)).collect()
+                };
+                emit_list(format, results);
+            }
+        },
+        Commands::Find { entry_type, name, max_displayed, fold, regex, glob, filters, .. } => {
+            let search = search_options(entry_type, &name, fold, regex, glob, &filters);
+            if format == OutputFormat::Text {
+                match entry_type {
+                    EntryType::City => database.print_matching_cities(&name, &search, max_displayed, &coord_format),
+                    EntryType::SubRegion => database.print_matching_subregion(&name, &search, max_displayed),
+                    EntryType::Region => database.print_matching_regions(&name, &search, max_displayed),
+                    EntryType::Country => database.print_matching_country(&name, &search, max_displayed)
+                }
+            } else {
+                let results: Vec<Json> = match entry_type {
+                    EntryType::City => database.find_matching_cities(&name, &search)?.into_iter()
+                        .map(|ix| json::city_info(&database.city_info(ix), &coord_format)).collect(),
+                    EntryType::SubRegion => database.find_matching_subregions(&name, &search)?.into_iter()
+                        .map(|ix| json::subregion_summary(&database.subregion_summary(ix))).collect(),
+                    EntryType::Region => database.find_matching_regions(&name, &search)?.into_iter()
+                        .map(|ix| json::region_summary(&database.region_summary(ix))).collect(),
+                    EntryType::Country => database.find_matching_countries(&name, &search)?.into_iter()
+                        .map(|ix| json::country_summary(&database.country_summary(ix))).collect()
+                };
+                emit_list(format, results);
+            }
+        },
+        Commands::Autocomplete { prefix, limit } => {
+            let suggestions = database.autocomplete_index().autocomplete(&prefix, limit);
+            match format {
+                OutputFormat::Text => {
+                    for suggestion in &suggestions {
+                        println!("{} ({})", suggestion.name, place_kind(suggestion.place));
+                    }
+                },
+                _ => emit_list(format, suggestions.iter()
+                    .map(|suggestion| json::suggestion(suggestion, place_kind(suggestion.place))).collect())
             }
         },
         Commands::Add {
@@ -212,12 +667,13 @@ fn main() -> Result<(), Box<dyn Error>> {
                         Ok((lat, long)) => (lat, long),
                         Err(err) => {
                             eprintln!("Invalid position: {err}");
+                            eprintln!("{}", err.annotate(&position));
                             process::exit(1);
                         }
                     };
 
                     // subregion
-                    let matching_subregions = database.find_matching_subregions(&sub_region);
+                    let matching_subregions = database.find_matching_subregions(&sub_region, &SearchOptions::default())?;
                     let subregion_ix = match matching_subregions.len() {
                         1 => matching_subregions[0],
                         0 => {
@@ -242,7 +698,7 @@ fn main() -> Result<(), Box<dyn Error>> {
 
                     // region
                     if let Some(region_name) = region {
-                        let matching_regions = database.find_matching_regions(&region_name);
+                        let matching_regions = database.find_matching_regions(&region_name, &SearchOptions::default())?;
                         region_ix = match matching_regions.len() {
                             1 => matching_regions[0],
                             0 => {
@@ -265,7 +721,7 @@ fn main() -> Result<(), Box<dyn Error>> {
 
                     // country
                     if let Some(country_name) = country {
-                        let matching_countries = database.find_matching_countries(&country_name);
+                        let matching_countries = database.find_matching_countries(&country_name, &SearchOptions::default())?;
                         country_ix = match matching_countries.len() {
                             1 => matching_countries[0],
                             0 => {
@@ -273,8 +729,8 @@ fn main() -> Result<(), Box<dyn Error>> {
                                 process::exit(1);
                             }
                             n => {
-                                eprintln!("Multiple ({n}) countries matched \"{}\" try prefixing the country name with the two letter \
-                                    country code e.g. \"GBUnited Kingdom\"", country_name);
+                                let examples = if cfg!(feature = "iso3166") { "\"GB\", \"GBR\" or \"826\"" } else { "\"GB\"" };
+                                eprintln!("Multiple ({n}) countries matched \"{}\" try the ISO 3166-1 country code instead e.g. {examples}", country_name);
     
                                 if n <= 5 {
                                     for country_ix in matching_countries {
@@ -288,7 +744,7 @@ fn main() -> Result<(), Box<dyn Error>> {
 
                     // timezone
                     if let Some(timezone_name) = timezone {
-                        let matching_timezones = database.find_matching_timezones(&timezone_name);
+                        let matching_timezones = database.find_matching_timezones(&timezone_name, &SearchOptions::default())?;
                         timezone_ix = match matching_timezones.len() {
                             1 => matching_timezones[0],
                             0 => {
@@ -308,8 +764,13 @@ fn main() -> Result<(), Box<dyn Error>> {
                         };
                     }
 
+                    // timezones are only chosen from the table, but the table itself may contain invalid names
+                    if let Some(problem) = load_tzdata(args.zoneinfo.as_deref()).and_then(|tzdata| tzdata.check(database.timezone_name(timezone_ix))) {
+                        eprintln!("Warning: timezone {}: {}", database.timezone_name(timezone_ix), problem);
+                    }
+
                     // feature
-                    let matching_features = database.find_matching_features(&feature_type);
+                    let matching_features = database.find_matching_features(&feature_type, &SearchOptions::default())?;
                     let feature_ix = match matching_features.len() {
                         1 => matching_features[0],
                         0 => {
@@ -334,22 +795,29 @@ fn main() -> Result<(), Box<dyn Error>> {
                         process::exit(1);
                     });
 
-                    println!("----------------- New Entry ------------------");
-                    println!("        name: {name}");
-                    println!("    position: {lat:.2}°, {long:.2}°");
-                    println!("   subregion: {} ({subregion_ix})", database.subregion_name(subregion_ix));
-                    println!("      region: {} ({region_ix})", database.region_name(region_ix));
-                    println!("     country: {} ({country_ix})", database.country_name(country_ix));
-                    println!("    timezone: {} ({timezone_ix})", database.timezone_name(timezone_ix));
-                    println!("     feature: {} ({feature_ix})", database.feature_name(feature_ix));
-                    println!("  population: {} (0x{population:X})", format_population(population));
-                    println!("----------------------------------------------");
+                    if format == OutputFormat::Text {
+                        println!("----------------- New Entry ------------------");
+                        println!("        name: {name}");
+                        println!("    position: {}", coord_format.format_position(lat, long));
+                        println!("   subregion: {} ({subregion_ix})", database.subregion_name(subregion_ix));
+                        println!("      region: {} ({region_ix})", database.region_name(region_ix));
+                        println!("     country: {} ({country_ix})", database.country_name(country_ix));
+                        println!("    timezone: {} ({timezone_ix})", database.timezone_name(timezone_ix));
+                        println!("     feature: {} ({feature_ix})", database.feature_name(feature_ix));
+                        println!("  population: {} (0x{population:X})", format_population(population));
+                        println!("----------------------------------------------");
+                    }
 
                     let city = CityEntry{
-                        name, latitude: lat, longitude: long, population, country_ix, region_ix, subregion_ix, timezone_ix, feature_ix
+                        name, latitude: lat, longitude: long, population, country_ix, region_ix, subregion_ix, timezone_ix, feature_ix, reserved: 0
                     };
 
                     database.add_city(city);
+
+                    if format != OutputFormat::Text {
+                        let city_info = database.city_info(CityId(database.city_count() - 1));
+                        emit(format, Json::Object(vec![("added", json::city_info(&city_info, &coord_format))]));
+                    }
                 }
                 _ => {
                     eprintln!("Adding non-city entries is not supported currently");
@@ -357,14 +825,134 @@ fn main() -> Result<(), Box<dyn Error>> {
                 }
             }
         },
+        Commands::Convert { to_version } => {
+            write_out = true;
+            out_version = format_version(&to_version).unwrap_or_else(|| {
+                let supported: Vec<&str> = FORMAT_VERSIONS.iter().map(|version| version.version()).collect();
+                eprintln!("Unsupported database version \"{to_version}\", expected one of {}", supported.join(", "));
+                process::exit(1);
+            });
+        },
+        Commands::VerifyRoundtrip => unreachable!("verified before reading the database"),
+        Commands::Offset { city, timestamp } => {
+            let timestamp = parse_timestamp(&timestamp).unwrap_or_else(|err| {
+                eprintln!("Invalid timestamp \"{timestamp}\": {err}");
+                process::exit(1);
+            });
+            let matching_cities = database.find_matching_cities(&city, &SearchOptions::default())?;
+            let city_ix = match matching_cities.len() {
+                1 => matching_cities[0],
+                0 => {
+                    eprintln!("No cities were found matching \"{city}\"");
+                    process::exit(1);
+                },
+                n => {
+                    eprintln!("Multiple ({n}) cities matched \"{city}\", you may need to provide greater specificity");
+                    process::exit(1);
+                }
+            };
+            let zoneinfo = args.zoneinfo.map_or_else(default_zoneinfo_dir, PathBuf::from);
+            let zone = database.city_time_zone(city_ix, &zoneinfo).unwrap_or_else(|err| {
+                eprintln!("Error: {err}");
+                process::exit(1);
+            });
+
+            // a local time has one offset unless the clocks change around it
+            let (local, offsets) = match timestamp {
+                Timestamp::Utc(time) => {
+                    let offset = zone.offset_at(time);
+                    (time.saturating_add(i64::from(offset.seconds)), vec![offset])
+                },
+                Timestamp::Local(time) => match zone.local_offset(time) {
+                    LocalOffset::Unique(offset) => (time, vec![offset]),
+                    LocalOffset::Ambiguous { earlier, later } => {
+                        eprintln!("Warning: {} happens twice in {} as the clocks go back, giving both offsets", format_date_time(time), zone.name());
+                        (time, vec![earlier, later])
+                    },
+                    LocalOffset::Skipped { before, after } => {
+                        eprintln!("Error: {} doesn't exist in {} as the clocks go forward from {} to {}", format_date_time(time), zone.name(), before, after);
+                        process::exit(1);
+                    }
+                }
+            };
+            match format {
+                OutputFormat::Text => {
+                    println!("{}, {}", database.city(city_ix).name(), zone.name());
+                    for offset in &offsets {
+                        let utc = local - i64::from(offset.seconds);
+                        println!("{} local, {} UTC", format_date_time(local), format_date_time(utc));
+                        println!("UTC offset: {} ({}, {})", offset, offset.abbreviation,
+                            if offset.is_dst { "daylight saving time" } else { "standard time" });
+                    }
+                },
+                format => emit_list(format, offsets.iter()
+                    .map(|offset| json::utc_offset(&database.city_info(city_ix), zone.name(), local, offset)).collect())
+            }
+        },
+        Commands::AuditTimezones { neighbours } => {
+            let tzdata = load_tzdata(args.zoneinfo.as_deref());
+            if tzdata.is_none() {
+                eprintln!("Warning: built without the embedded time zone names, give a zoneinfo directory with --zoneinfo to check timezones against countries");
+            }
+            let findings = database.audit_timezones(neighbours, tzdata.as_ref());
+            match format {
+                OutputFormat::Text => {
+                    for finding in &findings {
+                        let city = database.city_info(finding.city_ix);
+                        let reason = match &finding.mismatch {
+                            TimezoneMismatch::Neighbours { majority, agreeing, compared } =>
+                                format!("{agreeing} of the {compared} nearest cities are in {}", database.timezone_name(*majority)),
+                            TimezoneMismatch::Subregion { majority, agreeing, compared } =>
+                                format!("{agreeing} of the {compared} other cities in the sub-region are in {}", database.timezone_name(*majority)),
+                            TimezoneMismatch::Country { zone_countries } =>
+                                format!("zone.tab lists the timezone for {}, not {}", zone_countries.join(", "), city.country_code)
+                        };
+                        println!("{}, {}, {}, {} ({}): {}", city.name, city.subregion, city.region, city.country, city.timezone, reason);
+                    }
+                    if findings.is_empty() {
+                        println!("No timezone mismatches found");
+                    }
+                },
+                format => emit_list(format, findings.iter()
+                    .map(|finding| json::timezone_finding(&database.city_info(finding.city_ix), &finding.mismatch, |ix| database.timezone_name(ix))).collect())
+            }
+            if !findings.is_empty() {
+                process::exit(1);
+            }
+        },
+        Commands::ValidateTimezones => {
+            let Some(tzdata) = load_tzdata(args.zoneinfo.as_deref()) else {
+                eprintln!("Error: built without the embedded time zone names, give a zoneinfo directory with --zoneinfo");
+                process::exit(1);
+            };
+            let issues = database.validate_timezones(&tzdata);
+            match format {
+                OutputFormat::Text => {
+                    for issue in &issues {
+                        println!("{}: {} ({} {})", issue.name, issue.problem, issue.n_cities, plural(issue.n_cities, "city", "cities"));
+                    }
+                    if issues.is_empty() {
+                        println!("All {} timezones are valid (tzdata {})", database.timezone_count(), tzdata.version().unwrap_or("of unknown version"));
+                    }
+                },
+                format => emit_list(format, issues.iter().map(json::timezone_issue).collect())
+            }
+            if !issues.is_empty() {
+                process::exit(1);
+            }
+        },
         Commands::Remove { entry_type, name } => {
             write_out = true;
             match entry_type {
                 EntryType::City => {
-                    let matching_cities = database.find_matching_cities(&name);
+                    let matching_cities = database.find_matching_cities(&name, &SearchOptions::default())?;
                     match matching_cities.len() {
                         1 => {
+                            let city_info = database.city_info(matching_cities[0]);
                             database.remove_city(matching_cities[0]);
+                            if format != OutputFormat::Text {
+                                emit(format, Json::Object(vec![("removed", json::city_info(&city_info, &coord_format))]));
+                            }
                         },
                         0 => {
                             eprintln!("No cities were found matching \"{name}\"");
@@ -385,10 +973,11 @@ fn main() -> Result<(), Box<dyn Error>> {
     }
 
     if write_out {
-        let out_file = if args.overwrite {
-            args.in_file
+        let (out_file, out_compression) = if args.overwrite {
+            (args.in_file, in_compression)
         } else if let Some(out_file) = args.out_file {
-            out_file
+            let compression = Compression::from_path(&out_file);
+            (out_file, compression)
         } else {
             eprintln!("No output file path given and overwrite flag not set: use the \"--out <path>\" option to specify an output file or provide the \"--overwrite\" flag to permit writing to the source file.");
             process::exit(1);
@@ -402,7 +991,7 @@ fn main() -> Result<(), Box<dyn Error>> {
         let mut writer = BufWriter::new(f);
 
         // write database
-        database.write_to(&mut writer).unwrap_or_else(|err| {
+        database.write_compressed(&mut writer, out_compression, out_version).unwrap_or_else(|err| {
             eprintln!("Error writing database: {}", err);
             process::exit(1);
         });
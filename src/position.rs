@@ -0,0 +1,1119 @@
+//! Formatting and parsing of positions. As well as degrees, minutes and seconds `parse_pos_string`
+//! accepts ISO 6709 strings, `geo:` URIs, map URLs, geohashes, Open Location Codes, Maidenhead locators,
+//! UTM and MGRS references.
+//!
+//! Notations which describe an area rather than a point (geohashes, plus codes, Maidenhead locators and
+//! MGRS references) resolve to the centre of that area.
+
+use std::{f64::consts::PI, fmt, ops::Range, sync::LazyLock};
+use regex::Regex;
+
+/// The notation used to format a position
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CoordinateNotation {
+    /// Decimal degrees, e.g. `48.87667°S, 123.39333°W`
+    DecimalDegrees,
+    /// Degrees and decimal minutes, e.g. `48°52.600'S, 123°23.600'W`
+    DegreesMinutes,
+    /// Degrees, minutes and decimal seconds, e.g. `48°52'36.00"S, 123°23'36.00"W`
+    DegreesMinutesSeconds,
+    /// ISO 6709 string representation in decimal degrees, e.g. `-48.87667-123.39333/`
+    Iso6709,
+    /// RFC 5870 geo URI, e.g. `geo:-48.87667,-123.39333`
+    GeoUri,
+}
+
+impl CoordinateNotation {
+    /// Number of decimal places used when no precision is given
+    pub fn default_precision(self: Self) -> usize {
+        return match self {
+            CoordinateNotation::DecimalDegrees => 5,
+            CoordinateNotation::DegreesMinutes => 3,
+            CoordinateNotation::DegreesMinutesSeconds => 2,
+            CoordinateNotation::Iso6709 => 5,
+            CoordinateNotation::GeoUri => 5,
+        };
+    }
+}
+
+/// Precision is capped so that the scaled angle always fits in a `u64`
+const MAX_PRECISION: usize = 9;
+
+/// How to format a position, the default matches the original output of two decimal places of seconds
+/// with a hemisphere letter, e.g. `48°52'36.00"S, 123°23'36.00"W`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CoordinateFormat {
+    pub notation: CoordinateNotation,
+    /// Prefix negative angles with a minus sign rather than suffixing a hemisphere letter. ISO 6709 and
+    /// geo URIs are always signed.
+    pub signed: bool,
+    /// Number of decimal places of the smallest unit (degrees, minutes or seconds), `None` for the
+    /// notation's default. At most 9 decimal places are used.
+    pub precision: Option<usize>,
+}
+
+impl Default for CoordinateFormat {
+    fn default() -> Self {
+        CoordinateFormat { notation: CoordinateNotation::DegreesMinutesSeconds, signed: false, precision: None }
+    }
+}
+
+impl CoordinateFormat {
+    pub fn new(notation: CoordinateNotation) -> CoordinateFormat {
+        CoordinateFormat { notation, ..CoordinateFormat::default() }
+    }
+
+    fn precision(self: &Self) -> usize {
+        return self.precision.unwrap_or(self.notation.default_precision()).min(MAX_PRECISION);
+    }
+
+    /// Format a latitude and longitude in decimal degrees
+    pub fn format_position(self: &Self, lat: f64, long: f64) -> String {
+        let precision = self.precision();
+        return match self.notation {
+            CoordinateNotation::Iso6709 => {
+                format!("{}{}/", format_iso6709_part(lat, 2, precision), format_iso6709_part(long, 3, precision))
+            },
+            CoordinateNotation::GeoUri => {
+                format!("geo:{},{}", format_signed_decimal(lat, precision), format_signed_decimal(long, precision))
+            },
+            _ => format!("{}, {}", self.format_angle(lat, 'N', 'S'), self.format_angle(long, 'E', 'W')),
+        };
+    }
+
+    /// Format a single angle in decimal degrees, using `if_pos` or `if_neg` as the hemisphere letter when
+    /// not signed. ISO 6709 and geo URI notations fall back to signed decimal degrees.
+    pub fn format_angle(self: &Self, dd: f64, if_pos: char, if_neg: char) -> String {
+        let precision = self.precision();
+        let units_per_degree = match self.notation {
+            CoordinateNotation::DegreesMinutes => 60,
+            CoordinateNotation::DegreesMinutesSeconds => 3600,
+            CoordinateNotation::DecimalDegrees => 1,
+            CoordinateNotation::Iso6709 | CoordinateNotation::GeoUri => return format_signed_decimal(dd, precision),
+        };
+
+        // round once in the smallest unit so that e.g. 59.999 seconds carries into the minutes rather
+        // than being printed as 60.00
+        let scale = 10_u64.pow(precision as u32);
+        let total = (dd.abs() * (units_per_degree * scale) as f64).round() as u64;
+        let negative = dd < 0.0 && total != 0;
+
+        let body = match self.notation {
+            CoordinateNotation::DecimalDegrees => {
+                let deg = format_scaled(total, scale, precision);
+                if self.signed { deg } else { format!("{}°", deg) }
+            },
+            CoordinateNotation::DegreesMinutes => {
+                let per_degree = 60 * scale;
+                format!("{}°{}'", total / per_degree, format_scaled(total % per_degree, scale, precision))
+            },
+            _ => {
+                let per_degree = 3600 * scale;
+                let per_minute = 60 * scale;
+                format!("{}°{}'{}\"", total / per_degree, (total % per_degree) / per_minute, format_scaled(total % per_minute, scale, precision))
+            },
+        };
+
+        return if self.signed {
+            format!("{}{}", if negative { "-" } else { "" }, body)
+        } else {
+            format!("{}{}", body, if negative { if_neg } else { if_pos })
+        };
+    }
+}
+
+/// Format an integer count of `1 / scale` units with `precision` decimal places
+fn format_scaled(value: u64, scale: u64, precision: usize) -> String {
+    if precision == 0 {
+        return value.to_string();
+    }
+    return format!("{}.{:0precision$}", value / scale, value % scale, precision = precision);
+}
+
+fn format_signed_decimal(dd: f64, precision: usize) -> String {
+    let scale = 10_u64.pow(precision as u32);
+    let total = (dd.abs() * scale as f64).round() as u64;
+    return format!("{}{}", if dd < 0.0 && total != 0 { "-" } else { "" }, format_scaled(total, scale, precision));
+}
+
+/// Format one ISO 6709 component, whose whole degrees are padded to `deg_len` digits
+fn format_iso6709_part(dd: f64, deg_len: usize, precision: usize) -> String {
+    let scale = 10_u64.pow(precision as u32);
+    let total = (dd.abs() * scale as f64).round() as u64;
+    let sign = if dd < 0.0 && total != 0 { '-' } else { '+' };
+    let deg = format!("{:0deg_len$}", total / scale, deg_len = deg_len);
+    if precision == 0 {
+        return format!("{}{}", sign, deg);
+    }
+    return format!("{}{}.{:0precision$}", sign, deg, total % scale, precision = precision);
+}
+
+/// An error parsing a position, with the byte range of the input which caused it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PositionParseError {
+    pub msg: String,
+    pub span: Range<usize>,
+}
+
+impl PositionParseError {
+    fn new(msg: impl Into<String>, span: Range<usize>) -> PositionParseError {
+        PositionParseError { msg: msg.into(), span }
+    }
+
+    /// The input followed by a line of carets underlining the part which caused the error
+    pub fn annotate(self: &Self, input: &str) -> String {
+        let start = self.span.start.min(input.len());
+        let end = self.span.end.clamp(start, input.len());
+        let offset = input.get(..start).map_or(0, |s| s.chars().count());
+        let width = input.get(start..end).map_or(0, |s| s.chars().count()).max(1);
+        return format!("{}\n{}{}", input, " ".repeat(offset), "^".repeat(width));
+    }
+}
+
+impl fmt::Display for PositionParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.msg)
+    }
+}
+
+impl std::error::Error for PositionParseError {}
+
+/// Parse a position in any of the supported notations, returning the latitude and longitude in decimal
+/// degrees.
+///
+/// Degrees, minutes and seconds are parsed with a small grammar:
+///
+/// ```text
+/// position   := angle [","|";"] angle
+/// angle      := [hemisphere] [sign] components | [sign] components [hemisphere]
+/// components := number [degree]
+///             | integer degree number minute
+///             | integer degree integer minute number second
+/// ```
+///
+/// The latitude comes first unless the hemisphere letters say otherwise, e.g. `123°W, 48°S`.
+pub fn parse_pos_string(s: &str) -> Result<(f64, f64), PositionParseError> {
+    // ISO 6709, geo URIs, map URLs, geohashes, plus codes, Maidenhead, UTM and MGRS
+    if let Some(result) = parse_alternative_notation(s) {
+        let start = s.len() - s.trim_start().len();
+        let end = s.trim_end().len().max(start);
+        return result.map_err(|msg| PositionParseError::new(msg, start..end));
+    }
+
+    let tokens = tokenise(s)?;
+    if tokens.is_empty() {
+        return Err(PositionParseError::new("expected a latitude and longitude", 0..s.len()));
+    }
+
+    let mut pos = 0;
+    let first = parse_angle(s, &tokens, &mut pos)?;
+    if let Some(Token { kind: TokenKind::Separator, .. }) = tokens.get(pos) {
+        pos += 1;
+    }
+    if pos == tokens.len() {
+        return Err(PositionParseError::new("expected a longitude after the latitude", s.len()..s.len()));
+    }
+    let second = parse_angle(s, &tokens, &mut pos)?;
+    if let Some(token) = tokens.get(pos) {
+        return Err(PositionParseError::new("unexpected input after the longitude", token.span.start..s.trim_end().len()));
+    }
+
+    let swapped = matches!(first.hemisphere, Some(('E' | 'W', _))) || matches!(second.hemisphere, Some(('N' | 'S', _)));
+    let (lat, long) = if swapped { (second, first) } else { (first, second) };
+    let lat = lat.to_dd(('N', 'S'), "latitude", 90.0)?;
+    let long = long.to_dd(('E', 'W'), "longitude", 180.0)?;
+    return Ok((lat, long));
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Unit {
+    Degree,
+    Minute,
+    Second,
+}
+
+impl Unit {
+    fn name(self: Self) -> &'static str {
+        return match self {
+            Unit::Degree => "degrees",
+            Unit::Minute => "minutes",
+            Unit::Second => "seconds",
+        };
+    }
+
+    fn from_word(word: &str) -> Option<Unit> {
+        return match word {
+            "d" | "deg" => Some(Unit::Degree),
+            "m" | "min" => Some(Unit::Minute),
+            "s" | "sec" => Some(Unit::Second),
+            _ => None,
+        };
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum TokenKind {
+    Number { value: f64, integer: bool },
+    Sign { negative: bool },
+    Unit(Unit),
+    Hemisphere(char),
+    Separator,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Token {
+    kind: TokenKind,
+    span: Range<usize>,
+}
+
+fn tokenise(s: &str) -> Result<Vec<Token>, PositionParseError> {
+    let mut tokens = vec![];
+    let mut chars = s.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        let mut end = start + c.len_utf8();
+        let kind = match c {
+            c if c.is_whitespace() => continue,
+            '0'..='9' | '.' => {
+                let mut seen_point = c == '.';
+                while let Some(&(i, next)) = chars.peek() {
+                    if next.is_ascii_digit() || (next == '.' && !seen_point) {
+                        seen_point |= next == '.';
+                        end = i + 1;
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                let text = &s[start..end];
+                if text.starts_with('.') || text.ends_with('.') {
+                    return Err(PositionParseError::new("expected digits on both sides of the decimal point", start..end));
+                }
+                let value = text.parse::<f64>().map_err(|_| PositionParseError::new("not a valid number", start..end))?;
+                TokenKind::Number { value, integer: !seen_point }
+            },
+            '-' | '\u{2212}' => TokenKind::Sign { negative: true },
+            '+' => TokenKind::Sign { negative: false },
+            '°' | 'º' | '˚' => TokenKind::Unit(Unit::Degree),
+            '\'' if matches!(chars.peek(), Some((_, '\''))) => {
+                let (i, _) = chars.next().expect("peeked above");
+                end = i + 1;
+                TokenKind::Unit(Unit::Second)
+            },
+            '\'' | '\u{2018}' | '\u{2019}' | '\u{2032}' => TokenKind::Unit(Unit::Minute),
+            '"' | '\u{201C}' | '\u{201D}' | '\u{2033}' => TokenKind::Unit(Unit::Second),
+            ',' | ';' => TokenKind::Separator,
+            c if c.is_alphabetic() => {
+                while let Some(&(i, next)) = chars.peek() {
+                    if next.is_alphabetic() {
+                        end = i + next.len_utf8();
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                let word = &s[start..end];
+                if let Some(unit) = Unit::from_word(word) {
+                    TokenKind::Unit(unit)
+                } else if let Some(hemisphere) = hemisphere_from_word(word) {
+                    TokenKind::Hemisphere(hemisphere)
+                } else if let Some((unit, hemisphere)) = word.char_indices().last()
+                    .and_then(|(last, _)| Some((Unit::from_word(&word[..last])?, hemisphere_from_word(&word[last..])?))) {
+                    // a unit immediately followed by a hemisphere, e.g. "36sS"
+                    tokens.push(Token { kind: TokenKind::Unit(unit), span: start..end - 1 });
+                    tokens.push(Token { kind: TokenKind::Hemisphere(hemisphere), span: end - 1..end });
+                    continue;
+                } else {
+                    return Err(PositionParseError::new(format!("unrecognised \"{}\", expected a unit or one of N, S, E or W", word), start..end));
+                }
+            },
+            c => return Err(PositionParseError::new(format!("unexpected character '{}'", c), start..end)),
+        };
+        tokens.push(Token { kind, span: start..end });
+    }
+    return Ok(tokens);
+}
+
+fn hemisphere_from_word(word: &str) -> Option<char> {
+    return match word {
+        "N" | "S" | "E" | "W" => word.chars().next(),
+        _ => None,
+    };
+}
+
+/// A single parsed latitude or longitude, before it has been checked against its hemisphere and range
+struct Angle {
+    negative: Option<Range<usize>>,
+    hemisphere: Option<(char, Range<usize>)>,
+    value: f64,
+    span: Range<usize>,
+}
+
+impl Angle {
+    fn to_dd(self: &Self, (if_pos, if_neg): (char, char), name: &str, max_abs: f64) -> Result<f64, PositionParseError> {
+        let mut dd = self.value;
+        if let Some((hemisphere, span)) = &self.hemisphere {
+            if *hemisphere != if_pos && *hemisphere != if_neg {
+                return Err(PositionParseError::new(format!("expected {} or {} for the {}", if_pos, if_neg, name), span.clone()));
+            }
+            if *hemisphere == if_neg {
+                if let Some(sign_span) = &self.negative {
+                    return Err(PositionParseError::new(format!("a negative angle {} is likely a mistake so it is disallowed", if if_neg == 'S' { "south" } else { "west" }), sign_span.clone()));
+                }
+                dd = -dd;
+            }
+        }
+        if self.negative.is_some() {
+            dd = -dd;
+        }
+        if dd.abs() > max_abs {
+            return Err(PositionParseError::new(format!("{} must be between -{}° and {}° inclusive", name, max_abs, max_abs), self.span.clone()));
+        }
+        return Ok(dd);
+    }
+}
+
+fn parse_angle(s: &str, tokens: &[Token], pos: &mut usize) -> Result<Angle, PositionParseError> {
+    let start = tokens[*pos].span.start;
+    let mut hemisphere = None;
+    let mut negative = None;
+
+    if let Some(Token { kind: TokenKind::Hemisphere(c), span }) = tokens.get(*pos) {
+        hemisphere = Some((*c, span.clone()));
+        *pos += 1;
+    }
+    if let Some(Token { kind: TokenKind::Sign { negative: is_negative }, span }) = tokens.get(*pos) {
+        if *is_negative {
+            negative = Some(span.clone());
+        }
+        *pos += 1;
+    }
+
+    // (value, is integer, span) for degrees, minutes and seconds in turn
+    let mut components: Vec<(f64, bool, Range<usize>)> = vec![];
+    let expected = [Unit::Degree, Unit::Minute, Unit::Second];
+    while let Some(Token { kind: TokenKind::Number { value, integer }, span }) = tokens.get(*pos) {
+        let n = components.len();
+        match tokens.get(*pos + 1) {
+            // a degree symbol, or a number without a unit, after the first component starts the next angle
+            Some(Token { kind: TokenKind::Unit(Unit::Degree), .. }) if n > 0 => break,
+            Some(Token { kind: TokenKind::Unit(unit), span: unit_span }) => {
+                if n == 3 {
+                    break;
+                }
+                if *unit != expected[n] {
+                    return Err(PositionParseError::new(format!("expected {} but found {}", expected[n].name(), unit.name()), unit_span.clone()));
+                }
+                components.push((*value, *integer, span.clone()));
+                *pos += 2;
+            },
+            _ if n > 0 => break,
+            _ => {
+                // decimal degrees without a degree symbol
+                components.push((*value, *integer, span.clone()));
+                *pos += 1;
+                break;
+            },
+        }
+    }
+
+    if components.is_empty() {
+        let span = tokens.get(*pos).map_or(s.len()..s.len(), |token| token.span.clone());
+        return Err(PositionParseError::new("expected a number", span));
+    }
+    for (i, (_, integer, span)) in components.iter().enumerate().take(components.len() - 1) {
+        if !integer {
+            return Err(PositionParseError::new(format!("{} must be a whole number when {} are given", expected[i].name(), expected[i + 1].name()), span.clone()));
+        }
+    }
+    for (i, (value, _, span)) in components.iter().enumerate().skip(1) {
+        if *value >= 60.0 {
+            return Err(PositionParseError::new(format!("{} must be between 0 inclusive and 60 exclusive", expected[i].name()), span.clone()));
+        }
+    }
+
+    if let Some(Token { kind: TokenKind::Hemisphere(c), span }) = tokens.get(*pos) {
+        if hemisphere.is_some() {
+            return Err(PositionParseError::new("hemisphere given both before and after the angle", span.clone()));
+        }
+        hemisphere = Some((*c, span.clone()));
+        *pos += 1;
+    }
+
+    let value = components.iter().zip([1.0, 60.0, 3600.0]).map(|((value, _, _), divisor)| value / divisor).sum();
+    let end = tokens[*pos - 1].span.end;
+    return Ok(Angle { negative, hemisphere, value, span: start..end });
+}
+
+/// Try each of the alternative notations in turn.
+///
+/// Returns `None` when the input doesn't look like any of them so the caller can fall back to the
+/// degree, minute, second formats, or `Some` with the result of parsing the notation it does look like.
+fn parse_alternative_notation(s: &str) -> Option<Result<(f64, f64), &'static str>> {
+    let s = s.trim();
+    let lower = s.to_ascii_lowercase();
+    if lower.starts_with("geo:") {
+        return Some(parse_geo_uri(s));
+    }
+    if lower.starts_with("http://") || lower.starts_with("https://") {
+        return Some(parse_map_url(s));
+    }
+    if s.starts_with('+') || s.starts_with('-') {
+        if let Some(result) = parse_iso6709(s) {
+            return Some(result);
+        }
+    }
+    if s.contains('+') && !s.contains(char::is_whitespace) {
+        if let Some(result) = parse_plus_code(s) {
+            return Some(result);
+        }
+    }
+    if let Some(result) = parse_utm(s) {
+        return Some(result);
+    }
+    if let Some(result) = parse_mgrs(s) {
+        return Some(result);
+    }
+    if let Some(result) = parse_maidenhead(s) {
+        return Some(result);
+    }
+    if let Some(result) = parse_geohash(s) {
+        return Some(result);
+    }
+    return None;
+}
+
+static ISO6709_REGEX: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^([+-])(\d{6}|\d{4}|\d{2})(\.\d+)?([+-])(\d{7}|\d{5}|\d{3})(\.\d+)?(?:[+-]\d+(?:\.\d+)?)?(?:CRS([A-Za-z0-9_:]+))?/?$").expect("invalid regex pattern"));
+static MAIDENHEAD_REGEX: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^([A-R]{2})(\d{2})(?:([A-Xa-x]{2})(\d{2})?)?$").expect("invalid regex pattern"));
+static UTM_REGEX: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^(\d{1,2})\s*([C-HJ-NP-Xc-hj-np-x])\s+(\d+(?:\.\d+)?)\s*(?:mE|E)?\s*,?\s+(\d+(?:\.\d+)?)\s*(?:mN|N)?$").expect("invalid regex pattern"));
+static MGRS_REGEX: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^(\d{1,2})\s*([C-HJ-NP-Xc-hj-np-x])\s*([A-HJ-NP-Za-hj-np-z])([A-HJ-NP-Va-hj-np-v])\s*(\d{0,10})\s*(\d{0,5})$").expect("invalid regex pattern"));
+
+fn check_lat_long(lat: f64, long: f64) -> Result<(f64, f64), &'static str> {
+    if !(-90.0..=90.0).contains(&lat) {
+        return Err("latitude must be between -90 and 90 inclusive");
+    }
+    if !(-180.0..=180.0).contains(&long) {
+        return Err("longitude must be between -180 and 180 inclusive");
+    }
+    return Ok((lat, long));
+}
+
+fn parse_decimal_pair(lat: &str, long: &str) -> Result<(f64, f64), &'static str> {
+    let lat = lat.trim().parse::<f64>().or(Err("latitude not a valid decimal number"))?;
+    let long = long.trim().parse::<f64>().or(Err("longitude not a valid decimal number"))?;
+    return check_lat_long(lat, long);
+}
+
+/// ISO 6709 string representation, e.g. `+40.20361-075.00417/`, `+4012.22-07500.25/` or
+/// `+401213.1-0750015.1+2.79CRSWGS_84/`. Altitude is accepted but ignored.
+fn parse_iso6709(s: &str) -> Option<Result<(f64, f64), &'static str>> {
+    let captures = ISO6709_REGEX.captures(s)?;
+    let lat_sign = &captures[1];
+    let lat_digits = &captures[2];
+    let lat_frac = captures.get(3).map_or("", |m| m.as_str());
+    let long_sign = &captures[4];
+    let long_digits = &captures[5];
+    let long_frac = captures.get(6).map_or("", |m| m.as_str());
+
+    if let Some(crs) = captures.get(7) {
+        let crs = crs.as_str().to_ascii_uppercase();
+        if !(crs.contains("WGS") || crs.ends_with("4326") || crs.ends_with("4979")) {
+            return Some(Err("only WGS 84 coordinates are supported"));
+        }
+    }
+
+    let result = iso6709_part(lat_sign, lat_digits, lat_frac, 2)
+        .and_then(|lat| Ok((lat, iso6709_part(long_sign, long_digits, long_frac, 3)?)))
+        .and_then(|(lat, long)| check_lat_long(lat, long));
+    return Some(result);
+}
+
+/// Convert one ISO 6709 component, whose whole degrees occupy `deg_len` digits, to decimal degrees. The
+/// decimal fraction applies to whichever unit comes last.
+fn iso6709_part(sign: &str, digits: &str, frac: &str, deg_len: usize) -> Result<f64, &'static str> {
+    let frac = if frac.is_empty() { 0.0 } else { frac.parse::<f64>().or(Err("invalid decimal fraction"))? };
+    let deg = digits[..deg_len].parse::<f64>().or(Err("degrees not a valid integer"))?;
+    let rest = &digits[deg_len..];
+    let dd = match rest.len() {
+        0 => deg + frac,
+        2 => {
+            let min = rest.parse::<f64>().or(Err("minutes not a valid integer"))? + frac;
+            if min >= 60.0 {
+                return Err("minutes must be between 0 inclusive and 60 exclusive");
+            }
+            deg + min / 60.0
+        }
+        _ => {
+            let min = rest[..2].parse::<f64>().or(Err("minutes not a valid integer"))?;
+            let sec = rest[2..].parse::<f64>().or(Err("seconds not a valid integer"))? + frac;
+            if min >= 60.0 {
+                return Err("minutes must be between 0 inclusive and 60 exclusive");
+            }
+            if sec >= 60.0 {
+                return Err("seconds must be between 0 inclusive and 60 exclusive");
+            }
+            deg + (min + sec / 60.0) / 60.0
+        }
+    };
+    return Ok(if sign == "-" { -dd } else { dd });
+}
+
+/// RFC 5870 `geo:` URI, e.g. `geo:48.2010,16.3695,183;u=40`. Altitude and uncertainty are ignored and
+/// the only coordinate reference system accepted is WGS 84.
+fn parse_geo_uri(s: &str) -> Result<(f64, f64), &'static str> {
+    let path = &s[4..];
+    let path = path.split('?').next().unwrap_or("");
+    let mut parts = path.split(';');
+    let coords = parts.next().unwrap_or("");
+
+    for param in parts {
+        let (key, value) = param.split_once('=').unwrap_or((param, ""));
+        if key.trim().eq_ignore_ascii_case("crs") && !value.trim().eq_ignore_ascii_case("wgs84") {
+            return Err("only WGS 84 geo URIs are supported");
+        }
+    }
+
+    let coords: Vec<&str> = coords.split(',').collect();
+    if coords.len() != 2 && coords.len() != 3 {
+        return Err("geo URI must contain a latitude and longitude, e.g. \"geo:48.2010,16.3695\"");
+    }
+    return parse_decimal_pair(coords[0], coords[1]);
+}
+
+/// Decode the percent-encoded characters likely to appear in coordinates within a URL.
+fn percent_decode(s: &str) -> String {
+    let mut out = Vec::with_capacity(s.len());
+    let bytes = s.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            if let Some(Ok(byte)) = s.get(i + 1..i + 3).map(|hex| u8::from_str_radix(hex, 16)) {
+                out.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+        out.push(if bytes[i] == b'+' { b' ' } else { bytes[i] });
+        i += 1;
+    }
+    return String::from_utf8_lossy(&out).into_owned();
+}
+
+/// Google Maps and OpenStreetMap URLs, e.g. `https://www.google.com/maps/@51.5014,-0.1419,17z`,
+/// `https://www.google.com/maps/search/?api=1&query=51.5014,-0.1419` or
+/// `https://www.openstreetmap.org/?mlat=51.5014&mlon=-0.1419#map=17/51.5014/-0.1419`.
+fn parse_map_url(s: &str) -> Result<(f64, f64), &'static str> {
+    let decimal = r"(-?\d+(?:\.\d+)?)";
+    let without_scheme = s.split_once("://").map_or(s, |(_, rest)| rest);
+    let host = without_scheme.split(['/', '?', '#']).next().unwrap_or("").to_ascii_lowercase();
+    let (before_fragment, fragment) = without_scheme.split_once('#').unwrap_or((without_scheme, ""));
+    let (path, query) = before_fragment.split_once('?').unwrap_or((before_fragment, ""));
+    let query: Vec<(String, String)> = query.split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(key, value)| (key.to_ascii_lowercase(), percent_decode(value)))
+        .collect();
+    let query_value = |key: &str| query.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str());
+
+    if host == "goo.gl" || host == "maps.app.goo.gl" {
+        return Err("shortened map links cannot be resolved offline, open the link and copy the full URL instead");
+    }
+
+    if host.contains("google.") {
+        // the marker position of a place is more precise than the position of the viewport
+        let regex_place = Regex::new(&format!(r"!3d{}!4d{}", decimal, decimal)).expect("invalid regex pattern");
+        if let Some(captures) = regex_place.captures(path) {
+            return parse_decimal_pair(&captures[1], &captures[2]);
+        }
+        let regex_pair = Regex::new(&format!(r"^\s*{}\s*,\s*{}\s*$", decimal, decimal)).expect("invalid regex pattern");
+        for key in ["query", "q", "ll", "center", "destination", "daddr"] {
+            if let Some(value) = query_value(key) {
+                if let Some(captures) = regex_pair.captures(value) {
+                    return parse_decimal_pair(&captures[1], &captures[2]);
+                }
+            }
+        }
+        let regex_viewport = Regex::new(&format!(r"/@{},{}", decimal, decimal)).expect("invalid regex pattern");
+        if let Some(captures) = regex_viewport.captures(&percent_decode(path)) {
+            return parse_decimal_pair(&captures[1], &captures[2]);
+        }
+        return Err("no coordinates found in Google Maps URL");
+    }
+
+    if host.ends_with("openstreetmap.org") || host.ends_with("osm.org") {
+        if let (Some(lat), Some(long)) = (query_value("mlat"), query_value("mlon")) {
+            return parse_decimal_pair(lat, long);
+        }
+        let regex_map = Regex::new(&format!(r"map=\d+(?:\.\d+)?/{}/{}", decimal, decimal)).expect("invalid regex pattern");
+        if let Some(captures) = regex_map.captures(fragment) {
+            return parse_decimal_pair(&captures[1], &captures[2]);
+        }
+        if let (Some(lat), Some(long)) = (query_value("lat"), query_value("lon")) {
+            return parse_decimal_pair(lat, long);
+        }
+        return Err("no coordinates found in OpenStreetMap URL");
+    }
+
+    return Err("unrecognised map URL, only Google Maps and OpenStreetMap URLs are supported");
+}
+
+const GEOHASH_ALPHABET: &str = "0123456789bcdefghjkmnpqrstuvwxyz";
+
+/// Geohash, e.g. `u4pruydqqvj` or `geohash:gcpvj`. Without the `geohash:` prefix only lowercase geohashes of
+/// at least 5 characters including a digit are accepted, so that Maidenhead locators, lone numbers and place
+/// names such as "bed" aren't mistaken for one.
+fn parse_geohash(s: &str) -> Option<Result<(f64, f64), &'static str>> {
+    if s.get(..8).is_some_and(|prefix| prefix.eq_ignore_ascii_case("geohash:")) {
+        return Some(decode_geohash(&s[8..].to_ascii_lowercase()));
+    }
+    let looks_like_geohash = s.len() >= 5 && s.chars().all(|c| GEOHASH_ALPHABET.contains(c))
+        && s.chars().any(|c| c.is_ascii_lowercase()) && s.chars().any(|c| c.is_ascii_digit());
+    if !looks_like_geohash {
+        return None;
+    }
+    return Some(decode_geohash(s));
+}
+
+/// The centre of the area of a lowercase geohash
+fn decode_geohash(hash: &str) -> Result<(f64, f64), &'static str> {
+    if hash.is_empty() || !hash.chars().all(|c| GEOHASH_ALPHABET.contains(c)) {
+        return Err("geohash must only contain the characters 0-9 and b-z except i, l and o");
+    }
+    if hash.len() > 12 {
+        return Err("geohash cannot be longer than 12 characters");
+    }
+
+    let (mut lat_min, mut lat_max) = (-90.0, 90.0);
+    let (mut long_min, mut long_max) = (-180.0, 180.0);
+    let mut is_long = true;
+    for c in hash.chars() {
+        let value = GEOHASH_ALPHABET.find(c).expect("character checked above");
+        for bit in (0..5).rev() {
+            let set = (value >> bit) & 1 == 1;
+            let (min, max) = if is_long { (&mut long_min, &mut long_max) } else { (&mut lat_min, &mut lat_max) };
+            let mid = (*min + *max) / 2.0;
+            if set {
+                *min = mid;
+            } else {
+                *max = mid;
+            }
+            is_long = !is_long;
+        }
+    }
+    return Ok(((lat_min + lat_max) / 2.0, (long_min + long_max) / 2.0));
+}
+
+const PLUS_CODE_ALPHABET: &str = "23456789CFGHJMPQRVWX";
+
+/// Full Open Location Code (plus code), e.g. `9C3XGV4C+2V` or the padded `9C3XGV00+`. Short codes such as
+/// `GV4C+2V London` need a reference location to be resolved and so are rejected.
+fn parse_plus_code(s: &str) -> Option<Result<(f64, f64), &'static str>> {
+    let code = s.to_ascii_uppercase();
+    if !code.chars().all(|c| c == '+' || c == '0' || PLUS_CODE_ALPHABET.contains(c)) {
+        return None;
+    }
+    let separator = code.find('+').expect("caller checks for separator");
+    if code.matches('+').count() != 1 {
+        return Some(Err("plus code must contain a single \"+\" separator"));
+    }
+    if separator < 8 {
+        return Some(Err("short plus codes cannot be resolved without a reference location, use the full code"));
+    }
+    if separator > 8 {
+        return Some(Err("plus code must have 8 characters before the \"+\" separator"));
+    }
+    let (head, tail) = (&code[..8], &code[9..]);
+
+    if let Some(padding) = head.find('0') {
+        if padding == 0 || padding % 2 == 1 || !head[padding..].chars().all(|c| c == '0') || !tail.is_empty() {
+            return Some(Err("invalid padding in plus code"));
+        }
+    }
+    if tail.len() == 1 {
+        return Some(Err("plus code must have at least 2 characters after the \"+\" separator"));
+    }
+    if tail.contains('0') {
+        return Some(Err("invalid padding in plus code"));
+    }
+
+    let digits: Vec<usize> = head.chars().chain(tail.chars())
+        .take_while(|c| *c != '0')
+        .map(|c| PLUS_CODE_ALPHABET.find(c).expect("character checked above"))
+        .take(15)
+        .collect();
+    if digits[0] >= 9 || digits.get(1).is_some_and(|d| *d >= 18) {
+        return Some(Err("plus code is outside the valid range of latitudes and longitudes"));
+    }
+
+    let mut lat = -90.0;
+    let mut long = -180.0;
+    let mut lat_res = 20.0 * 20.0;
+    let mut long_res = 20.0 * 20.0;
+    for pair in digits.iter().take(10).collect::<Vec<_>>().chunks(2) {
+        lat_res /= 20.0;
+        long_res /= 20.0;
+        lat += *pair[0] as f64 * lat_res;
+        if let Some(d) = pair.get(1) {
+            long += **d as f64 * long_res;
+        }
+    }
+    for d in digits.iter().skip(10) {
+        lat_res /= 5.0;
+        long_res /= 4.0;
+        lat += (d / 4) as f64 * lat_res;
+        long += (d % 4) as f64 * long_res;
+    }
+    return Some(check_lat_long((lat + lat_res / 2.0).min(90.0), long + long_res / 2.0));
+}
+
+/// Maidenhead locator, e.g. `IO91`, `IO91wm` or `IO91wm48`. The field letters must be uppercase and the
+/// square digits are required, so that two letters such as "NE" aren't taken for a field.
+fn parse_maidenhead(s: &str) -> Option<Result<(f64, f64), &'static str>> {
+    let captures = MAIDENHEAD_REGEX.captures(s)?;
+
+    let field = captures[1].as_bytes();
+    let square = captures[2].as_bytes();
+    let mut long_size = 2.0;
+    let mut lat_size = 1.0;
+    let mut long = f64::from(field[0] - b'A') * 20.0 - 180.0 + f64::from(square[0] - b'0') * long_size;
+    let mut lat = f64::from(field[1] - b'A') * 10.0 - 90.0 + f64::from(square[1] - b'0') * lat_size;
+
+    if let Some(subsquare) = captures.get(3) {
+        let subsquare = subsquare.as_str().to_ascii_lowercase();
+        let subsquare = subsquare.as_bytes();
+        long_size /= 24.0;
+        lat_size /= 24.0;
+        long += f64::from(subsquare[0] - b'a') * long_size;
+        lat += f64::from(subsquare[1] - b'a') * lat_size;
+    }
+    if let Some(extended) = captures.get(4) {
+        let extended = extended.as_str().as_bytes();
+        long_size /= 10.0;
+        lat_size /= 10.0;
+        long += f64::from(extended[0] - b'0') * long_size;
+        lat += f64::from(extended[1] - b'0') * lat_size;
+    }
+    return Some(Ok((lat + lat_size / 2.0, long + long_size / 2.0)));
+}
+
+const UTM_BANDS: &str = "CDEFGHJKLMNPQRSTUVWX";
+
+/// WGS 84 ellipsoid semi-major axis in metres
+const WGS84_A: f64 = 6378137.0;
+/// WGS 84 ellipsoid flattening
+const WGS84_F: f64 = 1.0 / 298.257223563;
+/// UTM central meridian scale factor
+const UTM_K0: f64 = 0.9996;
+const UTM_FALSE_EASTING: f64 = 500000.0;
+const UTM_FALSE_NORTHING_SOUTH: f64 = 10000000.0;
+
+/// Coefficients of the Krüger series used to convert to and from transverse Mercator.
+struct KruegerSeries {
+    /// rectifying radius
+    a: f64,
+    n: f64,
+    alpha: [f64; 3],
+    beta: [f64; 3],
+    delta: [f64; 3],
+}
+
+fn krueger_series() -> KruegerSeries {
+    let n = WGS84_F / (2.0 - WGS84_F);
+    let (n2, n3) = (n * n, n * n * n);
+    KruegerSeries {
+        a: WGS84_A / (1.0 + n) * (1.0 + n2 / 4.0 + n2 * n2 / 64.0),
+        n,
+        alpha: [n / 2.0 - 2.0 / 3.0 * n2 + 5.0 / 16.0 * n3, 13.0 / 48.0 * n2 - 3.0 / 5.0 * n3, 61.0 / 240.0 * n3],
+        beta: [n / 2.0 - 2.0 / 3.0 * n2 + 37.0 / 96.0 * n3, 1.0 / 48.0 * n2 + 1.0 / 15.0 * n3, 17.0 / 480.0 * n3],
+        delta: [2.0 * n - 2.0 / 3.0 * n2 - 2.0 * n3, 7.0 / 3.0 * n2 - 8.0 / 5.0 * n3, 56.0 / 15.0 * n3],
+    }
+}
+
+fn utm_central_meridian(zone: u8) -> f64 {
+    return f64::from(zone) * 6.0 - 183.0;
+}
+
+/// Project a latitude and longitude in degrees to an easting and northing within the given UTM zone.
+fn utm_forward(lat: f64, long: f64, zone: u8) -> (f64, f64) {
+    let k = krueger_series();
+    let phi = lat * PI / 180.0;
+    let lambda = (long - utm_central_meridian(zone)) * PI / 180.0;
+    let c = 2.0 * k.n.sqrt() / (1.0 + k.n);
+    let t = (phi.sin().atanh() - c * (c * phi.sin()).atanh()).sinh();
+    let xi_p = t.atan2(lambda.cos());
+    let eta_p = (lambda.sin() / (1.0 + t * t).sqrt()).atanh();
+
+    let mut easting = eta_p;
+    let mut northing = xi_p;
+    for (j, alpha) in k.alpha.iter().enumerate() {
+        let j2 = 2.0 * (j + 1) as f64;
+        easting += alpha * (j2 * xi_p).cos() * (j2 * eta_p).sinh();
+        northing += alpha * (j2 * xi_p).sin() * (j2 * eta_p).cosh();
+    }
+    let easting = UTM_FALSE_EASTING + UTM_K0 * k.a * easting;
+    let northing = UTM_K0 * k.a * northing;
+    return (easting, if lat < 0.0 { northing + UTM_FALSE_NORTHING_SOUTH } else { northing });
+}
+
+/// Convert a UTM easting and northing within the given zone and hemisphere to a latitude and longitude
+/// in degrees.
+fn utm_inverse(zone: u8, north: bool, easting: f64, northing: f64) -> (f64, f64) {
+    let k = krueger_series();
+    let northing = if north { northing } else { northing - UTM_FALSE_NORTHING_SOUTH };
+    let xi = northing / (UTM_K0 * k.a);
+    let eta = (easting - UTM_FALSE_EASTING) / (UTM_K0 * k.a);
+
+    let mut xi_p = xi;
+    let mut eta_p = eta;
+    for (j, beta) in k.beta.iter().enumerate() {
+        let j2 = 2.0 * (j + 1) as f64;
+        xi_p -= beta * (j2 * xi).sin() * (j2 * eta).cosh();
+        eta_p -= beta * (j2 * xi).cos() * (j2 * eta).sinh();
+    }
+    let chi = (xi_p.sin() / eta_p.cosh()).asin();
+    let mut phi = chi;
+    for (j, delta) in k.delta.iter().enumerate() {
+        phi += delta * (2.0 * (j + 1) as f64 * chi).sin();
+    }
+    let lambda = eta_p.sinh().atan2(xi_p.cos());
+
+    return (phi * 180.0 / PI, utm_central_meridian(zone) + lambda * 180.0 / PI);
+}
+
+fn parse_utm_zone(zone: &str, band: &str) -> Result<(u8, char), &'static str> {
+    let zone = zone.parse::<u8>().or(Err("UTM zone not a valid integer"))?;
+    if !(1..=60).contains(&zone) {
+        return Err("UTM zone must be between 1 and 60 inclusive");
+    }
+    let band = band.chars().next().expect("regex ensures a band letter").to_ascii_uppercase();
+    return Ok((zone, band));
+}
+
+/// UTM reference with a latitude band letter, e.g. `31U 448251 5411932` or `31U 448251mE 5411932mN`.
+/// The letter is always interpreted as a latitude band so "N" and "S" refer to the bands 0°-8°N and
+/// 40°-48°N rather than hemispheres.
+fn parse_utm(s: &str) -> Option<Result<(f64, f64), &'static str>> {
+    let captures = UTM_REGEX.captures(s)?;
+    let result = parse_utm_zone(&captures[1], &captures[2]).and_then(|(zone, band)| {
+        let easting = captures[3].parse::<f64>().or(Err("easting not a valid decimal number"))?;
+        let northing = captures[4].parse::<f64>().or(Err("northing not a valid decimal number"))?;
+        if !(100000.0..1000000.0).contains(&easting) {
+            return Err("UTM easting must be between 100000 and 999999 metres");
+        }
+        if !(0.0..10000000.0).contains(&northing) {
+            return Err("UTM northing must be between 0 and 9999999 metres");
+        }
+        let (lat, long) = utm_inverse(zone, band >= 'N', easting, northing);
+        return check_lat_long(lat, long);
+    });
+    return Some(result);
+}
+
+/// MGRS reference, e.g. `31UDQ4825111932` or `31U DQ 48251 11932`. Polar (UPS) references are not
+/// supported.
+fn parse_mgrs(s: &str) -> Option<Result<(f64, f64), &'static str>> {
+    let captures = MGRS_REGEX.captures(s)?;
+    let result = parse_utm_zone(&captures[1], &captures[2]).and_then(|(zone, band)| {
+        let (easting_digits, northing_digits) = if captures[6].is_empty() {
+            let digits = &captures[5];
+            if digits.len() % 2 != 0 {
+                return Err("MGRS easting and northing must have the same number of digits");
+            }
+            digits.split_at(digits.len() / 2)
+        } else {
+            (&captures[5], &captures[6])
+        };
+        if easting_digits.len() != northing_digits.len() || easting_digits.len() > 5 {
+            return Err("MGRS easting and northing must have the same number of digits, at most 5");
+        }
+
+        let column = captures[3].to_ascii_uppercase().chars().next().expect("regex ensures a letter");
+        let row = captures[4].to_ascii_uppercase().chars().next().expect("regex ensures a letter");
+
+        // the column letters cycle every three zones
+        let column_letters = match zone % 3 {
+            1 => "ABCDEFGH",
+            2 => "JKLMNPQR",
+            _ => "STUVWXYZ",
+        };
+        let column_ix = column_letters.find(column).ok_or("MGRS column letter is not valid for this zone")?;
+        // the row letters are offset by five in even zones
+        let row_letters = "ABCDEFGHJKLMNPQRSTUV";
+        let row_ix = (row_letters.find(row).expect("regex ensures a valid row letter") + 20 - if zone % 2 == 0 { 5 } else { 0 }) % 20;
+
+        let precision = 10_f64.powi(5 - easting_digits.len() as i32);
+        let easting_offset = if easting_digits.is_empty() { 0.0 } else { easting_digits.parse::<f64>().or(Err("easting not a valid integer"))? };
+        let northing_offset = if northing_digits.is_empty() { 0.0 } else { northing_digits.parse::<f64>().or(Err("northing not a valid integer"))? };
+        let easting = (column_ix + 1) as f64 * 100000.0 + easting_offset * precision + precision / 2.0;
+        let mut northing = row_ix as f64 * 100000.0 + northing_offset * precision + precision / 2.0;
+
+        // the row letters repeat every 2000km, find the repeat which falls within the latitude band
+        let band_ix = UTM_BANDS.find(band).expect("regex ensures a valid band letter");
+        let band_lat = -80.0 + 8.0 * band_ix as f64;
+        let (_, band_northing) = utm_forward(band_lat, utm_central_meridian(zone), zone);
+        let band_northing = (band_northing / 100000.0).floor() * 100000.0;
+        while northing < band_northing {
+            northing += 2000000.0;
+        }
+
+        let (lat, long) = utm_inverse(zone, band >= 'N', easting, northing);
+        return check_lat_long(lat, long);
+    });
+    return Some(result);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LATITUDES: [f64; 14] = [-90.0, -89.999999, -48.876667, -23.550520, -0.5, -0.000001, 0.0, 0.000001, 0.5, 12.345678, 51.454513, 59.999999, 89.999999, 90.0];
+    const LONGITUDES: [f64; 14] = [-180.0, -179.999999, -123.393333, -46.633308, -0.5, -0.000001, 0.0, 0.000001, 0.5, 2.294481, 45.999999, 151.215297, 179.999999, 180.0];
+
+    fn formats() -> Vec<CoordinateFormat> {
+        let notations = [
+            CoordinateNotation::DecimalDegrees,
+            CoordinateNotation::DegreesMinutes,
+            CoordinateNotation::DegreesMinutesSeconds,
+            CoordinateNotation::Iso6709,
+            CoordinateNotation::GeoUri,
+        ];
+        let mut formats = vec![];
+        for notation in notations {
+            for signed in [false, true] {
+                for precision in [None, Some(0), Some(1), Some(4), Some(7)] {
+                    formats.push(CoordinateFormat { notation, signed, precision });
+                }
+            }
+        }
+        return formats;
+    }
+
+    /// Half of the smallest increment of the format, in degrees
+    fn tolerance(format: &CoordinateFormat) -> f64 {
+        let unit = match format.notation {
+            CoordinateNotation::DegreesMinutes => 60.0,
+            CoordinateNotation::DegreesMinutesSeconds => 3600.0,
+            _ => 1.0,
+        };
+        return 0.5 * 10_f64.powi(-(format.precision() as i32)) / unit + 1e-9;
+    }
+
+    #[test]
+    fn round_trip_corpus() {
+        for format in formats() {
+            for lat in LATITUDES {
+                for long in LONGITUDES {
+                    let formatted = format.format_position(lat, long);
+                    let (parsed_lat, parsed_long) = parse_pos_string(&formatted)
+                        .unwrap_or_else(|err| panic!("{:?} failed to parse \"{}\": {}\n{}", format, formatted, err, err.annotate(&formatted)));
+                    let tolerance = tolerance(&format);
+                    assert!((parsed_lat - lat).abs() <= tolerance, "{:?} latitude {} formatted as \"{}\" parsed as {}", format, lat, formatted, parsed_lat);
+                    assert!((parsed_long - long).abs() <= tolerance, "{:?} longitude {} formatted as \"{}\" parsed as {}", format, long, formatted, parsed_long);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn default_format_matches_original_dms() {
+        let format = CoordinateFormat::default();
+        assert_eq!(format.format_position(-48.876667, -123.393333), "48°52'36.00\"S, 123°23'36.00\"W");
+        assert_eq!(format.format_position(51.454513, -2.587910), "51°27'16.25\"N, 2°35'16.48\"W");
+        // 59.999 seconds carries into the minutes rather than printing 60.00
+        assert_eq!(format.format_angle(0.99999999, 'N', 'S'), "1°0'0.00\"N");
+    }
+
+    #[test]
+    fn accepted_notations() {
+        let cases = [
+            ("48°52'36.0\"S, 123°23'36.0\"W", (-48.876667, -123.393333)),
+            ("48deg 52min 36.0sec S, 123deg 23min 36.0sec W", (-48.876667, -123.393333)),
+            ("48d 52m 36.0s S, 123d 23m 36.0s W", (-48.876667, -123.393333)),
+            ("-48d 52m 36.0s N, -123d 23m 36.0s E", (-48.876667, -123.393333)),
+            ("48° 52.6' S, 123° 23.6' W", (-48.876667, -123.393333)),
+            ("48d 52.6mS, 123d 23.6mW", (-48.876667, -123.393333)),
+            ("48.876667 S, 123.393333 W", (-48.876667, -123.393333)),
+            ("-48.876667, -123.393333", (-48.876667, -123.393333)),
+            ("S 48°52'36\", W 123°23'36\"", (-48.876667, -123.393333)),
+            ("123°23'36\"W, 48°52'36\"S", (-48.876667, -123.393333)),
+            ("48°52′36″S 123°23′36″W", (-48.876667, -123.393333)),
+            ("-0°30'N, 0°30'W", (-0.5, -0.5)),
+            ("-0°30', -0°30'", (-0.5, -0.5)),
+            ("+0°30', +0°30'", (0.5, 0.5)),
+            ("90, 180", (90.0, 180.0)),
+            ("IO91", (51.5, -1.0)),
+            ("IO91wm", (51.520833, -0.125)),
+        ];
+        for (input, (lat, long)) in cases {
+            let (parsed_lat, parsed_long) = parse_pos_string(input).unwrap_or_else(|err| panic!("\"{}\" failed to parse: {}", input, err));
+            assert!((parsed_lat - lat).abs() < 1e-6 && (parsed_long - long).abs() < 1e-6, "\"{}\" parsed as {}, {}", input, parsed_lat, parsed_long);
+        }
+    }
+
+    #[test]
+    fn alternative_notations() {
+        // each a position of the Eiffel Tower, within the precision of the notation
+        const EIFFEL_TOWER: (f64, f64) = (48.858333, 2.294444);
+        let cases = [
+            ("+48.858333+002.294444/", 1e-6),
+            ("+485130+0021740/", 1e-6),
+            ("+485130+0021740+330CRSWGS_84/", 1e-6),
+            ("geo:48.858333,2.294444", 1e-6),
+            ("geo:48.858333,2.294444,330;u=10", 1e-6),
+            ("https://www.google.com/maps/@48.858333,2.294444,17z", 1e-6),
+            ("https://www.google.com/maps/search/?api=1&query=48.858333%2C2.294444", 1e-6),
+            ("https://www.openstreetmap.org/?mlat=48.858333&mlon=2.294444#map=17/48.858333/2.294444", 1e-6),
+            ("u09tunquc", 1e-4),
+            ("geohash:U09TUNQUC", 1e-4),
+            ("8FW4V75V+8Q", 1e-4),
+            ("31U 448251 5411932", 2e-4),
+            ("31UDQ4825111932", 2e-4),
+            ("JN18du", 5e-2),
+        ];
+        for (input, tolerance) in cases {
+            let (lat, long) = parse_pos_string(input).unwrap_or_else(|err| panic!("\"{}\" failed to parse: {}", input, err));
+            assert!((lat - EIFFEL_TOWER.0).abs() < tolerance && (long - EIFFEL_TOWER.1).abs() < tolerance, "\"{}\" parsed as {}, {}", input, lat, long);
+        }
+
+        assert_eq!(parse_pos_string("geohash:s").unwrap(), (22.5, 22.5));
+        assert!(parse_pos_string("geohash:bad!").is_err());
+        // words which could be geohashes are only read as one with the prefix
+        for input in ["bed", "s", "gcpvj"] {
+            assert!(parse_pos_string(input).is_err(), "\"{}\" parsed as a position", input);
+        }
+    }
+
+    #[test]
+    fn error_spans() {
+        let cases = [
+            ("48x5, 2", "x"),
+            ("48.5.3, 2", ".3"),
+            ("48°72'N, 2°E", "72"),
+            ("48°52'60\"N, 2°E", "60"),
+            ("48°52.5'36\"N, 2°E", "52.5"),
+            ("95, 2", "95"),
+            ("-95°30', 2", "-95°30'"),
+            ("2, 181", "181"),
+            ("-48°52'S, 2°E", "-"),
+            ("48 N, 12 N", "N"),
+            ("48'N, 2°E", "'"),
+            ("48 S", ""),
+            ("48 S, 2 E extra", "extra"),
+            ("48 S, 2 E 3", "3"),
+            ("48 S, 2 E ?", "?"),
+            ("48é, 2", "é"),
+            ("48mé, 2", "mé"),
+            ("NE", "NE"),
+        ];
+        for (input, expected) in cases {
+            let err = parse_pos_string(input).expect_err(input);
+            assert_eq!(&input[err.span.clone()], expected, "\"{}\": {}", input, err);
+            assert!(!err.msg.contains('{'), "unformatted error message {:?}", err.msg);
+        }
+    }
+
+    #[test]
+    fn annotate_underlines_span() {
+        let input = "48°72'N, 2°E";
+        let err = parse_pos_string(input).unwrap_err();
+        assert_eq!(err.annotate(input), "48°72'N, 2°E\n   ^^");
+    }
+}
@@ -0,0 +1,372 @@
+//! Reading an uncompressed database file, keeping track of where in the file each part was read from so
+//! that errors can say where the file is damaged
+
+use std::{collections::VecDeque, fmt, io::{self, BufRead}, sync::LazyLock};
+use regex::Regex;
+use crate::{format::{self, FormatVersion, RECORD_LEN}, DatabaseReadError, GeoDatabase};
+
+/// The parts of a database file, in the order they appear
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Section {
+    Header,
+    Cities,
+    Countries,
+    Regions,
+    Subregions,
+    Timezones,
+    Features
+}
+
+impl fmt::Display for Section {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Section::Header => "header",
+            Section::Cities => "cities",
+            Section::Countries => "countries",
+            Section::Regions => "regions",
+            Section::Subregions => "sub-regions",
+            Section::Timezones => "timezones",
+            Section::Features => "features"
+        })
+    }
+}
+
+/// Number of bytes either side of an error included in its location's hex dump
+pub(crate) const CONTEXT_LEN: usize = 32;
+
+/// Where in a database file reading failed, offsets of compressed files are into the uncompressed data
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ErrorLocation {
+    pub offset: u64,
+    pub section: Section,
+    /// Index of the city or table entry being read, counting from 0 in each section
+    pub entry: Option<usize>,
+    /// Bytes surrounding `offset`, starting at `context_offset`
+    pub context: Vec<u8>,
+    pub context_offset: u64
+}
+
+impl ErrorLocation {
+    /// `available` are the bytes of the file starting at `available_offset` which are known
+    pub(crate) fn new(offset: u64, section: Section, entry: Option<usize>, available: &[u8], available_offset: u64) -> ErrorLocation {
+        let start = offset.saturating_sub(CONTEXT_LEN as u64).max(available_offset);
+        let end = (offset + CONTEXT_LEN as u64).min(available_offset + available.len() as u64).max(start);
+        let context = available[(start - available_offset) as usize..(end - available_offset) as usize].to_vec();
+        ErrorLocation { offset, section, entry, context, context_offset: start }
+    }
+
+    /// The surrounding bytes as hex and ASCII, 16 to a row, with the byte at `offset` marked beneath
+    pub fn hex_dump(self: &Self) -> String {
+        return hex_dump(&self.context, self.context_offset, self.offset);
+    }
+}
+
+impl fmt::Display for ErrorLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "byte {} in the {} section", self.offset, self.section)?;
+        if let Some(entry) = self.entry {
+            write!(f, " (entry {entry})")?;
+        }
+        Ok(())
+    }
+}
+
+/// `context`, which starts at `context_offset` in the file, as hex and ASCII, 16 to a row, with the byte at
+/// `offset` marked beneath
+pub(crate) fn hex_dump(context: &[u8], context_offset: u64, offset: u64) -> String {
+    let mut out = String::new();
+    let first_row = context_offset - context_offset % 16;
+    let end = context_offset + context.len() as u64;
+    let mut row = first_row;
+    while row < end.max(offset + 1) {
+        let mut hex = String::new();
+        let mut ascii = String::new();
+        for pos in row..row + 16 {
+            let byte = pos.checked_sub(context_offset).and_then(|i| context.get(i as usize));
+            match byte {
+                Some(byte) => {
+                    hex.push_str(&format!("{byte:02x} "));
+                    ascii.push(if byte.is_ascii_graphic() || *byte == b' ' { *byte as char } else { '.' });
+                },
+                None => {
+                    hex.push_str("   ");
+                    ascii.push(' ');
+                }
+            }
+        }
+        out.push_str(&format!("{row:08x}  {hex} |{ascii}|\n"));
+        if (row..row + 16).contains(&offset) {
+            let column = (offset - row) as usize;
+            out.push_str(&format!("{}^^\n", " ".repeat(10 + 3 * column)));
+        }
+        row += 16;
+    }
+    return out.trim_end().to_owned();
+}
+
+static HEADER_REGEX: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^Geolocation(\d+\.\d+)\s+(\d+)$").expect("invalid regex pattern"));
+
+/// The format version and number of cities given in the header line
+pub(crate) fn check_header(header: &str) -> Result<(&'static dyn FormatVersion, usize), DatabaseReadError> {
+    let invalid_header = || DatabaseReadError::InvalidHeader { msg: String::from("Expected \"Geolocation x.xx (n)\" where \"x.xx\" is the database version number and \"n\" is the number of cities in the database") };
+    let (_, [version_string, n_cities]) = HEADER_REGEX.captures(header).ok_or_else(invalid_header)?.extract();
+    let n_cities = n_cities.parse().map_err(|_| invalid_header())?;
+
+    let format = format::format_version(version_string).ok_or_else(|| {
+        let supported: Vec<&str> = format::FORMAT_VERSIONS.iter().map(|format| format.version()).collect();
+        DatabaseReadError::UnsupportedVersion { expected: supported.join(" or "), found: String::from(version_string) }
+    })?;
+    return Ok((format, n_cities));
+}
+
+/// The terminator ending each table, the last table of every version is ended by `0,0,0,0,0`
+pub(crate) fn table_terminator(section: Section, format: &dyn FormatVersion) -> [u8; 5] {
+    match section {
+        Section::Countries => [0, 0, 0, 0, 2],
+        Section::Regions => [0, 0, 0, 0, 3],
+        Section::Subregions => [0, 0, 0, 0, 4],
+        Section::Timezones if format.has_feature_table() => [0, 0, 0, 0, 5],
+        _ => [0, 0, 0, 0, 0]
+    }
+}
+
+/// Whether data after the last section looks like further sections, i.e. is empty or ends with a
+/// `0,0,0,0,n,\n` terminator line, rather than garbage
+pub(crate) fn is_sections(data: &[u8]) -> bool {
+    let Some(before_terminator) = data.len().checked_sub(6) else {
+        return data.is_empty();
+    };
+    return data[before_terminator..before_terminator + 4] == [0, 0, 0, 0]
+        && data.ends_with(b"\n")
+        && (before_terminator == 0 || data[before_terminator - 1] == b'\n');
+}
+
+/// Terminator ending the cities
+pub(crate) const CITIES_TERMINATOR: [u8; 6] = [0, 0, 0, 0, 1, 0xA];
+
+struct DatabaseReader<'r> {
+    inner: &'r mut dyn BufRead,
+    /// Number of bytes read so far
+    offset: u64,
+    section: Section,
+    /// Index of the entry being read in the current section
+    entry: Option<usize>,
+    /// The last bytes read, for the context of errors
+    recent: VecDeque<u8>
+}
+
+impl<'r> DatabaseReader<'r> {
+    fn start_section(self: &mut Self, section: Section) {
+        self.section = section;
+        self.entry = Some(0);
+    }
+
+    fn consumed(self: &mut Self, bytes: &[u8]) {
+        self.offset += bytes.len() as u64;
+        self.recent.extend(bytes);
+        let excess = self.recent.len().saturating_sub(2 * CONTEXT_LEN);
+        self.recent.drain(..excess);
+    }
+
+    fn location(self: &mut Self, offset: u64) -> ErrorLocation {
+        let mut available: Vec<u8> = self.recent.iter().copied().collect();
+        let available_offset = self.offset - available.len() as u64;
+        if let Ok(following) = self.inner.fill_buf() {
+            available.extend(following.iter().take(CONTEXT_LEN));
+        }
+        return ErrorLocation::new(offset, self.section, self.entry, &available, available_offset);
+    }
+
+    fn truncated(self: &mut Self) -> DatabaseReadError {
+        DatabaseReadError::Truncated { location: self.location(self.offset) }
+    }
+
+    fn io_error(self: &mut Self, source: io::Error) -> DatabaseReadError {
+        DatabaseReadError::IoError { source, location: Some(self.location(self.offset)) }
+    }
+
+    fn read_exact(self: &mut Self, buf: &mut [u8]) -> Result<(), DatabaseReadError> {
+        let mut filled = 0;
+        while filled < buf.len() {
+            let available = match self.inner.fill_buf() {
+                Ok(available) => available,
+                Err(err) => return Err(self.io_error(err))
+            };
+            if available.is_empty() {
+                return Err(self.truncated());
+            }
+            let len = available.len().min(buf.len() - filled);
+            buf[filled..filled + len].copy_from_slice(&available[..len]);
+            self.inner.consume(len);
+            self.consumed(&buf[filled..filled + len]);
+            filled += len;
+        }
+        return Ok(());
+    }
+
+    /// A newline terminated line without its newline, any other trailing whitespace is kept
+    fn line(self: &mut Self) -> Result<String, DatabaseReadError> {
+        let start = self.offset;
+        let mut bytes = vec![];
+        if let Err(err) = self.inner.read_until(b'\n', &mut bytes) {
+            return Err(self.io_error(err));
+        }
+        self.consumed(&bytes);
+        if bytes.pop() != Some(b'\n') {
+            return Err(self.truncated());
+        }
+        let line = match String::from_utf8(bytes) {
+            Ok(line) => line,
+            Err(err) => {
+                let offset = start + err.utf8_error().valid_up_to() as u64;
+                return Err(DatabaseReadError::InvalidText { location: self.location(offset) });
+            }
+        };
+        return Ok(line);
+    }
+
+    fn table(self: &mut Self, section: Section, format: &dyn FormatVersion) -> Result<Vec<String>, DatabaseReadError> {
+        self.start_section(section);
+        let terminator = table_terminator(section, format);
+        let mut entries = vec![];
+        loop {
+            self.entry = Some(entries.len());
+            let entry = self.line()?;
+            if entry.as_bytes() == terminator {
+                return Ok(entries);
+            }
+            entries.push(entry);
+        }
+    }
+}
+
+pub(crate) fn read_database(reader: &mut dyn BufRead) -> Result<GeoDatabase, DatabaseReadError> {
+    let mut reader = DatabaseReader { inner: reader, offset: 0, section: Section::Header, entry: None, recent: VecDeque::new() };
+    let header_line = reader.line()?;
+    let comment = reader.line()?;
+
+    let (format, n_cities) = check_header(header_line.trim_end())?;
+
+    // cities
+    reader.start_section(Section::Cities);
+    let mut buf = [0; RECORD_LEN];
+    let mut cities = vec![];
+    loop {
+        reader.entry = Some(cities.len());
+        let (buf_start, buf_end) = buf.split_at_mut(CITIES_TERMINATOR.len());
+        reader.read_exact(buf_start)?;
+        if buf_start == CITIES_TERMINATOR {
+            break;
+        }
+        reader.read_exact(buf_end)?;
+
+        let name = reader.line()?;
+        cities.push(format.decode_record(&buf, name));
+    }
+    if cities.len() != n_cities {
+        return Err(DatabaseReadError::CityCountMismatch { expected: n_cities, found: cities.len() });
+    }
+
+    let countries = reader.table(Section::Countries, format)?;
+    let regions = reader.table(Section::Regions, format)?;
+    let subregions = reader.table(Section::Subregions, format)?;
+    let timezones = reader.table(Section::Timezones, format)?;
+
+    // versions without features give every city the same unnamed feature
+    let features = if format.has_feature_table() {
+        reader.table(Section::Features, format)?
+    } else {
+        vec![String::new()]
+    };
+
+    reader.entry = None;
+    let end = reader.offset;
+    let mut extra_sections = vec![];
+    if let Err(err) = reader.inner.read_to_end(&mut extra_sections) {
+        return Err(reader.io_error(err));
+    }
+    if !is_sections(&extra_sections) {
+        // only the start of the garbage is shown, consuming all of it would leave none in the context
+        reader.consumed(&extra_sections[..extra_sections.len().min(CONTEXT_LEN)]);
+        return Err(DatabaseReadError::TrailingData { location: reader.location(end) });
+    }
+
+    Ok(GeoDatabase {
+        comment, cities, countries, regions, subregions, timezones, features, extra_sections, region_codes: vec![]
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CONTEXT_LEN;
+    use crate::{DatabaseReadError, ErrorLocation, GeoDatabase, NewCity, Section};
+
+    fn sample_bytes() -> Vec<u8> {
+        let mut builder = GeoDatabase::builder();
+        builder.comment("sample");
+        builder.add_city(NewCity {
+            name: "Hobart", latitude: -42.87936, longitude: 147.32941, population: 0x225,
+            country_code: "AU", country: "Australia", region: "Tasmania", subregion: "Hobart",
+            timezone: "Australia/Hobart", feature: "PPLA"
+        });
+        let mut bytes = vec![];
+        builder.build().unwrap().write_to(&mut bytes).unwrap();
+        return bytes;
+    }
+
+    #[test]
+    fn truncated_at_every_length() {
+        let bytes = sample_bytes();
+        for len in 0..bytes.len() {
+            match GeoDatabase::read_from(&mut &bytes[..len]) {
+                Err(DatabaseReadError::Truncated { location }) => assert_eq!(location.offset, len as u64),
+                other => panic!("{len}: expected truncated, found {:?}", other.err())
+            }
+        }
+        let err = GeoDatabase::read_from(&mut &bytes[..30]).err();
+        assert!(matches!(err, Some(DatabaseReadError::Truncated { location: ErrorLocation { section: Section::Cities, entry: Some(0), .. } })), "{err:?}");
+    }
+
+    #[test]
+    fn header_count_and_trailing_data() {
+        let bytes = sample_bytes();
+        let miscounted = [b"Geolocation1.03 2".as_slice(), &bytes[17..]].concat();
+        assert!(matches!(GeoDatabase::read_from(&mut &miscounted[..]), Err(DatabaseReadError::CityCountMismatch { expected: 2, found: 1 })));
+
+        let trailing = [bytes.as_slice(), b"extra"].concat();
+        assert!(matches!(GeoDatabase::read_from(&mut &trailing[..]), Err(DatabaseReadError::TrailingData { location }) if location.offset == bytes.len() as u64));
+
+        // the context is around the start of the trailing data however long it is
+        let garbage: Vec<u8> = (0..100).map(|ix| b'a' + ix % 26).collect();
+        let trailing = [bytes.as_slice(), &garbage].concat();
+        match GeoDatabase::read_from(&mut &trailing[..]) {
+            Err(DatabaseReadError::TrailingData { location }) => {
+                assert_eq!(location.offset, bytes.len() as u64);
+                assert_eq!(location.context, trailing[bytes.len() - CONTEXT_LEN..bytes.len() + CONTEXT_LEN]);
+                assert!(location.hex_dump().contains("abcdef"), "{}", location.hex_dump());
+            },
+            other => panic!("expected trailing data, found {:?}", other.err())
+        }
+
+        // further sections ending with a terminator are kept and written back
+        let extra_section = [bytes.as_slice(), b"extra\n\0\0\0\0\x06\n"].concat();
+        let mut rewritten = vec![];
+        GeoDatabase::read_from(&mut &extra_section[..]).expect("extra section is kept").write_to(&mut rewritten).unwrap();
+        assert_eq!(rewritten, extra_section);
+    }
+
+    #[test]
+    fn invalid_text_location() {
+        let mut bytes = sample_bytes();
+        let name_offset = bytes.windows(6).position(|window| window == b"Hobart").unwrap();
+        bytes[name_offset + 2] = 0xff;
+        let err = GeoDatabase::read_from(&mut &bytes[..]).err().expect("name isn't valid UTF-8");
+        let location = err.location().expect("error has a location");
+        assert!(matches!(err, DatabaseReadError::InvalidText { .. }));
+        assert_eq!((location.offset, location.section, location.entry), (name_offset as u64 + 2, Section::Cities, Some(0)));
+
+        let dump = location.hex_dump();
+        let marked_row = dump.lines().position(|line| line.trim() == "^^").expect("offset is marked") - 1;
+        assert!(dump.lines().nth(marked_row).unwrap().contains("48 6f ff 61"), "{dump}");
+    }
+}
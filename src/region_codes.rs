@@ -0,0 +1,209 @@
+//! ISO 3166-2 subdivision codes for regions, e.g. GB-ENG or US-CA
+//!
+//! The database file has nowhere to store these, so they're imported from a table each time they're
+//! needed. Region names are shared between countries in the region table, so a code belongs to a region
+//! within a country.
+
+use std::{collections::BTreeSet, fmt, io::{self, BufRead}};
+use crate::{fold_name, CountryId, GeoDatabase, RegionId};
+
+/// The ISO 3166-2 code of a region within a country
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RegionCode {
+    pub country_ix: CountryId,
+    pub region_ix: RegionId,
+    pub code: String
+}
+
+#[derive(Debug)]
+pub enum RegionCodeError {
+    InvalidCode { line: usize, code: String },
+    MissingName { line: usize, code: String },
+    Io(io::Error)
+}
+
+impl fmt::Display for RegionCodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RegionCodeError::InvalidCode { line, code } => write!(f, "line {line}: invalid ISO 3166-2 code {code:?}, expected a country code, '-' and up to three letters or digits"),
+            RegionCodeError::MissingName { line, code } => write!(f, "line {line}: no region name given for {code}"),
+            RegionCodeError::Io(err) => write!(f, "failed to read region codes: {err}")
+        }
+    }
+}
+
+impl std::error::Error for RegionCodeError {}
+
+impl From<io::Error> for RegionCodeError {
+    fn from(err: io::Error) -> Self {
+        RegionCodeError::Io(err)
+    }
+}
+
+/// A line of a region code table which didn't match any region
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UnmatchedRegionCode {
+    pub line: usize,
+    pub code: String,
+    pub name: String
+}
+
+/// The outcome of importing a region code table
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RegionCodeImport {
+    /// The number of regions given a code
+    pub assigned: usize,
+    pub unmatched: Vec<UnmatchedRegionCode>
+}
+
+/// The code in upper case if it's a valid ISO 3166-2 code
+fn normalise_code(code: &str) -> Option<String> {
+    let (country, subdivision) = code.split_once('-')?;
+    let valid = country.len() == 2 && country.chars().all(|c| c.is_ascii_alphabetic())
+        && (1..=3).contains(&subdivision.len()) && subdivision.chars().all(|c| c.is_ascii_alphanumeric());
+    return valid.then(|| code.to_ascii_uppercase());
+}
+
+impl GeoDatabase {
+    /// The ISO 3166-2 code of a region within a country, if one has been set or imported
+    pub fn region_code<'a>(self: &'a Self, region_ix: RegionId, country_ix: CountryId) -> Option<&'a str> {
+        return self.region_codes.binary_search_by_key(&(country_ix, region_ix), |entry| (entry.country_ix, entry.region_ix)).ok()
+            .map(|ix| self.region_codes[ix].code.as_str());
+    }
+
+    /// Every code which has been set, ordered by country then region
+    pub fn region_codes<'a>(self: &'a Self) -> &'a [RegionCode] {
+        return &self.region_codes;
+    }
+
+    /// Set or, with `None`, clear the ISO 3166-2 code of a region within a country. The code is stored in
+    /// upper case, returns `false` without changing anything if it isn't a valid code.
+    pub fn set_region_code(self: &mut Self, region_ix: RegionId, country_ix: CountryId, code: Option<&str>) -> bool {
+        let position = self.region_codes.binary_search_by_key(&(country_ix, region_ix), |entry| (entry.country_ix, entry.region_ix));
+        match (code, position) {
+            (None, Ok(ix)) => {
+                self.region_codes.remove(ix);
+            },
+            (None, Err(_)) => {},
+            (Some(code), position) => {
+                let Some(code) = normalise_code(code) else {
+                    return false;
+                };
+                match position {
+                    Ok(ix) => self.region_codes[ix].code = code,
+                    Err(ix) => self.region_codes.insert(ix, RegionCode { country_ix, region_ix, code })
+                }
+            }
+        }
+        return true;
+    }
+
+    /// Import region codes from a table with a code and a region name on each line, separated by a tab or,
+    /// if there's no tab, a comma. Blank lines and lines starting with `#` are skipped.
+    ///
+    /// The country is given by the start of the code, e.g. "GB" for "GB-ENG". Region names within that country
+    /// are compared exactly, then ignoring case and diacritics.
+    pub fn import_region_codes(self: &mut Self, reader: &mut dyn BufRead) -> Result<RegionCodeImport, RegionCodeError> {
+        let mut result = RegionCodeImport::default();
+        for (line_ix, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let line_no = line_ix + 1;
+            let (code, name) = line.split_once('\t').or_else(|| line.split_once(','))
+                .map(|(code, name)| (code.trim(), name.trim()))
+                .unwrap_or((line, ""));
+            let Some(normalised) = normalise_code(code) else {
+                return Err(RegionCodeError::InvalidCode { line: line_no, code: code.to_owned() });
+            };
+            if name.is_empty() {
+                return Err(RegionCodeError::MissingName { line: line_no, code: normalised });
+            }
+
+            let matches = self.regions_named(&normalised[..2], name);
+            if matches.is_empty() {
+                result.unmatched.push(UnmatchedRegionCode { line: line_no, code: normalised, name: name.to_owned() });
+                continue;
+            }
+            for (country_ix, region_ix) in matches {
+                self.set_region_code(region_ix, country_ix, Some(&normalised));
+                result.assigned += 1;
+            }
+        }
+        return Ok(result);
+    }
+
+    /// Regions with cities in the country with this code, named `name` exactly or if none are, ignoring case
+    /// and diacritics
+    fn regions_named(self: &Self, country_code: &str, name: &str) -> Vec<(CountryId, RegionId)> {
+        let in_country: BTreeSet<(CountryId, RegionId)> = self.cities.iter()
+            .filter(|city| self.countries[city.country_ix.0][..2].eq_ignore_ascii_case(country_code))
+            .map(|city| (city.country_ix, city.region_ix))
+            .collect();
+        let exact: Vec<_> = in_country.iter().copied().filter(|(_, region_ix)| self.regions[region_ix.0] == name).collect();
+        if !exact.is_empty() {
+            return exact;
+        }
+        let folded = fold_name(name);
+        return in_country.into_iter().filter(|(_, region_ix)| fold_name(&self.regions[region_ix.0]) == folded).collect();
+    }
+
+    /// The codes of a region in every country which has been given one
+    pub(crate) fn codes_of_region<'a>(self: &'a Self, region_ix: RegionId) -> impl Iterator<Item = &'a str> + 'a {
+        self.region_codes.iter().filter(move |entry| entry.region_ix == region_ix).map(|entry| entry.code.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RegionCodeError;
+    use crate::{CityFilter, CountryId, GeoDatabase, NewCity, RegionId, SearchOptions};
+
+    fn sample() -> GeoDatabase {
+        let mut builder = GeoDatabase::builder();
+        let city = NewCity {
+            name: "Wick", latitude: 58.43906, longitude: -3.09424, population: 0x324,
+            country_code: "GB", country: "United Kingdom", region: "Scotland", subregion: "Highland",
+            timezone: "Europe/London", feature: "PPL"
+        };
+        builder.add_city(city);
+        builder.add_city(NewCity { name: "Truro", latitude: 50.26526, longitude: -5.05436, region: "England", subregion: "Cornwall", ..city });
+        builder.add_city(NewCity { name: "Sacramento", latitude: 38.58157, longitude: -121.4944, population: 0x264, country_code: "US", country: "United States",
+            region: "California", subregion: "Sacramento County", timezone: "America/Los_Angeles", feature: "PPLA" });
+        return builder.build().unwrap();
+    }
+
+    #[test]
+    fn imported_codes_are_searchable() {
+        let mut database = sample();
+        let table = "# code\tname\nGB-SCT\tScotland\ngb-eng,ENGLAND\nUS-CA\tCalifornia\nUS-TX\tTexas\n";
+        let result = database.import_region_codes(&mut table.as_bytes()).unwrap();
+        assert_eq!(result.assigned, 3);
+        assert_eq!(result.unmatched.len(), 1);
+        assert_eq!((result.unmatched[0].line, result.unmatched[0].code.as_str()), (5, "US-TX"));
+
+        let england = database.find_matching_regions("GB-ENG", &SearchOptions::default()).unwrap();
+        assert_eq!(england, vec![RegionId(1)]);
+        assert_eq!(database.region_code(england[0], CountryId(0)), Some("GB-ENG"));
+        assert_eq!(database.region_summary(england[0]).code.as_deref(), Some("GB-ENG"));
+
+        let options = SearchOptions::default();
+        assert_eq!(database.find_matching_cities("Truro, gb-eng, GB", &options).unwrap().len(), 1);
+        assert_eq!(database.find_matching_cities("Truro, GB-SCT, GB", &options).unwrap().len(), 0);
+        assert_eq!(database.city_info(database.find_matching_cities("Wick", &options).unwrap()[0]).region_code.as_deref(), Some("GB-SCT"));
+        let filter = CityFilter { region: Some("us-ca".to_owned()), ..CityFilter::default() };
+        assert_eq!(database.filter_cities(&SearchOptions { filter, ..SearchOptions::default() }).len(), 1);
+    }
+
+    #[test]
+    fn invalid_tables_are_rejected() {
+        let mut database = sample();
+        assert!(matches!(database.import_region_codes(&mut "ENG\tEngland\n".as_bytes()), Err(RegionCodeError::InvalidCode { line: 1, .. })));
+        assert!(matches!(database.import_region_codes(&mut "\nGB-ENG\n".as_bytes()), Err(RegionCodeError::MissingName { line: 2, .. })));
+        assert!(!database.set_region_code(RegionId(0), CountryId(0), Some("GB-SCOT")));
+        assert!(database.region_codes().is_empty());
+    }
+}
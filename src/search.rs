@@ -0,0 +1,415 @@
+//! How names given in searches are compared with the names in the database
+
+use std::{fmt, io};
+use regex::{Regex, RegexBuilder};
+use crate::{country::country_matches, CityFilter, SortOrder};
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
+
+/// Options for the `find_matching_*` functions
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SearchOptions {
+    /// Compare names ignoring case and diacritics, so "sao paulo" finds "São Paulo". Exact matches are still
+    /// listed first.
+    pub fold: bool,
+    /// How the name and each qualifier of a search string are interpreted
+    pub syntax: PatternSyntax,
+    /// Only include cities which pass the filter, and sub-regions, regions and countries with such a city
+    pub filter: CityFilter,
+    pub sort: SortOrder
+}
+
+/// How the parts of a search string are interpreted
+///
+/// The parts are separated by commas whatever the syntax. A comma inside `[...]` in a wildcard pattern, or inside
+/// `(...)`, `[...]` or `{...}` in a regular expression, is part of the pattern, so a repetition such as `{1,3}`
+/// can be written. Elsewhere a literal comma can be written as `\x2C` in a regular expression.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PatternSyntax {
+    /// Names are compared as they are searched for by each `find_matching_*` function, e.g. equal for city
+    /// names and contained in the name for qualifiers
+    #[default]
+    Literal,
+    /// Each part is a regular expression which must match somewhere in the name, e.g. " County$"
+    Regex,
+    /// Each part is a wildcard pattern which must match the whole name, `*` matches any text, `?` any single
+    /// character and `[...]` any one of a set of characters, e.g. "Saint *"
+    Glob
+}
+
+/// A part of a search string which isn't a valid pattern
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PatternError {
+    pub pattern: String,
+    pub message: String
+}
+
+impl fmt::Display for PatternError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid pattern \"{}\": {}", self.pattern, self.message)
+    }
+}
+
+impl std::error::Error for PatternError {}
+
+impl From<PatternError> for io::Error {
+    fn from(err: PatternError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidInput, err)
+    }
+}
+
+impl SearchOptions {
+    /// Check a search string has at most `max_parts` parts and each is a valid pattern for `syntax`, without
+    /// searching. The `find_matching_*` functions return the same error.
+    ///
+    /// `max_parts` is 4 for cities, 3 for sub-regions and 2 for regions. Countries, timezones and features are
+    /// searched for by the whole string, so for 1 it isn't split.
+    pub fn check_patterns(self: &Self, query: &str, max_parts: usize) -> Result<(), PatternError> {
+        let parts = if max_parts == 1 { vec![query] } else { split_query(query, self.syntax, max_parts)? };
+        for part in parts {
+            compile_pattern(part.trim(), self)?;
+        }
+        return Ok(());
+    }
+}
+
+/// Split a search string at the commas which separate its parts, see `PatternSyntax`, failing if there are
+/// more than `max_parts`
+pub(crate) fn split_query<'q>(query: &'q str, syntax: PatternSyntax, max_parts: usize) -> Result<Vec<&'q str>, PatternError> {
+    let mut parts = vec![];
+    let mut start = 0;
+    let mut depth = 0_usize;
+    let mut set_depth = 0_usize;
+    let mut escaped = false;
+    for (ix, c) in query.char_indices() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match (syntax, c) {
+            (PatternSyntax::Regex, '\\') => escaped = true,
+            (PatternSyntax::Regex | PatternSyntax::Glob, '[') => set_depth += 1,
+            (PatternSyntax::Regex | PatternSyntax::Glob, ']') => set_depth = set_depth.saturating_sub(1),
+            _ if set_depth > 0 => {},
+            (PatternSyntax::Regex, '(' | '{') => depth += 1,
+            (PatternSyntax::Regex, ')' | '}') => depth = depth.saturating_sub(1),
+            (_, ',') if depth == 0 => {
+                parts.push(&query[start..ix]);
+                start = ix + 1;
+            },
+            _ => {}
+        }
+    }
+    parts.push(&query[start..]);
+
+    if parts.len() > max_parts {
+        return Err(PatternError { pattern: query.to_owned(), message: format!("expected at most {max_parts} comma separated parts, found {}", parts.len()) });
+    }
+    return Ok(parts);
+}
+
+/// Translate a wildcard pattern into an anchored regular expression
+fn glob_to_regex(glob: &str) -> String {
+    let mut regex = String::from("^");
+    let mut in_set = false;
+    for c in glob.chars() {
+        match c {
+            '*' if !in_set => regex.push_str(".*"),
+            '?' if !in_set => regex.push('.'),
+            '[' if !in_set => {
+                in_set = true;
+                regex.push('[');
+            },
+            ']' if in_set => {
+                in_set = false;
+                regex.push(']');
+            },
+            '!' if in_set && regex.ends_with('[') => regex.push('^'),
+            c if in_set && "\\[&~".contains(c) => {
+                regex.push('\\');
+                regex.push(c);
+            },
+            c if in_set => regex.push(c),
+            c => regex.push_str(&regex::escape(&c.to_string()))
+        }
+    }
+    regex.push('$');
+    return regex;
+}
+
+/// The regular expression for a part of a search string, `None` for literal searches
+fn compile_pattern(part: &str, options: &SearchOptions) -> Result<Option<Regex>, PatternError> {
+    let source = match options.syntax {
+        PatternSyntax::Literal => return Ok(None),
+        PatternSyntax::Regex => part.to_owned(),
+        PatternSyntax::Glob => glob_to_regex(part)
+    };
+    return RegexBuilder::new(&source).case_insensitive(options.fold).build()
+        .map(Some)
+        .map_err(|err| PatternError { pattern: part.to_owned(), message: err.to_string() });
+}
+
+/// Default for the lowest similarity, from 0 to 1, of the candidates returned by the `find_similar_*` functions
+pub const DEFAULT_MIN_SIMILARITY: f64 = 0.6;
+
+/// An entry found by a fuzzy search and how similar its name is to the name searched for
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ScoredMatch<T> {
+    pub entry: T,
+    /// From 0 to 1, where 1 is an exact match ignoring case and diacritics
+    pub score: f64
+}
+
+/// Letters which aren't a base letter with a combining mark so survive decomposition, and what to fold them to
+const FOLDED_LETTERS: &[(char, &str)] = &[
+    ('ß', "ss"), ('æ', "ae"), ('œ', "oe"), ('ø', "o"), ('ł', "l"), ('đ', "d"), ('ð', "d"), ('þ', "th"), ('ı', "i"), ('ħ', "h")
+];
+
+/// Normalise a name for comparison ignoring case and diacritics, e.g. "São Paulo" and "SAO PAULO" both fold
+/// to "sao paulo"
+pub fn fold_name(name: &str) -> String {
+    let mut folded = String::with_capacity(name.len());
+    for c in name.nfkd().filter(|c| !is_combining_mark(*c)).flat_map(char::to_lowercase) {
+        match FOLDED_LETTERS.iter().find(|(letter, _)| *letter == c) {
+            Some((_, replacement)) => folded.push_str(replacement),
+            None => folded.push(c)
+        }
+    }
+    return folded;
+}
+
+/// How similar two names are, from 0 to 1, ignoring case and diacritics
+///
+/// One minus the edit distance between the folded names, counting a transposition of adjacent letters as a
+/// single edit, divided by the length of the longer name.
+pub fn similarity(a: &str, b: &str) -> f64 {
+    let a: Vec<char> = fold_name(a).chars().collect();
+    let b: Vec<char> = fold_name(b).chars().collect();
+    let longest = a.len().max(b.len());
+    if longest == 0 {
+        return 1.0;
+    }
+    return 1.0 - edit_distance(&a, &b) as f64 / longest as f64;
+}
+
+/// Optimal string alignment distance, Levenshtein distance which also allows adjacent letters to be swapped
+fn edit_distance(a: &[char], b: &[char]) -> usize {
+    // rows for the prefixes of `a` of length i - 2, i - 1 and i
+    let mut before_previous: Vec<usize> = vec![0; b.len() + 1];
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+    for i in 1..=a.len() {
+        current[0] = i;
+        for j in 1..=b.len() {
+            let substitution = usize::from(a[i - 1] != b[j - 1]);
+            current[j] = (previous[j] + 1).min(current[j - 1] + 1).min(previous[j - 1] + substitution);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                current[j] = current[j].min(before_previous[j - 2] + 1);
+            }
+        }
+        std::mem::swap(&mut before_previous, &mut previous);
+        std::mem::swap(&mut previous, &mut current);
+    }
+    return previous[b.len()];
+}
+
+/// Candidates ordered by score then population, both highest first
+pub(crate) fn best_first<T>(mut candidates: Vec<(ScoredMatch<T>, f64)>) -> Vec<ScoredMatch<T>> {
+    candidates.sort_by(|(a, a_population), (b, b_population)| {
+        b.score.total_cmp(&a.score).then(b_population.total_cmp(a_population))
+    });
+    return candidates.into_iter().map(|(candidate, _)| candidate).collect();
+}
+
+/// A name, or part of a name, being searched for
+pub(crate) struct SearchTerm<'q> {
+    text: &'q str,
+    folded: Option<String>,
+    pattern: Option<Regex>
+}
+
+impl<'q> SearchTerm<'q> {
+    pub(crate) fn new(text: &'q str, options: &SearchOptions) -> Result<SearchTerm<'q>, PatternError> {
+        let pattern = compile_pattern(text, options)?;
+        return Ok(SearchTerm { text, folded: options.fold.then(|| fold_name(text)), pattern });
+    }
+
+    /// Whether `name` is exactly the term, byte for byte
+    pub(crate) fn is_exact(self: &Self, name: &str) -> bool {
+        return name == self.text;
+    }
+
+    pub(crate) fn equals(self: &Self, name: &str) -> bool {
+        return self.compare(name, |name, term| name == term);
+    }
+
+    pub(crate) fn contained_in(self: &Self, name: &str) -> bool {
+        return self.compare(name, |name, term| name.contains(term));
+    }
+
+    pub(crate) fn prefix_of(self: &Self, name: &str) -> bool {
+        return self.compare(name, |name, term| name.starts_with(term));
+    }
+
+    /// Whether a country table entry matches, a literal term must be the code or whole name (see
+    /// `country_matches`) and a pattern must match one of them
+    pub(crate) fn matches_country(self: &Self, country: &str) -> bool {
+        if self.pattern.is_none() {
+            return country_matches(self.text, country, self.folded.is_some());
+        }
+        return self.contained_in(&country[..2]) || self.contained_in(&country[2..]);
+    }
+
+    /// Whether `code`, an ISO 3166-2 code, is the term ignoring case or matches its pattern
+    pub(crate) fn is_code(self: &Self, code: Option<&str>) -> bool {
+        let Some(code) = code else {
+            return false;
+        };
+        return match &self.pattern {
+            Some(pattern) => pattern.is_match(code),
+            None => code.eq_ignore_ascii_case(self.text)
+        };
+    }
+
+    /// See `similarity`
+    pub(crate) fn similarity(self: &Self, name: &str) -> f64 {
+        return similarity(self.text, name);
+    }
+
+    /// Compare `name` with the term using `compare`, or match it against the term's pattern
+    fn compare(self: &Self, name: &str, compare: fn(&str, &str) -> bool) -> bool {
+        if let Some(pattern) = &self.pattern {
+            return pattern.is_match(name) || (self.folded.is_some() && pattern.is_match(&fold_name(name)));
+        }
+        match &self.folded {
+            None => compare(name, self.text),
+            Some(folded) => compare(name, self.text) || compare(&fold_name(name), folded)
+        }
+    }
+}
+
+/// Matching entries with those which matched exactly first, otherwise keeping the order of the database
+pub(crate) fn exact_first<T>(mut matches: Vec<(T, bool)>) -> Vec<T> {
+    matches.sort_by_key(|(_, exact)| !exact);
+    return matches.into_iter().map(|(entry, _)| entry).collect();
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use super::{fold_name, glob_to_regex, similarity, split_query, PatternSyntax, SearchOptions};
+    use crate::{CityFilter, CityId, CoordinateFormat, GeoDatabase, NewCity};
+
+    #[test]
+    fn folding() {
+        assert_eq!(fold_name("São Paulo"), "sao paulo");
+        assert_eq!(fold_name("SAO PAULO"), "sao paulo");
+        // decomposed input folds the same as precomposed
+        assert_eq!(fold_name("Sa\u{303}o Paulo"), "sao paulo");
+        assert_eq!(fold_name("Łódź"), "lodz");
+        assert_eq!(fold_name("Straße"), "strasse");
+    }
+
+    #[test]
+    fn folded_search_ranks_exact_matches_first() {
+        let mut builder = GeoDatabase::builder();
+        for name in ["Sao Paulo", "São Paulo"] {
+            builder.add_city(NewCity {
+                name, latitude: -23.5475, longitude: -46.63611, population: 0x317,
+                country_code: "BR", country: "Brazil", region: "São Paulo", subregion: "São Paulo",
+                timezone: "America/Sao_Paulo", feature: "PPLA"
+            });
+        }
+        let database = builder.build().unwrap();
+        let folded = SearchOptions { fold: true, ..SearchOptions::default() };
+
+        assert_eq!(database.find_matching_cities("são paulo", &SearchOptions::default()).unwrap(), vec![]);
+        assert_eq!(database.find_matching_cities("São Paulo", &folded).unwrap(), vec![CityId(1), CityId(0)]);
+        assert_eq!(database.find_matching_cities("são paulo, sao paulo, brazil", &folded).unwrap(), vec![CityId(0), CityId(1)]);
+        assert_eq!(database.find_matching_regions("SAO PAULO", &folded).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn similarity_scores() {
+        assert_eq!(similarity("Zürich", "zurich"), 1.0);
+        // one transposition or deletion in nine letters
+        assert_eq!(similarity("Edinburgh", "Edinbrugh"), 1.0 - 1.0 / 9.0);
+        assert_eq!(similarity("Marseille", "Marseile"), 1.0 - 1.0 / 9.0);
+        assert!(similarity("Marseille", "Munich") < 0.5);
+        assert_eq!(similarity("", ""), 1.0);
+    }
+
+    #[test]
+    fn similar_cities_by_score_then_population() {
+        let mut builder = GeoDatabase::builder();
+        for (name, population) in [("Bergen", 0x125), ("Bergan", 0x113), ("Berlin", 0x366), ("Bregen", 0x244)] {
+            builder.add_city(NewCity {
+                name, latitude: 60.39299, longitude: 5.32415, population,
+                country_code: "NO", country: "Norway", region: "Vestland", subregion: "Bergen",
+                timezone: "Europe/Oslo", feature: "PPLA"
+            });
+        }
+        let database = builder.build().unwrap();
+        let found: Vec<(CityId, f64)> = database.find_similar_cities("bergen, norway", 0.6, &CityFilter::default()).unwrap().into_iter()
+            .map(|candidate| (candidate.entry, candidate.score)).collect();
+        // Bregen is a transposition away so scores the same as Bergan, but has the larger population
+        assert_eq!(found, vec![(CityId(0), 1.0), (CityId(3), 1.0 - 1.0 / 6.0), (CityId(1), 1.0 - 1.0 / 6.0), (CityId(2), 1.0 - 2.0 / 6.0)]);
+        assert!(database.find_similar_cities("bergen, sweden", 0.6, &CityFilter::default()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn patterns() {
+        assert_eq!(glob_to_regex("Saint *"), "^Saint .*$");
+        assert_eq!(glob_to_regex("St. [!a-c]?"), "^St\\. [^a-c].$");
+
+        let mut builder = GeoDatabase::builder();
+        let city = NewCity {
+            name: "Saint Paul", latitude: 44.94441, longitude: -93.09327, population: 0x305,
+            country_code: "US", country: "United States", region: "Minnesota", subregion: "Ramsey County",
+            timezone: "America/Chicago", feature: "PPLA"
+        };
+        builder.add_city(city);
+        builder.add_city(NewCity { name: "Saint Cloud", subregion: "Stearns County", ..city });
+        builder.add_city(NewCity { name: "Saint-Denis", country_code: "FR", country: "France", region: "Île-de-France", subregion: "Seine-Saint-Denis", ..city });
+        let database = builder.build().unwrap();
+        let glob = SearchOptions { syntax: PatternSyntax::Glob, ..SearchOptions::default() };
+        let regex = SearchOptions { syntax: PatternSyntax::Regex, ..SearchOptions::default() };
+
+        assert_eq!(database.find_matching_cities("Saint *", &glob).unwrap(), vec![CityId(0), CityId(1)]);
+        assert_eq!(database.find_matching_cities("Saint*, U?", &glob).unwrap(), vec![CityId(0), CityId(1)]);
+        assert_eq!(database.find_matching_cities("saint*, ile-de-*, fr", &SearchOptions { fold: true, ..glob.clone() }).unwrap(), vec![CityId(2)]);
+        assert_eq!(database.find_matching_subregions(" County$", &regex).unwrap().len(), 2);
+        assert_eq!(database.find_matching_countries("^Fr", &regex).unwrap().len(), 1);
+        assert!(regex.check_patterns("Saint (, US", 4).is_err());
+        assert!(regex.check_patterns("Saint.*, US", 4).is_ok());
+
+        // only commas outside brackets separate the parts of a pattern
+        assert_eq!(split_query("Sa(i|y)nt{1,2} .*, [,U]S", PatternSyntax::Regex, 4).unwrap(), ["Sa(i|y)nt{1,2} .*", " [,U]S"]);
+        assert_eq!(split_query(r"St\(, US", PatternSyntax::Regex, 4).unwrap(), [r"St\(", " US"]);
+        assert_eq!(split_query("Saint [,P]*, U?", PatternSyntax::Glob, 4).unwrap(), ["Saint [,P]*", " U?"]);
+        assert_eq!(split_query("Saint Paul {1,2}", PatternSyntax::Literal, 4).unwrap(), ["Saint Paul {1", "2}"]);
+        assert_eq!(database.find_matching_cities("Saint [CP][a-z]{3,4}, U.", &regex).unwrap(), vec![CityId(0), CityId(1)]);
+
+        // too many parts is an error for every syntax
+        for options in [&SearchOptions::default(), &glob, &regex] {
+            assert!(options.check_patterns("a,b,c,d,e", 4).is_err());
+            assert!(options.check_patterns("a,b,c,d", 4).is_ok());
+            assert!(options.check_patterns("a,b", 1).is_ok());
+            assert!(database.find_matching_cities("a,b,c,d,e", options).unwrap_err().message.contains("at most 4"));
+            assert!(database.find_matching_subregions("a,b,c,d", options).is_err());
+            assert!(database.find_matching_regions("a,b,c", options).is_err());
+        }
+        assert!(database.find_similar_cities("a,b,c,d,e", 0.6, &CityFilter::default()).is_err());
+        assert!(database.find_similar_subregions("a,b,c,d", 0.6, &CityFilter::default()).is_err());
+        assert!(database.find_similar_regions("a,b,c", 0.6, &CityFilter::default()).is_err());
+
+        // invalid patterns are reported rather than panicking
+        assert_eq!(database.find_matching_cities("Saint.*, (US", &regex).unwrap_err().pattern, "(US");
+        assert!(database.find_matching_subregions("[Rr]amsey, Minnesota, *)", &regex).is_err());
+        assert!(database.find_matching_regions("[Mm", &glob).is_err());
+        assert!(database.find_matching_countries("+", &regex).is_err());
+        assert!(database.find_matching_timezones("America/(", &regex).is_err());
+        assert!(database.find_matching_features("PPL[", &glob).is_err());
+        let err = database.write_matching_cities(&mut vec![], "(", &regex, 10, &CoordinateFormat::default()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+}
@@ -0,0 +1,116 @@
+//! Checks applied when deserialising a `GeoDatabase` with the `serde` feature
+
+use crate::{builder::{check_city, check_table_sizes}, CityEntry, DatabaseBuildError, GeoDatabase, RegionCode};
+
+/// The fields of a `GeoDatabase` as serialised, before checking the cities can be written to a file
+#[derive(serde::Deserialize)]
+pub(crate) struct GeoDatabaseTables {
+    comment: String,
+    cities: Vec<CityEntry>,
+    countries: Vec<String>,
+    regions: Vec<String>,
+    subregions: Vec<String>,
+    timezones: Vec<String>,
+    features: Vec<String>,
+    #[serde(default)]
+    extra_sections: Vec<u8>,
+    #[serde(default)]
+    region_codes: Vec<RegionCode>
+}
+
+impl TryFrom<GeoDatabaseTables> for GeoDatabase {
+    type Error = DatabaseBuildError;
+
+    fn try_from(tables: GeoDatabaseTables) -> Result<Self, Self::Error> {
+        for country in &tables.countries {
+            if !country.get(..2).is_some_and(|code| code.chars().all(|c| c.is_ascii_alphabetic())) {
+                return Err(DatabaseBuildError::InvalidCountryCode { code: country.chars().take(2).collect() });
+            }
+        }
+        let sizes = [tables.countries.len(), tables.regions.len(), tables.subregions.len(), tables.timezones.len(), tables.features.len()];
+        check_table_sizes(sizes)?;
+        for city in &tables.cities {
+            check_city(city, sizes)?;
+        }
+
+        let mut region_codes = tables.region_codes;
+        for entry in &region_codes {
+            if entry.country_ix.0 >= tables.countries.len() || entry.region_ix.0 >= tables.regions.len() {
+                return Err(DatabaseBuildError::InvalidReference { city: entry.code.clone(), table: "regions" });
+            }
+        }
+        region_codes.sort_by_key(|entry| (entry.country_ix, entry.region_ix));
+        region_codes.dedup_by_key(|entry| (entry.country_ix, entry.region_ix));
+
+        Ok(GeoDatabase {
+            comment: tables.comment,
+            cities: tables.cities,
+            countries: tables.countries,
+            regions: tables.regions,
+            subregions: tables.subregions,
+            timezones: tables.timezones,
+            features: tables.features,
+            extra_sections: tables.extra_sections,
+            region_codes
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{CityId, GeoDatabase, NewCity};
+
+    fn sample() -> GeoDatabase {
+        let mut builder = GeoDatabase::builder();
+        builder.comment("sample");
+        builder.add_city(NewCity {
+            name: "Wellington", latitude: -41.28664, longitude: 174.77557, population: 0x215,
+            country_code: "NZ", country: "New Zealand", region: "Wellington", subregion: "Wellington City",
+            timezone: "Pacific/Auckland", feature: "PPLC"
+        });
+        builder.build().expect("sample database is valid")
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let database = sample();
+        let json = serde_json::to_string(&database).unwrap();
+        let read: GeoDatabase = serde_json::from_str(&json).unwrap();
+        assert_eq!(read.city(CityId(0)), database.city(CityId(0)));
+        assert_eq!(serde_json::to_string(&read).unwrap(), json);
+
+        let info = database.city(CityId(0)).to_info();
+        let info_json = serde_json::to_string(&info).unwrap();
+        assert_eq!(serde_json::from_str::<crate::CityInfo>(&info_json).unwrap(), info);
+    }
+
+    /// The sample as JSON with `from` replaced by `to`, which must be rejected
+    fn rejected(from: &str, to: &str) -> String {
+        let json = serde_json::to_string(&sample()).unwrap();
+        assert!(json.contains(from), "{from} not in {json}");
+        let err = serde_json::from_str::<GeoDatabase>(&json.replace(from, to)).err().unwrap_or_else(|| panic!("{to} accepted"));
+        return err.to_string();
+    }
+
+    #[test]
+    fn rejects_unwritable_cities() {
+        assert!(rejected("\"latitude\":-41.28664", "\"latitude\":90.5").contains("out of range"));
+        assert!(rejected("\"longitude\":174.77557", "\"longitude\":-180.5").contains("out of range"));
+        assert!(rejected("\"population\":533", "\"population\":4095").contains("invalid population"));
+        assert!(rejected("\"name\":\"Wellington\"", "\"name\":\"Wel\\nlington\"").contains("line breaks"));
+
+        // indices past the end of their record fields, with tables large enough for them
+        let large_table = |size: usize| (0..size).map(|ix| format!("\"T{ix}\"")).collect::<Vec<_>>().join(",");
+        assert!(rejected("\"timezone_ix\":0", "\"timezone_ix\":512").contains("timezones"));
+        assert!(rejected("\"timezones\":[\"Pacific/Auckland\"]", &format!("\"timezones\":[{}]", large_table(513))).contains("too many timezones"));
+        assert!(rejected("\"countries\":[\"NZNew Zealand\"]", &format!("\"countries\":[{}]", large_table(257).replace("\"T", "\"XXT"))).contains("too many countries"));
+        assert!(rejected("\"regions\":[\"Wellington\"]", &format!("\"regions\":[{}]", large_table(4097))).contains("too many regions"));
+    }
+
+    #[test]
+    fn rejects_dangling_reference() {
+        let json = serde_json::to_string(&sample()).unwrap().replace("\"timezone_ix\":0", "\"timezone_ix\":1");
+        let err = serde_json::from_str::<GeoDatabase>(&json).err().expect("timezone 1 doesn't exist");
+        assert!(err.to_string().contains("timezones"), "{err}");
+    }
+}
@@ -0,0 +1,569 @@
+//! The UTC offset and daylight saving time of a timezone at an instant, read from the compiled zone files
+//! (TZif, RFC 8536) in a `zoneinfo` directory
+
+use std::{env, fmt, fs, io, path::{Path, PathBuf}};
+use byteorder::{NetworkEndian, ReadBytesExt};
+use regex::Regex;
+use crate::{CityId, GeoDatabase};
+
+const SECONDS_PER_DAY: i64 = 86400;
+/// The Gregorian calendar, including weekdays, repeats every 400 years
+const SECONDS_PER_CYCLE: i64 = 146097 * SECONDS_PER_DAY;
+/// 0000-01-01 00:00:00 and 9999-12-31 23:59:59 UTC, the range of years with four digits
+const MIN_TIMESTAMP: i64 = -62167219200;
+const MAX_TIMESTAMP: i64 = 253402300799;
+
+/// The `zoneinfo` directory given by the `TZDIR` environment variable, or "/usr/share/zoneinfo"
+pub fn default_zoneinfo_dir() -> PathBuf {
+    return env::var_os("TZDIR").map_or_else(|| PathBuf::from("/usr/share/zoneinfo"), PathBuf::from);
+}
+
+#[derive(Debug)]
+pub enum TimeZoneError {
+    /// There's no zone file for the timezone
+    UnknownTimezone { name: String },
+    InvalidZoneFile { name: String, msg: &'static str },
+    Io { path: PathBuf, err: io::Error }
+}
+
+impl fmt::Display for TimeZoneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TimeZoneError::UnknownTimezone { name } => write!(f, "no zone file for timezone \"{name}\""),
+            TimeZoneError::InvalidZoneFile { name, msg } => write!(f, "invalid zone file for {name}: {msg}"),
+            TimeZoneError::Io { path, err } => write!(f, "failed to read {}: {err}", path.display())
+        }
+    }
+}
+
+impl std::error::Error for TimeZoneError {}
+
+/// The offset from UTC of local time, positive east of Greenwich
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UtcOffset {
+    pub seconds: i32,
+    pub is_dst: bool,
+    /// e.g. "BST", or a number such as "+0530" where there's no abbreviation in use
+    pub abbreviation: String
+}
+
+impl UtcOffset {
+    /// The offset as "+HH:MM", as used by the EXIF OffsetTime tags, any seconds are dropped
+    pub fn to_offset_string(self: &Self) -> String {
+        let sign = if self.seconds < 0 { '-' } else { '+' };
+        let minutes = self.seconds.unsigned_abs() / 60;
+        return format!("{}{:02}:{:02}", sign, minutes / 60, minutes % 60);
+    }
+}
+
+impl fmt::Display for UtcOffset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_offset_string())
+    }
+}
+
+/// The offsets which give a wall clock time, usually one but two when clocks go back and none when they go
+/// forward
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LocalOffset {
+    Unique(UtcOffset),
+    /// The time happens twice, `earlier` is the offset of its first occurrence
+    Ambiguous { earlier: UtcOffset, later: UtcOffset },
+    /// The time is skipped, `before` and `after` are the offsets either side of the gap
+    Skipped { before: UtcOffset, after: UtcOffset }
+}
+
+/// A day of the year in a POSIX TZ rule
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum RuleDay {
+    /// `Jn`, 1 to 365 where February 29th is never counted
+    Julian(i64),
+    /// `n`, 0 to 365 counting February 29th
+    ZeroBased(i64),
+    /// `Mm.w.d`, day `d` (0 is Sunday) of week `w` (5 is the last) of month `m`
+    MonthWeekDay { month: u32, week: i64, weekday: i64 }
+}
+
+impl RuleDay {
+    /// Days since 1970-01-01
+    fn days(self: &Self, year: i64) -> i64 {
+        let new_year = days_from_civil(year, 1, 1);
+        match *self {
+            RuleDay::Julian(n) => new_year + n - 1 + i64::from(is_leap_year(year) && n >= 60),
+            RuleDay::ZeroBased(n) => new_year + n,
+            RuleDay::MonthWeekDay { month, week, weekday } => {
+                let first = days_from_civil(year, month, 1);
+                let mut day = (weekday - weekday_of(first)).rem_euclid(7) + (week - 1) * 7;
+                while day >= i64::from(days_in_month(year, month)) {
+                    day -= 7;
+                }
+                first + day
+            }
+        }
+    }
+}
+
+/// Daylight saving time in a POSIX TZ string, with when it starts and ends in seconds after midnight local time
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct DstRule {
+    offset: UtcOffset,
+    start: (RuleDay, i64),
+    end: (RuleDay, i64)
+}
+
+/// A POSIX TZ string such as "GMT0BST,M3.5.0/1,M10.5.0", which gives the offsets after the last transition
+/// of a zone file
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct PosixTz {
+    std: UtcOffset,
+    dst: Option<DstRule>
+}
+
+impl PosixTz {
+    fn parse(tz: &str) -> Option<PosixTz> {
+        let mut rest = tz;
+        let std_name = parse_tz_name(&mut rest)?;
+        let std_seconds = -parse_tz_time(&mut rest, 24)?;
+        let std = UtcOffset { seconds: i32::try_from(std_seconds).ok()?, is_dst: false, abbreviation: std_name };
+        if rest.is_empty() {
+            return Some(PosixTz { std, dst: None });
+        }
+
+        let dst_name = parse_tz_name(&mut rest)?;
+        let dst_seconds = if rest.is_empty() || rest.starts_with(',') { std_seconds + 3600 } else { -parse_tz_time(&mut rest, 24)? };
+        let offset = UtcOffset { seconds: i32::try_from(dst_seconds).ok()?, is_dst: true, abbreviation: dst_name };
+        // the rules of the United States are the default
+        let rules = if rest.is_empty() { ",M3.2.0,M11.1.0" } else { rest };
+        let mut rules = rules.strip_prefix(',')?;
+        let start = parse_tz_rule(&mut rules)?;
+        let mut rules = rules.strip_prefix(',')?;
+        let end = parse_tz_rule(&mut rules)?;
+        if !rules.is_empty() {
+            return None;
+        }
+        return Some(PosixTz { std, dst: Some(DstRule { offset, start, end }) });
+    }
+
+    fn offset_at(self: &Self, time: i64) -> UtcOffset {
+        let Some(dst) = &self.dst else {
+            return self.std.clone();
+        };
+        // the rules are the same every 400 years, and a time within a cycle of 1970 can't overflow
+        let time = time.rem_euclid(SECONDS_PER_CYCLE);
+        let (year, _, _) = civil_from_days((time + i64::from(self.std.seconds)).div_euclid(SECONDS_PER_DAY));
+        let start = dst.start.0.days(year) * SECONDS_PER_DAY + dst.start.1 - i64::from(self.std.seconds);
+        let end = dst.end.0.days(year) * SECONDS_PER_DAY + dst.end.1 - i64::from(dst.offset.seconds);
+        let in_dst = if start < end {
+            start <= time && time < end
+        } else {
+            // daylight saving time spans the new year, as in the southern hemisphere
+            !(end <= time && time < start)
+        };
+        return if in_dst { dst.offset.clone() } else { self.std.clone() };
+    }
+}
+
+/// An abbreviation, either letters or quoted with angle brackets, e.g. "<+0530>"
+fn parse_tz_name(rest: &mut &str) -> Option<String> {
+    let (name, after) = if let Some(quoted) = rest.strip_prefix('<') {
+        let end = quoted.find('>')?;
+        (&quoted[..end], &quoted[end + 1..])
+    } else {
+        let end = rest.find(|c: char| !c.is_ascii_alphabetic()).unwrap_or(rest.len());
+        (&rest[..end], &rest[end..])
+    };
+    if name.len() < 3 {
+        return None;
+    }
+    *rest = after;
+    return Some(name.to_owned());
+}
+
+/// `[+-]hh[:mm[:ss]]` in seconds, hours may be up to `max_hours`
+fn parse_tz_time(rest: &mut &str, max_hours: i64) -> Option<i64> {
+    let end = rest.find(|c: char| !(c.is_ascii_digit() || c == ':' || c == '+' || c == '-')).unwrap_or(rest.len());
+    let (text, after) = rest.split_at(end);
+    let (sign, text) = match text.strip_prefix('-') {
+        Some(text) => (-1, text),
+        None => (1, text.strip_prefix('+').unwrap_or(text))
+    };
+    let parts: Vec<i64> = text.split(':').map(|part| part.parse().ok()).collect::<Option<_>>()?;
+    let valid = matches!(parts.len(), 1..=3) && parts[0] <= max_hours && parts[1..].iter().all(|part| *part < 60);
+    if !valid {
+        return None;
+    }
+    *rest = after;
+    return Some(sign * parts.iter().zip([3600, 60, 1]).map(|(part, unit)| part * unit).sum::<i64>());
+}
+
+/// `date[/time]` where the time, 02:00 by default, may be negative or more than a day as in RFC 8536
+fn parse_tz_rule(rest: &mut &str) -> Option<(RuleDay, i64)> {
+    let end = rest.find(['/', ',']).unwrap_or(rest.len());
+    let (date, after) = rest.split_at(end);
+    let day = if let Some(n) = date.strip_prefix('J') {
+        RuleDay::Julian(n.parse().ok().filter(|n| (1..=365).contains(n))?)
+    } else if let Some(mwd) = date.strip_prefix('M') {
+        let fields: Vec<i64> = mwd.split('.').map(|field| field.parse().ok()).collect::<Option<_>>()?;
+        match fields.as_slice() {
+            [month @ 1..=12, week @ 1..=5, weekday @ 0..=6] => RuleDay::MonthWeekDay { month: *month as u32, week: *week, weekday: *weekday },
+            _ => return None
+        }
+    } else {
+        RuleDay::ZeroBased(date.parse().ok().filter(|n| (0..=365).contains(n))?)
+    };
+    *rest = after;
+    let time = match rest.strip_prefix('/') {
+        Some(time) => {
+            *rest = time;
+            parse_tz_time(rest, 167)?
+        },
+        None => 7200
+    };
+    return Some((day, time));
+}
+
+/// The offsets of a timezone over time, read from its zone file
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TimeZone {
+    name: String,
+    /// The times at which the offset changes, in seconds since 1970 UTC, and the index of the offset after each
+    transitions: Vec<(i64, usize)>,
+    offsets: Vec<UtcOffset>,
+    /// The rule for times after the last transition
+    footer: Option<PosixTz>
+}
+
+impl TimeZone {
+    /// Read the zone file of timezone `name`, e.g. "Europe/London", from a `zoneinfo` directory
+    pub fn load(zoneinfo: &Path, name: &str) -> Result<TimeZone, TimeZoneError> {
+        let unknown = || TimeZoneError::UnknownTimezone { name: name.to_owned() };
+        let escapes = name.split('/').any(|part| part.is_empty() || part == "." || part == "..") || name.contains('\\');
+        if escapes {
+            return Err(unknown());
+        }
+        let path = zoneinfo.join(name);
+        let data = match fs::read(&path) {
+            Ok(data) => data,
+            Err(err) if matches!(err.kind(), io::ErrorKind::NotFound | io::ErrorKind::NotADirectory) => return Err(unknown()),
+            Err(err) if err.kind() == io::ErrorKind::IsADirectory => return Err(unknown()),
+            Err(err) => return Err(TimeZoneError::Io { path, err })
+        };
+        return TimeZone::parse(name, &data);
+    }
+
+    /// Parse the contents of a zone file, version 1 files are supported but only give offsets up to 2038
+    pub fn parse(name: &str, data: &[u8]) -> Result<TimeZone, TimeZoneError> {
+        let invalid = |msg| TimeZoneError::InvalidZoneFile { name: name.to_owned(), msg };
+        let truncated = |_| invalid("the file is truncated");
+        let mut reader = data;
+
+        let (mut version, mut counts) = read_tzif_header(&mut reader).ok_or_else(|| invalid("not a TZif file"))?;
+        let mut time_len = 4;
+        if version >= b'2' {
+            // skip the data with 32 bit times, which is followed by a second header and the same data with 64 bit times
+            let len = tzif_data_len(&counts, 4);
+            reader = reader.get(len..).ok_or_else(|| invalid("the file is truncated"))?;
+            (version, counts) = read_tzif_header(&mut reader).ok_or_else(|| invalid("second header is missing"))?;
+            time_len = 8;
+        }
+        let [is_ut_count, is_std_count, leap_count, time_count, type_count, char_count] = counts;
+        if type_count == 0 {
+            return Err(invalid("no local time types"));
+        }
+
+        let mut times = Vec::with_capacity(time_count);
+        for _ in 0..time_count {
+            times.push(if time_len == 8 { reader.read_i64::<NetworkEndian>().map_err(truncated)? } else { i64::from(reader.read_i32::<NetworkEndian>().map_err(truncated)?) });
+        }
+        let mut type_indices = Vec::with_capacity(time_count);
+        for _ in 0..time_count {
+            type_indices.push(usize::from(reader.read_u8().map_err(truncated)?));
+        }
+        let mut types = Vec::with_capacity(type_count);
+        for _ in 0..type_count {
+            let seconds = reader.read_i32::<NetworkEndian>().map_err(truncated)?;
+            let is_dst = reader.read_u8().map_err(truncated)? != 0;
+            let abbreviation_ix = usize::from(reader.read_u8().map_err(truncated)?);
+            types.push((seconds, is_dst, abbreviation_ix));
+        }
+        let chars = reader.get(..char_count).ok_or_else(|| invalid("the file is truncated"))?;
+        reader = &reader[char_count..];
+
+        if type_indices.iter().any(|ix| *ix >= type_count) {
+            return Err(invalid("a transition refers to a local time type which doesn't exist"));
+        }
+        let mut offsets = Vec::with_capacity(type_count);
+        for (seconds, is_dst, abbreviation_ix) in types {
+            let abbreviation = chars.get(abbreviation_ix..).ok_or_else(|| invalid("an abbreviation index is out of range"))?;
+            let end = abbreviation.iter().position(|c| *c == 0).unwrap_or(abbreviation.len());
+            offsets.push(UtcOffset { seconds, is_dst, abbreviation: String::from_utf8_lossy(&abbreviation[..end]).into_owned() });
+        }
+
+        let mut footer = None;
+        if version >= b'2' {
+            let len = leap_count * (time_len + 4) + is_std_count + is_ut_count;
+            let text = reader.get(len..).ok_or_else(|| invalid("the file is truncated"))?;
+            let tz = text.strip_prefix(b"\n").and_then(|text| text.split(|c| *c == b'\n').next()).ok_or_else(|| invalid("the footer is missing"))?;
+            if !tz.is_empty() {
+                let tz = std::str::from_utf8(tz).map_err(|_| invalid("the footer isn't valid UTF-8"))?;
+                footer = Some(PosixTz::parse(tz).ok_or_else(|| invalid("the footer isn't a valid TZ string"))?);
+            }
+        }
+
+        Ok(TimeZone { name: name.to_owned(), transitions: times.into_iter().zip(type_indices).collect(), offsets, footer })
+    }
+
+    pub fn name<'a>(self: &'a Self) -> &'a str {
+        return &self.name;
+    }
+
+    /// The offset at `time`, in seconds since 1970 UTC
+    pub fn offset_at(self: &Self, time: i64) -> UtcOffset {
+        let after = self.transitions.partition_point(|(start, _)| *start <= time);
+        if after == self.transitions.len() {
+            if let Some(footer) = &self.footer {
+                return footer.offset_at(time);
+            }
+        }
+        if after == 0 {
+            return self.offsets[0].clone();
+        }
+        return self.offsets[self.transitions[after - 1].1].clone();
+    }
+
+    /// The offsets which give the wall clock time `local`, in seconds since 1970 local time
+    pub fn local_offset(self: &Self, local: i64) -> LocalOffset {
+        // offsets don't change more than once a day, so the offsets a day either side are the only candidates
+        let before = self.offset_at(local.saturating_sub(SECONDS_PER_DAY));
+        let after = self.offset_at(local.saturating_add(SECONDS_PER_DAY));
+        if before.seconds == after.seconds {
+            return LocalOffset::Unique(self.offset_at(local.saturating_sub(i64::from(before.seconds))));
+        }
+        let gives_local = |offset: &UtcOffset| self.offset_at(local.saturating_sub(i64::from(offset.seconds))).seconds == offset.seconds;
+        match (gives_local(&before), gives_local(&after)) {
+            (true, true) => LocalOffset::Ambiguous { earlier: before, later: after },
+            (true, false) => LocalOffset::Unique(before),
+            (false, true) => LocalOffset::Unique(after),
+            (false, false) => LocalOffset::Skipped { before, after }
+        }
+    }
+}
+
+/// The version and counts of a TZif header, which are isutcnt, isstdcnt, leapcnt, timecnt, typecnt and charcnt
+fn read_tzif_header(reader: &mut &[u8]) -> Option<(u8, [usize; 6])> {
+    let header = reader.get(..44)?;
+    if &header[..4] != b"TZif" {
+        return None;
+    }
+    let mut counts = [0; 6];
+    for (i, count) in counts.iter_mut().enumerate() {
+        *count = u32::from_be_bytes(header[20 + i * 4..24 + i * 4].try_into().expect("four bytes")) as usize;
+    }
+    *reader = &reader[44..];
+    return Some((header[4], counts));
+}
+
+fn tzif_data_len(counts: &[usize; 6], time_len: usize) -> usize {
+    let [is_ut_count, is_std_count, leap_count, time_count, type_count, char_count] = *counts;
+    return time_count * (time_len + 1) + type_count * 6 + char_count + leap_count * (time_len + 4) + is_std_count + is_ut_count;
+}
+
+fn is_leap_year(year: i64) -> bool {
+    return year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31
+    }
+}
+
+/// 0 for Sunday
+fn weekday_of(days: i64) -> i64 {
+    // 1970-01-01 was a Thursday
+    return (days + 4).rem_euclid(7);
+}
+
+/// Days since 1970-01-01 of a date in the proleptic Gregorian calendar
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * i64::from((month + 9) % 12) + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    return era * 146097 + day_of_era - 719468;
+}
+
+/// The year, month and day of a number of days since 1970-01-01
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u32;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    return (year, month, day);
+}
+
+/// A time given either as an instant or as the time shown by a clock in the city's timezone
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Timestamp {
+    /// Seconds since 1970 UTC
+    Utc(i64),
+    /// Seconds since 1970 local time
+    Local(i64)
+}
+
+/// Parse a date and time such as "2024:07:01 12:00:00" (as in EXIF), "2024-07-01T12:00" or
+/// "2024-07-01T11:00:00Z", or "@1719831600" for seconds since 1970 UTC, within the years 0000 to 9999
+///
+/// Times with "Z" or an offset such as "+01:00" are instants, others are local times. Fractions of a second
+/// are ignored.
+pub fn parse_timestamp(s: &str) -> Result<Timestamp, &'static str> {
+    let s = s.trim();
+    if let Some(seconds) = s.strip_prefix('@') {
+        let seconds: i64 = seconds.parse().or(Err("seconds since 1970 not a valid integer"))?;
+        if !(MIN_TIMESTAMP..=MAX_TIMESTAMP).contains(&seconds) {
+            return Err("seconds since 1970 out of range, expected a time in the years 0000 to 9999");
+        }
+        return Ok(Timestamp::Utc(seconds));
+    }
+    let regex = Regex::new(r"^(\d{4})[-:](\d{2})[-:](\d{2})[T ](\d{2}):(\d{2})(?::(\d{2})(?:\.\d+)?)?\s*(Z|[+-]\d{2}:?\d{2})?$").expect("invalid regex pattern");
+    let captures = regex.captures(s).ok_or("expected a date and time such as \"2024-07-01 12:00:00\"")?;
+    let number = |ix: usize| captures.get(ix).map_or(0, |part| part.as_str().parse::<i64>().expect("digits"));
+    let (year, month, day, hour, minute, second) = (number(1), number(2), number(3), number(4), number(5), number(6));
+    if !(1..=12).contains(&month) || day < 1 || day > i64::from(days_in_month(year, month as u32)) {
+        return Err("date out of range");
+    }
+    if hour > 23 || minute > 59 || second > 60 {
+        return Err("time out of range");
+    }
+    let local = days_from_civil(year, month as u32, day as u32) * SECONDS_PER_DAY + hour * 3600 + minute * 60 + second;
+    return Ok(match captures.get(7).map(|offset| offset.as_str()) {
+        None => Timestamp::Local(local),
+        Some("Z") => Timestamp::Utc(local),
+        Some(offset) => {
+            let digits = offset[1..].replace(':', "");
+            let seconds = digits[..2].parse::<i64>().expect("digits") * 3600 + digits[2..].parse::<i64>().expect("digits") * 60;
+            Timestamp::Utc(if offset.starts_with('-') { local + seconds } else { local - seconds })
+        }
+    });
+}
+
+/// Seconds since 1970 as "YYYY-MM-DD HH:MM:SS"
+pub fn format_date_time(seconds: i64) -> String {
+    let (year, month, day) = civil_from_days(seconds.div_euclid(SECONDS_PER_DAY));
+    let time = seconds.rem_euclid(SECONDS_PER_DAY);
+    return format!("{:04}-{:02}-{:02} {:02}:{:02}:{:02}", year, month, day, time / 3600, time / 60 % 60, time % 60);
+}
+
+impl GeoDatabase {
+    /// The timezone of a city, read from a `zoneinfo` directory using its name in the timezone table
+    pub fn city_time_zone(self: &Self, city_ix: CityId, zoneinfo: &Path) -> Result<TimeZone, TimeZoneError> {
+        return TimeZone::load(zoneinfo, self.timezone_name(self.cities[city_ix.0].timezone_ix));
+    }
+
+    /// The UTC offset of a city at `time`, in seconds since 1970 UTC
+    pub fn city_utc_offset(self: &Self, city_ix: CityId, zoneinfo: &Path, time: i64) -> Result<UtcOffset, TimeZoneError> {
+        return Ok(self.city_time_zone(city_ix, zoneinfo)?.offset_at(time));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{days_from_civil, format_date_time, parse_timestamp, LocalOffset, PosixTz, TimeZone, Timestamp};
+
+    fn at(date_time: &str) -> i64 {
+        match parse_timestamp(date_time).unwrap() {
+            Timestamp::Utc(time) | Timestamp::Local(time) => time
+        }
+    }
+
+    /// A version 2 zone file with one transition in 2000 from LMT to the rules of `footer`
+    fn zone_file(footer: &str) -> Vec<u8> {
+        let header = |counts: [u32; 6]| {
+            let mut header = b"TZif2".to_vec();
+            header.extend([0; 15]);
+            counts.iter().for_each(|count| header.extend(count.to_be_bytes()));
+            header
+        };
+        let mut data = header([0, 0, 0, 1, 2, 8]);
+        data.extend(946684800_i32.to_be_bytes());
+        data.push(1);
+        data.extend([0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 4]);
+        data.extend(b"LMT\0GMT\0");
+        data.extend(header([0, 0, 0, 1, 2, 8]));
+        data.extend(946684800_i64.to_be_bytes());
+        data.push(1);
+        data.extend([0xFF, 0xFF, 0xFF, 0xFB, 0, 0, 0, 0, 0, 0, 0, 4]);
+        data.extend(b"LMT\0GMT\0");
+        data.extend(format!("\n{footer}\n").bytes());
+        return data;
+    }
+
+    #[test]
+    fn dates() {
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        assert_eq!(days_from_civil(2000, 3, 1), 11017);
+        assert_eq!(format_date_time(-1), "1969-12-31 23:59:59");
+        assert_eq!(format_date_time(at("2024-02-29 12:34:56")), "2024-02-29 12:34:56");
+        assert_eq!(parse_timestamp("2024:07:01 12:00:00"), Ok(Timestamp::Local(at("2024-07-01T12:00"))));
+        assert_eq!(parse_timestamp("2024-07-01T12:00:00.5+01:00"), Ok(Timestamp::Utc(at("2024-07-01 11:00:00"))));
+        assert_eq!(parse_timestamp("@1719831600"), Ok(Timestamp::Utc(at("2024-07-01 11:00"))));
+        assert!(parse_timestamp("2023-02-29 12:00").is_err());
+        assert_eq!(parse_timestamp("@-62167219200"), Ok(Timestamp::Utc(at("0000-01-01 00:00"))));
+        assert_eq!(parse_timestamp("@253402300799"), Ok(Timestamp::Utc(at("9999-12-31 23:59:59"))));
+        assert!(parse_timestamp("@253402300800").is_err());
+        assert!(parse_timestamp(&format!("@{}", i64::MAX)).is_err());
+    }
+
+    #[test]
+    fn posix_rules() {
+        let london = PosixTz::parse("GMT0BST,M3.5.0/1,M10.5.0").unwrap();
+        assert_eq!(london.offset_at(at("2024-03-31 00:59:59")).seconds, 0);
+        assert_eq!(london.offset_at(at("2024-03-31 01:00:00")).abbreviation, "BST");
+        assert_eq!(london.offset_at(at("2024-10-27 00:59:59")).seconds, 3600);
+        assert!(!london.offset_at(at("2024-10-27 01:00:00")).is_dst);
+
+        let sydney = PosixTz::parse("AEST-10AEDT,M10.1.0,M4.1.0/3").unwrap();
+        assert_eq!(sydney.offset_at(at("2024-01-15 00:00")).to_offset_string(), "+11:00");
+        assert_eq!(sydney.offset_at(at("2024-07-15 00:00")).to_offset_string(), "+10:00");
+
+        let india = PosixTz::parse("IST-5:30").unwrap();
+        assert_eq!(india.offset_at(0).to_offset_string(), "+05:30");
+        let kathmandu = PosixTz::parse("<+0545>-5:45").unwrap();
+        assert_eq!(kathmandu.offset_at(0).abbreviation, "+0545");
+        assert_eq!(PosixTz::parse("EST5EDT").unwrap().offset_at(at("2024-07-01 12:00")).to_offset_string(), "-04:00");
+        assert_eq!(PosixTz::parse("GMT0BST,M3.5.0/1"), None);
+        // far from 1970 the rules still apply, without overflowing
+        assert_eq!(london.offset_at(at("2024-07-01 12:00") + 1000 * 146097 * 86400).abbreviation, "BST");
+        assert!([0, 3600].contains(&london.offset_at(i64::MAX).seconds));
+        assert!([0, 3600].contains(&london.offset_at(i64::MIN).seconds));
+    }
+
+    #[test]
+    fn zone_files() {
+        let zone = TimeZone::parse("Test/Zone", &zone_file("GMT0BST,M3.5.0/1,M10.5.0")).unwrap();
+        assert_eq!(zone.offset_at(at("1999-07-01 12:00")).abbreviation, "LMT");
+        assert_eq!(zone.offset_at(at("2024-07-01 12:00")).to_offset_string(), "+01:00");
+        assert_eq!(zone.offset_at(at("2024-12-01 12:00")).to_offset_string(), "+00:00");
+
+        assert!(matches!(zone.local_offset(at("2024-07-01 12:00")), LocalOffset::Unique(offset) if offset.is_dst));
+        assert!(matches!(zone.local_offset(at("2024-03-31 01:30")), LocalOffset::Skipped { .. }));
+        match zone.local_offset(at("2024-10-27 01:30")) {
+            LocalOffset::Ambiguous { earlier, later } => assert_eq!((earlier.seconds, later.seconds), (3600, 0)),
+            other => panic!("expected an ambiguous time, got {other:?}")
+        }
+        assert!(matches!(zone.local_offset(i64::MIN), LocalOffset::Unique(offset) if offset.abbreviation == "LMT"));
+        assert!(!matches!(zone.local_offset(i64::MAX), LocalOffset::Skipped { .. }));
+
+        assert!(TimeZone::parse("Test/Zone", b"TZif2").is_err());
+        assert!(TimeZone::parse("Test/Zone", &zone_file("nonsense")).is_err());
+    }
+}
@@ -0,0 +1,198 @@
+//! Finding cities whose timezone looks wrong, compared with the cities around them and with the countries
+//! `zone.tab` lists for the timezone
+
+use std::collections::HashMap;
+use crate::{CityId, CountryId, GeoDatabase, RegionId, SubregionId, TimezoneId, TzData, ZoneName};
+
+/// Why a city's timezone looks wrong
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TimezoneMismatch {
+    /// `agreeing` of the `compared` nearest cities are in `majority` instead
+    Neighbours { majority: TimezoneId, agreeing: usize, compared: usize },
+    /// `agreeing` of the `compared` other cities in the same sub-region are in `majority` instead
+    Subregion { majority: TimezoneId, agreeing: usize, compared: usize },
+    /// `zone.tab` lists the timezone for other countries, with these codes
+    Country { zone_countries: Vec<String> }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TimezoneAuditFinding {
+    pub city_ix: CityId,
+    pub mismatch: TimezoneMismatch
+}
+
+/// The timezone of a strict majority of `timezones`, if it isn't `own`, with how many were in it and how
+/// many there were. At least two are needed so that a pair of cities don't each flag the other.
+fn other_majority(timezones: impl Iterator<Item = TimezoneId>, own: TimezoneId) -> Option<(TimezoneId, usize, usize)> {
+    let mut counts: HashMap<TimezoneId, usize> = HashMap::new();
+    let mut compared = 0;
+    for timezone_ix in timezones {
+        *counts.entry(timezone_ix).or_default() += 1;
+        compared += 1;
+    }
+    let (majority, agreeing) = counts.into_iter().max_by_key(|(timezone_ix, count)| (*count, std::cmp::Reverse(*timezone_ix)))?;
+    return (compared >= 2 && agreeing * 2 > compared && majority != own).then_some((majority, agreeing, compared));
+}
+
+/// A position on the unit sphere, where straight line distance increases with great circle distance
+fn unit_vector(latitude: f64, longitude: f64) -> [f64; 3] {
+    let (latitude, longitude) = (latitude.to_radians(), longitude.to_radians());
+    return [latitude.cos() * longitude.cos(), latitude.cos() * longitude.sin(), latitude.sin()];
+}
+
+fn squared_distance(a: &[f64; 3], b: &[f64; 3]) -> f64 {
+    return a.iter().zip(b).map(|(a, b)| (a - b) * (a - b)).sum();
+}
+
+/// A k-d tree of city positions, stored implicitly with each range's median as its node
+struct KdTree {
+    points: Vec<[f64; 3]>,
+    order: Vec<usize>
+}
+
+impl KdTree {
+    fn new(points: Vec<[f64; 3]>) -> KdTree {
+        let mut order: Vec<usize> = (0..points.len()).collect();
+        KdTree::build(&points, &mut order, 0);
+        return KdTree { points, order };
+    }
+
+    fn build(points: &[[f64; 3]], order: &mut [usize], depth: usize) {
+        if order.len() <= 1 {
+            return;
+        }
+        let mid = order.len() / 2;
+        let axis = depth % 3;
+        order.select_nth_unstable_by(mid, |a, b| points[*a][axis].total_cmp(&points[*b][axis]));
+        let (left, right) = order.split_at_mut(mid);
+        KdTree::build(points, left, depth + 1);
+        KdTree::build(points, &mut right[1..], depth + 1);
+    }
+
+    /// The indices of the `k` points nearest point `of`, nearest first
+    fn nearest(self: &Self, of: usize, k: usize) -> Vec<usize> {
+        let mut best = Vec::with_capacity(k + 1);
+        self.search(0, self.order.len(), 0, of, k, &mut best);
+        return best.into_iter().map(|(_, ix)| ix).collect();
+    }
+
+    fn search(self: &Self, start: usize, end: usize, depth: usize, of: usize, k: usize, best: &mut Vec<(f64, usize)>) {
+        if start >= end || k == 0 {
+            return;
+        }
+        let mid = start + (end - start) / 2;
+        let ix = self.order[mid];
+        let target = &self.points[of];
+        if ix != of {
+            let distance = squared_distance(&self.points[ix], target);
+            if best.len() < k || distance < best[best.len() - 1].0 {
+                let position = best.partition_point(|(other, _)| *other <= distance);
+                best.insert(position, (distance, ix));
+                best.truncate(k);
+            }
+        }
+        let axis = depth % 3;
+        let difference = target[axis] - self.points[ix][axis];
+        let (near, far) = if difference < 0.0 { ((start, mid), (mid + 1, end)) } else { ((mid + 1, end), (start, mid)) };
+        self.search(near.0, near.1, depth + 1, of, k, best);
+        if best.len() < k || difference * difference < best[best.len() - 1].0 {
+            self.search(far.0, far.1, depth + 1, of, k, best);
+        }
+    }
+}
+
+impl GeoDatabase {
+    /// Cities whose timezone differs from that of most of their `neighbours` nearest cities, or of most of the
+    /// other cities in their sub-region, or which `zone.tab` in `tzdata` doesn't list for their country
+    ///
+    /// Deprecated timezone names are checked as the zone they link to, and timezones `zone.tab` doesn't list,
+    /// such as "Etc/UTC", aren't checked against the country. Findings are ordered by city.
+    pub fn audit_timezones(self: &Self, neighbours: usize, tzdata: Option<&TzData>) -> Vec<TimezoneAuditFinding> {
+        let mut findings = vec![];
+
+        let tree = KdTree::new(self.cities.iter().map(|city| unit_vector(city.latitude, city.longitude)).collect());
+        let mut subregions: HashMap<(CountryId, RegionId, SubregionId), Vec<usize>> = HashMap::new();
+        for (city_ix, city) in self.cities.iter().enumerate() {
+            subregions.entry((city.country_ix, city.region_ix, city.subregion_ix)).or_default().push(city_ix);
+        }
+
+        for (city_ix, city) in self.cities.iter().enumerate() {
+            let nearest = tree.nearest(city_ix, neighbours).into_iter().map(|ix| self.cities[ix].timezone_ix);
+            if let Some((majority, agreeing, compared)) = other_majority(nearest, city.timezone_ix) {
+                findings.push(TimezoneAuditFinding { city_ix: CityId(city_ix), mismatch: TimezoneMismatch::Neighbours { majority, agreeing, compared } });
+            }
+
+            let in_subregion = subregions[&(city.country_ix, city.region_ix, city.subregion_ix)].iter()
+                .filter(|ix| **ix != city_ix)
+                .map(|ix| self.cities[*ix].timezone_ix);
+            if let Some((majority, agreeing, compared)) = other_majority(in_subregion, city.timezone_ix) {
+                findings.push(TimezoneAuditFinding { city_ix: CityId(city_ix), mismatch: TimezoneMismatch::Subregion { majority, agreeing, compared } });
+            }
+
+            let Some(tzdata) = tzdata else {
+                continue;
+            };
+            let timezone = &self.timezones[city.timezone_ix.0];
+            let zone = match tzdata.lookup(timezone) {
+                Some(ZoneName::Deprecated { replacement }) => replacement.as_str(),
+                Some(_) => timezone.as_str(),
+                None => continue
+            };
+            let zone_countries = tzdata.countries(zone);
+            let country_code = self.country_code(city.country_ix);
+            if !zone_countries.is_empty() && !zone_countries.iter().any(|code| code.eq_ignore_ascii_case(country_code)) {
+                findings.push(TimezoneAuditFinding { city_ix: CityId(city_ix), mismatch: TimezoneMismatch::Country { zone_countries: zone_countries.to_vec() } });
+            }
+        }
+        return findings;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{KdTree, TimezoneMismatch, unit_vector};
+    use crate::{CityId, GeoDatabase, NewCity, TimezoneId, TzData};
+
+    #[test]
+    fn nearest_cities() {
+        // either side of the antimeridian and near the pole
+        let positions = [(0.0, 179.9), (0.0, -179.9), (0.0, 170.0), (89.9, 0.0), (89.9, 180.0), (0.0, 0.0)];
+        let tree = KdTree::new(positions.iter().map(|(latitude, longitude)| unit_vector(*latitude, *longitude)).collect());
+        assert_eq!(tree.nearest(0, 2), vec![1, 2]);
+        assert_eq!(tree.nearest(3, 1), vec![4]);
+        assert_eq!(tree.nearest(5, 10).len(), 5);
+    }
+
+    #[test]
+    fn mismatches_are_found() {
+        let mut builder = GeoDatabase::builder();
+        let city = NewCity {
+            name: "Calais", latitude: 50.95194, longitude: 1.85635, population: 0x264,
+            country_code: "FR", country: "France", region: "Hauts-de-France", subregion: "Pas-de-Calais",
+            timezone: "Europe/Paris", feature: "PPL"
+        };
+        builder.add_city(city);
+        builder.add_city(NewCity { name: "Boulogne-sur-Mer", latitude: 50.72571, longitude: 1.61392, ..city });
+        builder.add_city(NewCity { name: "Arras", latitude: 50.29301, longitude: 2.78186, ..city });
+        builder.add_city(NewCity { name: "Béthune", latitude: 50.52965, longitude: 2.64003, ..city });
+        builder.add_city(NewCity { name: "Dunkerque", latitude: 51.03297, longitude: 2.377, subregion: "Nord", ..city });
+        builder.add_city(NewCity { name: "Lille", latitude: 50.63297, longitude: 3.05858, subregion: "Nord", ..city });
+        // in London's timezone, which is also listed for Great Britain rather than France
+        builder.add_city(NewCity { name: "Saint-Omer", latitude: 50.75, longitude: 2.25, timezone: "Europe/London", ..city });
+        builder.add_city(NewCity { name: "Dover", latitude: 51.12598, longitude: 1.31257, country_code: "GB", country: "United Kingdom",
+            region: "England", subregion: "Kent", timezone: "Europe/London", ..city });
+        let database = builder.build().unwrap();
+
+        let tzdata = TzData::parse("Z Europe/Paris\nZ Europe/London\n", "FR\t+4852+00220\tEurope/Paris\nGB\t+513030-0000731\tEurope/London\n");
+        let findings = database.audit_timezones(5, Some(&tzdata));
+        let found: Vec<(CityId, &TimezoneMismatch)> = findings.iter().map(|finding| (finding.city_ix, &finding.mismatch)).collect();
+        assert_eq!(found, vec![
+            (CityId(6), &TimezoneMismatch::Neighbours { majority: TimezoneId(0), agreeing: 5, compared: 5 }),
+            (CityId(6), &TimezoneMismatch::Subregion { majority: TimezoneId(0), agreeing: 4, compared: 4 }),
+            (CityId(6), &TimezoneMismatch::Country { zone_countries: vec!["GB".to_owned()] }),
+            // across the channel the nearest cities are in France
+            (CityId(7), &TimezoneMismatch::Neighbours { majority: TimezoneId(0), agreeing: 4, compared: 5 })
+        ]);
+        assert_eq!(database.audit_timezones(5, None).len(), 3);
+    }
+}
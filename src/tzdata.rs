@@ -0,0 +1,306 @@
+//! Checking timezone names against the IANA time zone database
+//!
+//! The names come from a `zoneinfo` directory or, with the `tzdata` feature, an embedded copy of the zone
+//! and link names. A link is deprecated unless `zone.tab` lists it for a country, e.g. "Asia/Calcutta" is
+//! a link kept for backward compatibility with "Asia/Kolkata" but "Europe/Amsterdam" is still the name used
+//! for the Netherlands.
+
+use std::{collections::BTreeMap, fmt, fs, io, path::{Path, PathBuf}};
+use crate::{similarity, GeoDatabase, TimezoneId};
+
+/// The least similarity for a name to be suggested as a replacement for an unknown timezone
+const MIN_SUGGESTION_SIMILARITY: f64 = 0.75;
+
+/// What a name is in the time zone database
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ZoneName {
+    Zone,
+    /// A link which is still in use, it has the same rules as `target`
+    Link { target: String },
+    /// A link kept for backward compatibility
+    Deprecated { replacement: String }
+}
+
+#[derive(Debug)]
+pub enum TzDataError {
+    Io { path: PathBuf, err: io::Error },
+    /// Neither `tzdata.zi` nor any compiled zone files were found in the directory
+    NoZones { path: PathBuf }
+}
+
+impl fmt::Display for TzDataError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TzDataError::Io { path, err } => write!(f, "failed to read {}: {err}", path.display()),
+            TzDataError::NoZones { path } => write!(f, "{} doesn't contain any time zones", path.display())
+        }
+    }
+}
+
+impl std::error::Error for TzDataError {}
+
+/// The zone and link names of a version of the IANA time zone database, with the countries `zone.tab` lists
+/// for each zone
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TzData {
+    version: Option<String>,
+    names: BTreeMap<String, ZoneName>,
+    countries: BTreeMap<String, Vec<String>>
+}
+
+#[cfg(feature = "tzdata")]
+static EMBEDDED: std::sync::LazyLock<TzData> = std::sync::LazyLock::new(|| {
+    TzData::parse(include_str!("../data/tzdata.zi"), include_str!("../data/zone.tab"))
+});
+
+impl TzData {
+    /// The names embedded in the library
+    #[cfg(feature = "tzdata")]
+    pub fn embedded() -> &'static TzData {
+        return &EMBEDDED;
+    }
+
+    /// Read the names from a `zoneinfo` directory, e.g. "/usr/share/zoneinfo"
+    ///
+    /// The names are read from `tzdata.zi` if there is one, otherwise every compiled zone file is a zone
+    /// except for symbolic links, which are links. Hard links to the same file can't be told apart so are
+    /// all treated as zones.
+    pub fn load(dir: &Path) -> Result<TzData, TzDataError> {
+        let read = |name: &str| {
+            let path = dir.join(name);
+            match fs::read_to_string(&path) {
+                Ok(text) => Ok(Some(text)),
+                Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+                Err(err) => Err(TzDataError::Io { path, err })
+            }
+        };
+        let zone_tab = read("zone.tab")?.unwrap_or_default();
+        if let Some(tzdata_zi) = read("tzdata.zi")? {
+            return Ok(TzData::parse(&tzdata_zi, &zone_tab));
+        }
+
+        let mut tzdata_zi = String::new();
+        list_zone_files(dir, dir, &mut tzdata_zi)?;
+        if tzdata_zi.is_empty() {
+            return Err(TzDataError::NoZones { path: dir.to_owned() });
+        }
+        if let Some(version) = read("+VERSION")? {
+            tzdata_zi.insert_str(0, &format!("# version {}\n", version.trim()));
+        }
+        return Ok(TzData::parse(&tzdata_zi, &zone_tab));
+    }
+
+    /// Parse the `Z` (zone) and `L` (link) lines of a `tzdata.zi` file, and a `zone.tab` file
+    pub(crate) fn parse(tzdata_zi: &str, zone_tab: &str) -> TzData {
+        let mut countries: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for line in zone_tab.lines().filter(|line| !line.starts_with('#')) {
+            let fields: Vec<&str> = line.split('\t').collect();
+            if fields.len() >= 3 {
+                countries.entry(fields[2].to_owned()).or_default().extend(fields[0].split(',').map(str::to_owned));
+            }
+        }
+
+        let mut version = None;
+        let mut names = BTreeMap::new();
+        let mut links = vec![];
+        for line in tzdata_zi.lines() {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            match fields.as_slice() {
+                ["#", "version", number, ..] => version = Some(number.to_string()),
+                ["Z", name, ..] => {
+                    names.insert(name.to_string(), ZoneName::Zone);
+                },
+                ["L", target, name, ..] => links.push((target.to_string(), name.to_string())),
+                _ => {}
+            }
+        }
+        for (target, name) in links {
+            let kind = if countries.contains_key(&name) { ZoneName::Link { target } } else { ZoneName::Deprecated { replacement: target } };
+            names.insert(name, kind);
+        }
+        return TzData { version, names, countries };
+    }
+
+    /// The release, e.g. "2026a", if it's known
+    pub fn version<'a>(self: &'a Self) -> Option<&'a str> {
+        return self.version.as_deref();
+    }
+
+    /// What `name` is, or `None` if it's neither a zone nor a link
+    pub fn lookup<'a>(self: &'a Self, name: &str) -> Option<&'a ZoneName> {
+        return self.names.get(name);
+    }
+
+    /// Every zone and link name, in order
+    pub fn names<'a>(self: &'a Self) -> impl Iterator<Item = &'a str> + 'a {
+        self.names.keys().map(String::as_str)
+    }
+
+    /// The codes of the countries `zone.tab` lists for a zone or link
+    pub fn countries<'a>(self: &'a Self, name: &str) -> &'a [String] {
+        return self.countries.get(name).map_or(&[], Vec::as_slice);
+    }
+
+    /// What's wrong with a timezone name, or `None` if it's a zone or a link in use
+    pub fn check(self: &Self, name: &str) -> Option<TimezoneProblem> {
+        match self.names.get(name) {
+            Some(ZoneName::Zone | ZoneName::Link { .. }) => None,
+            Some(ZoneName::Deprecated { replacement }) => Some(TimezoneProblem::Deprecated { replacement: replacement.clone() }),
+            None => Some(TimezoneProblem::Unknown { suggestion: self.suggest(name) })
+        }
+    }
+
+    /// The name most likely meant by an unknown name, e.g. with the wrong case, spaces instead of
+    /// underscores or a typo, replaced if it's deprecated
+    fn suggest(self: &Self, name: &str) -> Option<String> {
+        let normalised = name.trim().replace(' ', "_");
+        let found = self.names.keys()
+            .find(|candidate| candidate.eq_ignore_ascii_case(&normalised))
+            .or_else(|| {
+                self.names.keys()
+                    .map(|candidate| (candidate, similarity(&normalised, candidate)))
+                    .filter(|(_, score)| *score >= MIN_SUGGESTION_SIMILARITY)
+                    .max_by(|(_, a), (_, b)| a.total_cmp(b))
+                    .map(|(candidate, _)| candidate)
+            })?;
+        return match &self.names[found] {
+            ZoneName::Deprecated { replacement } => Some(replacement.clone()),
+            _ => Some(found.clone())
+        };
+    }
+}
+
+/// Add a `Z` or `L` line for each compiled zone file below `dir`, skipping the `posix` and `right` copies
+fn list_zone_files(root: &Path, dir: &Path, out: &mut String) -> Result<(), TzDataError> {
+    fn io_error(path: &Path) -> impl FnOnce(io::Error) -> TzDataError + '_ {
+        move |err| TzDataError::Io { path: path.to_owned(), err }
+    }
+    let mut entries: Vec<_> = fs::read_dir(dir).map_err(io_error(dir))?
+        .collect::<Result<_, _>>().map_err(io_error(dir))?;
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let path = entry.path();
+        let name = path.strip_prefix(root).expect("entries are below the root").to_string_lossy().replace('\\', "/");
+        if name == "posix" || name == "right" || name == "localtime" || name == "posixrules" {
+            continue;
+        }
+        if path.is_dir() {
+            list_zone_files(root, &path, out)?;
+            continue;
+        }
+        let contents = fs::read(&path).map_err(io_error(&path))?;
+        if !contents.starts_with(b"TZif") {
+            continue;
+        }
+        let is_symlink = entry.file_type().map_err(io_error(&path))?.is_symlink();
+        let target = is_symlink.then(|| fs::canonicalize(&path).ok()).flatten()
+            .and_then(|target| Some(target.strip_prefix(fs::canonicalize(root).ok()?).ok()?.to_string_lossy().replace('\\', "/")));
+        match target {
+            Some(target) => out.push_str(&format!("L {target} {name}\n")),
+            None => out.push_str(&format!("Z {name}\n"))
+        }
+    }
+    return Ok(());
+}
+
+/// Why a name in the timezone table isn't valid
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TimezoneProblem {
+    /// Not in the time zone database, with the name most likely meant if there is one
+    Unknown { suggestion: Option<String> },
+    /// A link kept for backward compatibility, `replacement` is the canonical name
+    Deprecated { replacement: String }
+}
+
+impl fmt::Display for TimezoneProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TimezoneProblem::Unknown { suggestion: Some(suggestion) } => write!(f, "unknown timezone, did you mean {suggestion}?"),
+            TimezoneProblem::Unknown { suggestion: None } => write!(f, "unknown timezone"),
+            TimezoneProblem::Deprecated { replacement } => write!(f, "deprecated, use {replacement}")
+        }
+    }
+}
+
+/// An entry of the timezone table which isn't a valid IANA time zone name
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TimezoneIssue {
+    pub timezone_ix: TimezoneId,
+    pub name: String,
+    pub problem: TimezoneProblem,
+    /// The number of cities in the timezone
+    pub n_cities: usize
+}
+
+impl GeoDatabase {
+    /// Check every entry of the timezone table against the time zone database, returning those which are
+    /// unknown or deprecated
+    pub fn validate_timezones(self: &Self, tzdata: &TzData) -> Vec<TimezoneIssue> {
+        let mut n_cities = vec![0; self.timezones.len()];
+        for city in &self.cities {
+            n_cities[city.timezone_ix.0] += 1;
+        }
+        return (0..self.timezones.len())
+            .filter_map(|timezone_ix| {
+                let name = &self.timezones[timezone_ix];
+                let problem = tzdata.check(name)?;
+                Some(TimezoneIssue { timezone_ix: TimezoneId(timezone_ix), name: name.clone(), problem, n_cities: n_cities[timezone_ix] })
+            })
+            .collect();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TimezoneProblem, TzData, ZoneName};
+    use crate::{GeoDatabase, NewCity, TimezoneId};
+
+    const TZDATA_ZI: &str = "# version 2026a\nZ Asia/Kolkata 5:53:28 - LMT 1854 Jun 28\nZ Europe/Brussels 0:17:30 - LMT 1880\nZ Europe/London -0:1:15 - LMT 1847 D\n\
+        L Asia/Kolkata Asia/Calcutta\nL Europe/Brussels Europe/Amsterdam\nL Europe/London GB\n";
+    const ZONE_TAB: &str = "# comment\nIN\t+2232+08822\tAsia/Kolkata\nBE\t+5050+00420\tEurope/Brussels\nNL\t+5222+00454\tEurope/Amsterdam\nGB,GG,IM,JE\t+513030-0000731\tEurope/London\n";
+
+    #[test]
+    fn names_are_classified() {
+        let tzdata = TzData::parse(TZDATA_ZI, ZONE_TAB);
+        assert_eq!(tzdata.version(), Some("2026a"));
+        assert_eq!(tzdata.lookup("Asia/Kolkata"), Some(&ZoneName::Zone));
+        assert_eq!(tzdata.lookup("Europe/Amsterdam"), Some(&ZoneName::Link { target: "Europe/Brussels".to_owned() }));
+        assert_eq!(tzdata.lookup("GB"), Some(&ZoneName::Deprecated { replacement: "Europe/London".to_owned() }));
+        assert_eq!(tzdata.countries("Europe/London"), ["GB", "GG", "IM", "JE"]);
+
+        assert_eq!(tzdata.check("Europe/Amsterdam"), None);
+        assert_eq!(tzdata.check("Asia/Calcutta"), Some(TimezoneProblem::Deprecated { replacement: "Asia/Kolkata".to_owned() }));
+        assert_eq!(tzdata.check("europe/london"), Some(TimezoneProblem::Unknown { suggestion: Some("Europe/London".to_owned()) }));
+        assert_eq!(tzdata.check("Europe/Lundon"), Some(TimezoneProblem::Unknown { suggestion: Some("Europe/London".to_owned()) }));
+        assert_eq!(tzdata.check("Asia/Calcuta"), Some(TimezoneProblem::Unknown { suggestion: Some("Asia/Kolkata".to_owned()) }));
+        assert_eq!(tzdata.check("Mars/Olympus_Mons"), Some(TimezoneProblem::Unknown { suggestion: None }));
+    }
+
+    #[test]
+    fn timezone_table_is_validated() {
+        let mut builder = GeoDatabase::builder();
+        let city = NewCity {
+            name: "Kolkata", latitude: 22.56263, longitude: 88.36304, population: 0x264,
+            country_code: "IN", country: "India", region: "West Bengal", subregion: "Kolkata",
+            timezone: "Asia/Calcutta", feature: "PPLA"
+        };
+        builder.add_city(city);
+        builder.add_city(NewCity { name: "Howrah", timezone: "Asia/Kolkata", ..city });
+        builder.add_city(NewCity { name: "Darjeeling", timezone: "Asia/Kolkatta", ..city });
+        let database = builder.build().unwrap();
+
+        let issues = database.validate_timezones(&TzData::parse(TZDATA_ZI, ZONE_TAB));
+        assert_eq!(issues.iter().map(|issue| issue.timezone_ix).collect::<Vec<_>>(), vec![TimezoneId(0), TimezoneId(2)]);
+        assert_eq!(issues[1].problem, TimezoneProblem::Unknown { suggestion: Some("Asia/Kolkata".to_owned()) });
+        assert_eq!(issues[1].n_cities, 1);
+    }
+
+    #[cfg(feature = "tzdata")]
+    #[test]
+    fn embedded_names() {
+        let tzdata = TzData::embedded();
+        assert!(tzdata.version().is_some());
+        assert_eq!(tzdata.check("America/New_York"), None);
+        assert_eq!(tzdata.check("Europe/Kiev"), Some(TimezoneProblem::Deprecated { replacement: "Europe/Kyiv".to_owned() }));
+    }
+}
@@ -0,0 +1,122 @@
+//! Checking that reading a database and writing it back reproduces the file byte for byte
+
+use std::io::{BufRead, Read};
+use crate::{compression, reader::{self, CONTEXT_LEN}, Compression, DatabaseReadError, GeoDatabase};
+
+/// Where a rewritten database first differs from the file it was read from, offsets are into the
+/// uncompressed data
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RoundTripDifference {
+    pub offset: u64,
+    pub original_len: u64,
+    pub rewritten_len: u64,
+    /// Bytes of each surrounding `offset`, starting at `context_offset`
+    pub original_context: Vec<u8>,
+    pub rewritten_context: Vec<u8>,
+    pub context_offset: u64
+}
+
+impl RoundTripDifference {
+    fn new(original: &[u8], rewritten: &[u8], offset: usize) -> RoundTripDifference {
+        let start = offset.saturating_sub(CONTEXT_LEN);
+        let context = |data: &[u8]| data[start.min(data.len())..(offset + CONTEXT_LEN).min(data.len())].to_vec();
+        RoundTripDifference {
+            offset: offset as u64,
+            original_len: original.len() as u64,
+            rewritten_len: rewritten.len() as u64,
+            original_context: context(original),
+            rewritten_context: context(rewritten),
+            context_offset: start as u64
+        }
+    }
+
+    /// The original bytes around the difference as hex and ASCII, with the first differing byte marked
+    pub fn original_hex_dump(self: &Self) -> String {
+        return reader::hex_dump(&self.original_context, self.context_offset, self.offset);
+    }
+
+    /// The rewritten bytes around the difference as hex and ASCII, with the first differing byte marked
+    pub fn rewritten_hex_dump(self: &Self) -> String {
+        return reader::hex_dump(&self.rewritten_context, self.context_offset, self.offset);
+    }
+}
+
+/// Read a database, which may be compressed, and write it back in the version given by its header,
+/// returning where the result first differs from the uncompressed original, or `None` if they're identical
+pub fn verify_round_trip(reader: &mut dyn BufRead) -> Result<Option<RoundTripDifference>, DatabaseReadError> {
+    let compression = Compression::detect(reader.fill_buf()?);
+    let mut original = vec![];
+    compression::decoder(reader, compression)?.read_to_end(&mut original)?;
+
+    let database = reader::read_database(&mut &original[..])?;
+    let header_len = original.iter().position(|byte| *byte == b'\n').unwrap_or(original.len());
+    let header = String::from_utf8_lossy(&original[..header_len]);
+    let (format, _) = reader::check_header(header.trim_end())?;
+
+    let mut rewritten = vec![];
+    database.write_version(&mut rewritten, format)?;
+    let differs_at = original.iter().zip(&rewritten).position(|(original, rewritten)| original != rewritten)
+        .or_else(|| (original.len() != rewritten.len()).then(|| original.len().min(rewritten.len())));
+    return Ok(differs_at.map(|offset| RoundTripDifference::new(&original, &rewritten, offset)));
+}
+
+impl GeoDatabase {
+    /// See `verify_round_trip`
+    pub fn verify_round_trip(reader: &mut dyn BufRead) -> Result<Option<RoundTripDifference>, DatabaseReadError> {
+        return verify_round_trip(reader);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::verify_round_trip;
+    use crate::{CityId, GeoDatabase, GeoDatabaseRef, NewCity};
+
+    fn sample() -> Vec<u8> {
+        let mut builder = GeoDatabase::builder();
+        builder.add_city(NewCity {
+            name: "Ushuaia", latitude: -54.81084, longitude: -68.31591, population: 0x175,
+            country_code: "AR", country: "Argentina", region: "Tierra del Fuego", subregion: "Departamento de Ushuaia",
+            timezone: "America/Argentina/Ushuaia", feature: "PPLA"
+        });
+        let database = builder.build().unwrap();
+        let mut bytes = vec![];
+        database.write_to(&mut bytes).unwrap();
+        return bytes;
+    }
+
+    #[test]
+    fn reserved_bits_and_extra_sections_survive() {
+        let mut bytes = sample();
+        let header_and_comment = bytes.iter().enumerate().filter(|(_, byte)| **byte == b'\n').nth(1).unwrap().0 + 1;
+        bytes[header_and_comment + 12] |= 0x40;
+        bytes.extend_from_slice(b"future table\n\0\0\0\0\x06\n");
+        assert_eq!(verify_round_trip(&mut &bytes[..]).unwrap(), None);
+    }
+
+    #[test]
+    fn line_endings_and_trailing_spaces_survive() {
+        let bytes = sample();
+        let header_end = bytes.iter().position(|byte| *byte == b'\n').unwrap() + 1;
+        let comment_end = header_end + bytes[header_end..].iter().position(|byte| *byte == b'\n').unwrap() + 1;
+        let name_end = bytes.windows(8).position(|window| window == b"Ushuaia\n").unwrap() + 7;
+        let altered = [&bytes[..header_end], b"c \r\n", &bytes[comment_end..name_end], b" ", &bytes[name_end..]].concat();
+        assert_eq!(verify_round_trip(&mut &altered[..]).unwrap(), None);
+
+        let database = GeoDatabase::read_from(&mut &altered[..]).unwrap();
+        assert_eq!((database.comment(), database.city(CityId(0)).name()), ("c \r", "Ushuaia "));
+        let borrowed = GeoDatabaseRef::from_bytes(&altered).unwrap();
+        assert_eq!((borrowed.comment(), borrowed.city_name(CityId(0))), ("c \r", "Ushuaia "));
+    }
+
+    #[test]
+    fn reports_first_difference() {
+        // extra spaces in the header are accepted when reading but not written back
+        let bytes = sample();
+        let altered = [b"Geolocation1.03  ".as_slice(), &bytes[16..]].concat();
+        let difference = verify_round_trip(&mut &altered[..]).unwrap().expect("files differ");
+        assert_eq!(difference.offset, 16);
+        assert_eq!((difference.original_len, difference.rewritten_len), (bytes.len() as u64 + 1, bytes.len() as u64));
+        assert!(difference.original_hex_dump().contains("^^"));
+    }
+}
@@ -0,0 +1,156 @@
+//! Read-only access to the contents of a `GeoDatabase`
+
+use crate::{CityEntry, CityId, CityInfo, CountryId, FeatureId, GeoDatabase, RegionId, SubregionId, TimezoneId};
+
+/// A city borrowed from a `GeoDatabase` with its table references resolved to names
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct CityView<'a> {
+    pub id: CityId,
+    pub entry: &'a CityEntry,
+    pub subregion: &'a str,
+    pub region: &'a str,
+    pub region_code: Option<&'a str>,
+    pub country: &'a str,
+    pub country_code: &'a str,
+    pub timezone: &'a str,
+    pub feature: &'a str
+}
+
+impl<'a> CityView<'a> {
+    pub fn name(self: &Self) -> &'a str {
+        return &self.entry.name;
+    }
+
+    /// An owned copy, e.g. to keep after the database has been modified
+    pub fn to_info(self: &Self) -> CityInfo {
+        CityInfo {
+            city_ix: self.id,
+            name: self.entry.name.clone(),
+            latitude: self.entry.latitude,
+            longitude: self.entry.longitude,
+            population: self.entry.population,
+            subregion: self.subregion.to_owned(),
+            region: self.region.to_owned(),
+            region_code: self.region_code.map(str::to_owned),
+            country: self.country.to_owned(),
+            country_code: self.country_code.to_owned(),
+            timezone: self.timezone.to_owned(),
+            feature: self.feature.to_owned()
+        }
+    }
+}
+
+/// An entry of the country table, which stores the two letter code and the name together
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct CountryView<'a> {
+    pub id: CountryId,
+    pub code: &'a str,
+    pub name: &'a str
+}
+
+impl GeoDatabase {
+    pub fn comment<'a>(self: &'a Self) -> &'a str {
+        return &self.comment;
+    }
+
+    pub fn set_comment(self: &mut Self, comment: impl Into<String>) {
+        self.comment = comment.into();
+    }
+
+    pub fn city_count(self: &Self) -> usize {
+        return self.cities.len();
+    }
+
+    pub fn country_count(self: &Self) -> usize {
+        return self.countries.len();
+    }
+
+    pub fn region_count(self: &Self) -> usize {
+        return self.regions.len();
+    }
+
+    pub fn subregion_count(self: &Self) -> usize {
+        return self.subregions.len();
+    }
+
+    pub fn timezone_count(self: &Self) -> usize {
+        return self.timezones.len();
+    }
+
+    pub fn feature_count(self: &Self) -> usize {
+        return self.features.len();
+    }
+
+    pub fn city<'a>(self: &'a Self, city_ix: CityId) -> CityView<'a> {
+        let entry = &self.cities[city_ix.0];
+        CityView {
+            id: city_ix,
+            entry,
+            subregion: self.subregion_name(entry.subregion_ix),
+            region: self.region_name(entry.region_ix),
+            region_code: self.region_code(entry.region_ix, entry.country_ix),
+            country: self.country_name(entry.country_ix),
+            country_code: self.country_code(entry.country_ix),
+            timezone: self.timezone_name(entry.timezone_ix),
+            feature: self.feature_name(entry.feature_ix)
+        }
+    }
+
+    pub fn cities<'a>(self: &'a Self) -> impl Iterator<Item = CityView<'a>> + 'a {
+        (0..self.cities.len()).map(|city_ix| self.city(CityId(city_ix)))
+    }
+
+    fn cities_where<'a, F>(self: &'a Self, predicate: F) -> impl Iterator<Item = CityView<'a>> + 'a
+    where
+        F: Fn(&CityEntry) -> bool + 'a
+    {
+        self.cities.iter().enumerate()
+            .filter(move |(_, city)| predicate(city))
+            .map(|(city_ix, _)| self.city(CityId(city_ix)))
+    }
+
+    pub fn cities_in_country<'a>(self: &'a Self, country_ix: CountryId) -> impl Iterator<Item = CityView<'a>> + 'a {
+        self.cities_where(move |city| city.country_ix == country_ix)
+    }
+
+    pub fn cities_in_region<'a>(self: &'a Self, region_ix: RegionId) -> impl Iterator<Item = CityView<'a>> + 'a {
+        self.cities_where(move |city| city.region_ix == region_ix)
+    }
+
+    pub fn cities_in_subregion<'a>(self: &'a Self, subregion_ix: SubregionId) -> impl Iterator<Item = CityView<'a>> + 'a {
+        self.cities_where(move |city| city.subregion_ix == subregion_ix)
+    }
+
+    pub fn cities_in_timezone<'a>(self: &'a Self, timezone_ix: TimezoneId) -> impl Iterator<Item = CityView<'a>> + 'a {
+        self.cities_where(move |city| city.timezone_ix == timezone_ix)
+    }
+
+    pub fn cities_with_feature<'a>(self: &'a Self, feature_ix: FeatureId) -> impl Iterator<Item = CityView<'a>> + 'a {
+        self.cities_where(move |city| city.feature_ix == feature_ix)
+    }
+
+    pub fn countries<'a>(self: &'a Self) -> impl Iterator<Item = CountryView<'a>> + 'a {
+        (0..self.countries.len()).map(|country_ix| {
+            let id = CountryId(country_ix);
+            CountryView { id, code: self.country_code(id), name: self.country_name(id) }
+        })
+    }
+
+    pub fn regions<'a>(self: &'a Self) -> impl Iterator<Item = (RegionId, &'a str)> + 'a {
+        self.regions.iter().enumerate().map(|(region_ix, name)| (RegionId(region_ix), name.as_str()))
+    }
+
+    pub fn subregions<'a>(self: &'a Self) -> impl Iterator<Item = (SubregionId, &'a str)> + 'a {
+        self.subregions.iter().enumerate().map(|(subregion_ix, name)| (SubregionId(subregion_ix), name.as_str()))
+    }
+
+    pub fn timezones<'a>(self: &'a Self) -> impl Iterator<Item = (TimezoneId, &'a str)> + 'a {
+        self.timezones.iter().enumerate().map(|(timezone_ix, name)| (TimezoneId(timezone_ix), name.as_str()))
+    }
+
+    pub fn features<'a>(self: &'a Self) -> impl Iterator<Item = (FeatureId, &'a str)> + 'a {
+        self.features.iter().enumerate().map(|(feature_ix, name)| (FeatureId(feature_ix), name.as_str()))
+    }
+}